@@ -0,0 +1,11 @@
+/* log::deferred の書式文字列を置くセクション */
+/* INFO にするとターゲットには書き込まれず、ELF にだけ残る（ホストの binlog-decode が読む） */
+/* セクション内のアドレスがそのまま書式文字列の番号になる（先頭に 1byte 置いて、0 番は使わない） */
+SECTIONS
+{
+    .binlog 0 (INFO) :
+    {
+        BYTE(0)
+        KEEP(*(.binlog .binlog.*));
+    }
+}
@@ -21,6 +21,11 @@ fn main() {
         .unwrap()
         .write_all(include_bytes!("memory.x"))
         .unwrap();
+    // log::deferred の書式文字列のセクション
+    File::create(out.join("binlog.x"))
+        .unwrap()
+        .write_all(include_bytes!("binlog.x"))
+        .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
     // By default, Cargo will re-run a build script whenever
@@ -28,4 +33,5 @@ fn main() {
     // here, we ensure the build script is only re-run when
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
+    println!("cargo:rerun-if-changed=binlog.x");
 }
@@ -4,6 +4,7 @@
 // 実用的に使う時は、誤差修正とサンプリング時間計算が必要
 // 誤差修正 https://qiita.com/kotetsu_yama/items/d31da1e7ef6a4d21b097
 // サンプリング時間修正 https://rt-net.jp/mobility/archives/19153
+// サンプリング時間は信号源のインピーダンスから adc::SampleTime::for_source で計算している。
 
 #![no_std]
 #![no_main]
@@ -39,6 +40,11 @@ use stm32f4::stm32f446::interrupt;
 
 use core::cell::RefCell;
 
+use stm32f446re_rust_example::adc::{self, Resolution, SampleTime};
+
+// PA4 につないだ信号源の出力インピーダンス[Ω]（ボリュームで分圧するなら、その最大値程度）
+const SOURCE_OHMS: u32 = 10_000;
+
 // グローバル変数(メインと割り込み関数の両方でペリフェラルアクセスするため)
 static PERIPHERAL: Mutex<RefCell<Option<stm32f446::Peripherals>>> = Mutex::new(RefCell::new(None));
 
@@ -115,7 +121,10 @@ fn config_adc(peripheral: &stm32f4::stm32f446::Peripherals) {
         .ADC1
         .cr2
         .modify(|_, w| w.adon().enabled().eocs().each_conversion());
-    peripheral.ADC1.smpr2.modify(|_, w| w.smp4().cycles56()); // 実験なので適当に長く設定
+    // ADC クロック 22.5MHz、12bit で必要なサンプリング時間（10kΩ なら 15 サイクル）
+    let sample_time = SampleTime::for_source(SOURCE_OHMS, 90_000_000 / 4, Resolution::Bits12)
+        .expect("source impedance too high");
+    adc::set_sample_time(&peripheral.ADC1, 4, sample_time);
 
     peripheral.ADC1.sqr1.modify(|_, w| w.l().bits(1)); // 1 channel だけなので1
     peripheral
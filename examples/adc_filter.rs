@@ -0,0 +1,158 @@
+// ADC のサンプルをディジタルフィルタに通す
+// ADC1 で PA0(ch0), PA4(ch4) をスキャン変換して DMA で取り込み、DMA2_STREAM0 割り込みでフィルタを通す。
+//   ch0: メディアン(スパイク除去) -> 移動平均
+//   ch4: 16倍オーバーサンプリング(14bit) -> 指数移動平均
+// スイッチ入力ごとに、フィルタ前(直近の生の値)とフィルタ後の値を semihosting で出力。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::scan::{ScanConfig, ScanDma};
+use stm32f446re_rust_example::adc::{
+    self, Conversion, Instance, Prescaler, Resolution, SampleTime,
+};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+use stm32f446re_rust_example::filter::{Chain, Ema, Filter, Median, MovingAverage, Oversample};
+
+const CHANNELS: usize = 2;
+const BUFFER_LEN: usize = CHANNELS * 64 * 2;
+
+struct Filters {
+    ch0: Chain<Median<5>, MovingAverage<16>>,
+    ch4: Chain<Oversample, Ema>,
+}
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static SCAN: Mutex<RefCell<Option<ScanDma<stm32f446::ADC1, 0>>>> = Mutex::new(RefCell::new(None));
+static FILTERS: Mutex<RefCell<Option<Filters>>> = Mutex::new(RefCell::new(None));
+// (ch0 生, ch0 フィルタ後, ch4 生, ch4 フィルタ後(14bit))
+static VALUES: Mutex<Cell<(u16, u16, u16, u16)>> = Mutex::new(Cell::new((0, 0, 0, 0)));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0, PA4
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // アナログ設定
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder0().analog().moder4().analog());
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    let filters = Filters {
+        ch0: Median::new().then(MovingAverage::new()),
+        ch4: Oversample::new(2).then(Ema::new(3)),
+    };
+    cortex_m::interrupt::free(|cs| FILTERS.borrow(cs).replace(Some(filters)));
+
+    let buffer = cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    let config = ScanConfig {
+        channels: &[(0, SampleTime::Cycles144), (4, SampleTime::Cycles144)],
+        resolution: Resolution::Bits12,
+        conversion: Conversion::Continuous,
+    };
+    // DMA2 stream0 の ADC1 要求で転送する（DMA2 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let mut scan = ScanDma::new(
+        peripheral.ADC1,
+        (streams.s0, request::Adc1),
+        &config,
+        buffer,
+    )
+    .unwrap();
+    scan.start();
+
+    cortex_m::interrupt::free(|cs| SCAN.borrow(cs).replace(Some(scan)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+    }
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let (raw0, filtered0, raw4, filtered4) =
+            cortex_m::interrupt::free(|cs| VALUES.borrow(cs).get());
+        hprintln!(
+            "ch0: {} -> {}, ch4: {} -> {} (14bit)",
+            raw0,
+            filtered0,
+            raw4,
+            filtered4
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut scan = SCAN.borrow(cs).borrow_mut();
+        let mut filters = FILTERS.borrow(cs).borrow_mut();
+        if let (Some(scan), Some(filters)) = (scan.as_mut(), filters.as_mut()) {
+            let result = scan.on_interrupt(|_, block| {
+                let (mut raw0, mut filtered0, mut raw4, mut filtered4) = VALUES.borrow(cs).get();
+                // ブロックは ch0, ch4, ch0, ch4, ... の順に並んでいる
+                filters
+                    .ch0
+                    .feed(block, CHANNELS, 0, |value| filtered0 = value);
+                filters
+                    .ch4
+                    .feed(block, CHANNELS, 1, |value| filtered4 = value);
+                if let [.., last0, last4] = *block {
+                    raw0 = last0;
+                    raw4 = last4;
+                }
+                VALUES.borrow(cs).set((raw0, filtered0, raw4, filtered4));
+            });
+            if result.is_err() {
+                scan.restart();
+                filters.ch0.reset();
+                filters.ch4.reset();
+            }
+        } else {
+            panic!("not found scan");
+        }
+    });
+}
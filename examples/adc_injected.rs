@@ -0,0 +1,189 @@
+// ADC1 のインジェクテッド変換をレギュラーの DMA スキャンと並行して動かす
+// レギュラー側は PA4(ch4) を連続変換して DMA で循環バッファへ取り込み、
+// インジェクテッド側は TIM1 CH4 の比較一致(20kHz)ごとに PA0(ch0), PA1(ch1) を割り込んで変換する。
+// インジェクテッド側は 2048 のオフセットを引いているので、中点を 0 とした符号付きの値になる。
+// スイッチ入力ごとに直近の結果を semihosting で出力。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::injected::{Injected, InjectedChannel, InjectedTrigger};
+use stm32f446re_rust_example::adc::scan::{ScanConfig, ScanDma};
+use stm32f446re_rust_example::adc::{
+    self, Conversion, Instance, Prescaler, Resolution, SampleTime, TriggerEdge,
+};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+
+const BUFFER_LEN: usize = 64;
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static SCAN: Mutex<RefCell<Option<ScanDma<stm32f446::ADC1, 0>>>> = Mutex::new(RefCell::new(None));
+static INJECTED: Mutex<RefCell<Option<Injected<stm32f446::ADC1>>>> = Mutex::new(RefCell::new(None));
+static REGULAR: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+// (ch0, ch1, 変換回数)
+static LATEST: Mutex<Cell<(i16, i16, u32)>> = Mutex::new(Cell::new((0, 0, 0)));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0, PA1, PA4
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    peripheral.RCC.apb2enr.modify(|_, w| w.tim1en().enabled()); // TIM1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // アナログ設定
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder0().analog().moder1().analog().moder4().analog());
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    // TIM1 設定（クロックはAPB2 * 2 = 180MHz）
+    // 180MHz / 9000 = 20kHz、CH4 は周期の中央で比較一致（出力ピンは使わない）
+    let tim1 = &peripheral.TIM1;
+    tim1.psc.write(|w| unsafe { w.bits(0) });
+    tim1.arr.write(|w| unsafe { w.bits(9000 - 1) });
+    tim1.ccr4.write(|w| unsafe { w.bits(4500) });
+    tim1.ccmr2_output().modify(|_, w| w.oc4m().pwm_mode1());
+    tim1.ccer.modify(|_, w| w.cc4e().set_bit());
+    tim1.bdtr.modify(|_, w| w.moe().set_bit()); // 高機能タイマは MOE が無いと比較イベントが出ない
+
+    // インジェクテッド側（TIM1 CC4 の立ち上がりで開始）
+    let mut injected = Injected::new(
+        &peripheral.ADC1,
+        &[
+            InjectedChannel {
+                channel: 0,
+                sample_time: SampleTime::Cycles56,
+                offset: 2048,
+            },
+            InjectedChannel {
+                channel: 1,
+                sample_time: SampleTime::Cycles56,
+                offset: 2048,
+            },
+        ],
+        Some((InjectedTrigger::Tim1Cc4, TriggerEdge::Rising)),
+    )
+    .unwrap();
+    injected.listen();
+
+    // レギュラー側（連続変換 + DMA）
+    let buffer = cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    let config = ScanConfig {
+        channels: &[(4, SampleTime::Cycles56)],
+        resolution: Resolution::Bits12,
+        conversion: Conversion::Continuous,
+    };
+    // DMA2 stream0 の ADC1 要求で転送する（DMA2 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let mut scan = ScanDma::new(
+        peripheral.ADC1,
+        (streams.s0, request::Adc1),
+        &config,
+        buffer,
+    )
+    .unwrap();
+    scan.start();
+
+    cortex_m::interrupt::free(|cs| {
+        SCAN.borrow(cs).replace(Some(scan));
+        INJECTED.borrow(cs).replace(Some(injected));
+    });
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::ADC);
+    }
+
+    // トリガ開始
+    peripheral.TIM1.cr1.modify(|_, w| w.cen().set_bit());
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let (regular, (ch0, ch1, count)) =
+            cortex_m::interrupt::free(|cs| (REGULAR.borrow(cs).get(), LATEST.borrow(cs).get()));
+        hprintln!(
+            "ch4: {}, ch0: {}, ch1: {} ({} injected)",
+            regular,
+            ch0,
+            ch1,
+            count
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
+
+#[interrupt]
+fn ADC() {
+    cortex_m::interrupt::free(|cs| {
+        let mut injected = INJECTED.borrow(cs).borrow_mut();
+        if let Some(injected) = injected.as_mut() {
+            if let Some(result) = injected.on_interrupt() {
+                let values = result.as_slice();
+                let (_, _, count) = LATEST.borrow(cs).get();
+                LATEST
+                    .borrow(cs)
+                    .set((values[0], values[1], count.wrapping_add(1)));
+            }
+        } else {
+            panic!("not found injected");
+        }
+    });
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut scan = SCAN.borrow(cs).borrow_mut();
+        if let Some(scan) = scan.as_mut() {
+            let result = scan.on_interrupt(|_, block| {
+                REGULAR.borrow(cs).set(block[block.len() - 1]);
+            });
+            if result.is_err() {
+                scan.restart();
+            }
+        } else {
+            panic!("not found scan");
+        }
+    });
+}
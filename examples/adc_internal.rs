@@ -0,0 +1,80 @@
+// 内部チャンネル（VREFINT、温度センサ、VBAT）の読み出し
+// VREFINT と工場出荷時のキャリブレーション値から実際の VDDA を求め、
+// PA4(ch4) の電圧を mV で、チップの温度を ℃ で出力する（スイッチ入力ごとに semihosting で出力）。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+use stm32f446re_rust_example::adc::internal::Analog;
+use stm32f446re_rust_example::adc::{self, Instance, Prescaler, SampleTime};
+use stm32f446re_rust_example::clock::config_clock;
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-4
+    peripheral.GPIOA.moder.modify(|_, w| w.moder4().analog()); // アナログ設定
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+    adc::set_sample_time(peripheral.ADC1.regs(), 4, SampleTime::Cycles56);
+
+    let mut analog = Analog::new(peripheral.ADC1, &peripheral.ADC_COMMON);
+    let cal = analog.calibration();
+    hprintln!(
+        "VREFINT_CAL: {}, TS_CAL1: {}, TS_CAL2: {}",
+        cal.vrefint,
+        cal.ts_cal1,
+        cal.ts_cal2
+    )
+    .unwrap();
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let vdda = analog.measure_vdda();
+        let pa4 = analog.read_mv(4);
+        let temperature = analog.temperature();
+        let vbat = analog.vbat();
+        hprintln!(
+            "VDDA: {} mV, PA4: {} mV, temp: {:.1} C, VBAT: {} mV",
+            vdda,
+            pa4,
+            temperature,
+            vbat
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+        cortex_m::asm::delay(clocks.sysclk / 50); // チャタリング対策で 20ms 待つ
+    }
+}
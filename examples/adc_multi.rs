@@ -0,0 +1,147 @@
+// トリプル ADC インターリーブモードで PA0(ch0) を高速サンプリング
+// ADC1 ~ ADC3 が同じチャンネルを 5 サイクルずつずらして変換する（ADCCLK 22.5MHz なので 4.5MSPS）。
+// 結果は DMA mode 2 で ADC_CDR から循環バッファへ取り込み、
+// スイッチ入力ごとに直近のブロックの最小/最大と、ADC ごとの平均を semihosting で出力する。
+// （ADC ごとの平均がずれている場合は ADC 間のオフセット誤差）
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::multi::{self, DmaMode, Mode, MultiConfig, MultiDma};
+use stm32f446re_rust_example::adc::{
+    self, Conversion, Instance, Prescaler, Resolution, SampleTime,
+};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+
+const ADCS: usize = 3;
+// 1ブロック(バッファの半分)に 3 x 100 サンプル
+const BUFFER_LEN: usize = ADCS * 100 * 2;
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static MULTI: Mutex<RefCell<Option<MultiDma<0>>>> = Mutex::new(RefCell::new(None));
+// (最小, 最大, ADC ごとの平均)
+static SUMMARY: Mutex<Cell<(u16, u16, [u32; ADCS])>> = Mutex::new(Cell::new((0, 0, [0; ADCS])));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+    stm32f446::ADC2::enable_clock(&peripheral.RCC);
+    stm32f446::ADC3::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-0
+    peripheral.GPIOA.moder.modify(|_, w| w.moder0().analog()); // アナログ設定
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    let buffer = cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    let sequence: &[(u8, SampleTime)] = &[(0, SampleTime::Cycles3)];
+    let config = MultiConfig {
+        mode: Mode::TripleInterleaved,
+        dma_mode: DmaMode::Mode2,
+        sequences: &[sequence, sequence, sequence],
+        resolution: Resolution::Bits12,
+        conversion: Conversion::Continuous,
+        delay: 5,
+    };
+    // DMA2 stream0 の ADC1 要求で転送する（DMA2 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let mut multi = MultiDma::new(
+        peripheral.ADC1,
+        peripheral.ADC2,
+        Some(peripheral.ADC3),
+        (streams.s0, request::Adc1),
+        &config,
+        buffer,
+    )
+    .unwrap();
+    multi.start();
+
+    cortex_m::interrupt::free(|cs| MULTI.borrow(cs).replace(Some(multi)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+    }
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let (min, max, average) = cortex_m::interrupt::free(|cs| SUMMARY.borrow(cs).get());
+        hprintln!(
+            "min: {}, max: {}, ADC1: {}, ADC2: {}, ADC3: {}",
+            min,
+            max,
+            average[0],
+            average[1],
+            average[2]
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut multi = MULTI.borrow(cs).borrow_mut();
+        if let Some(multi) = multi.as_mut() {
+            let result = multi.on_interrupt(|_, block| {
+                // インターリーブなのでブロックはそのまま時間順に並んでいる
+                let min = block.iter().copied().min().unwrap_or(0);
+                let max = block.iter().copied().max().unwrap_or(0);
+                let mut average = [0u32; ADCS];
+                for (adc, average) in average.iter_mut().enumerate() {
+                    let (sum, count) = multi::samples(block, ADCS, 1, adc, 0)
+                        .fold((0u32, 0u32), |(sum, count), value| {
+                            (sum + value as u32, count + 1)
+                        });
+                    *average = sum / count.max(1);
+                }
+                SUMMARY.borrow(cs).set((min, max, average));
+            });
+            if result.is_err() {
+                multi.restart();
+            }
+        } else {
+            panic!("not found multi");
+        }
+    });
+}
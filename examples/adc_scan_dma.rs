@@ -0,0 +1,144 @@
+// ADC1 のスキャン変換を DMA で循環バッファへ取り込む
+// PA0(ch0), PA1(ch1), PA4(ch4) を連続変換し、バッファの半分が埋まるたびに
+// DMA2_STREAM0 割り込みでチャンネルごとの平均を求める。平均値はスイッチ入力ごとに semihosting で出力。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::scan::{self, ScanConfig, ScanDma};
+use stm32f446re_rust_example::adc::{self, Conversion, Instance, Prescaler, Resolution};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+
+const CHANNELS: usize = 3;
+// 1ブロック(バッファの半分)に 32 組
+const BUFFER_LEN: usize = CHANNELS * 32 * 2;
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static SCAN: Mutex<RefCell<Option<ScanDma<stm32f446::ADC1, 0>>>> = Mutex::new(RefCell::new(None));
+static AVERAGE: Mutex<Cell<[u32; CHANNELS]>> = Mutex::new(Cell::new([0; CHANNELS]));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0, PA1, PA4
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // アナログ設定
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder0().analog().moder1().analog().moder4().analog());
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    let buffer = cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    // 信号源のインピーダンス[Ω]から各チャンネルのサンプリング時間を決める
+    let adc_clock = Prescaler::Div4.adc_clock(&clocks);
+    let channels = scan::channels_for_sources(
+        [
+            (0, 10_000), // 可変抵抗など
+            (1, 10_000),
+            (4, 100_000), // 分圧抵抗が大きい場合
+        ],
+        adc_clock,
+        Resolution::Bits12,
+    )
+    .expect("source impedance too high");
+    let config = ScanConfig {
+        channels: &channels,
+        resolution: Resolution::Bits12,
+        conversion: Conversion::Continuous,
+    };
+    // DMA2 stream0 の ADC1 要求で転送する（DMA2 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let mut scan = ScanDma::new(
+        peripheral.ADC1,
+        (streams.s0, request::Adc1),
+        &config,
+        buffer,
+    )
+    .unwrap();
+    scan.start();
+
+    cortex_m::interrupt::free(|cs| SCAN.borrow(cs).replace(Some(scan)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+    }
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let average = cortex_m::interrupt::free(|cs| AVERAGE.borrow(cs).get());
+        hprintln!(
+            "ch0: {}, ch1: {}, ch4: {}",
+            average[0],
+            average[1],
+            average[2]
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut scan = SCAN.borrow(cs).borrow_mut();
+        if let Some(scan) = scan.as_mut() {
+            let result = scan.on_interrupt(|_, block| {
+                // ブロックは ch0, ch1, ch4, ch0, ch1, ch4, ... の順に並んでいる
+                let mut sum = [0u32; CHANNELS];
+                for frame in block.chunks_exact(CHANNELS) {
+                    for (s, &value) in sum.iter_mut().zip(frame) {
+                        *s += value as u32;
+                    }
+                }
+                let frames = (block.len() / CHANNELS) as u32;
+                AVERAGE.borrow(cs).set(sum.map(|s| s / frames));
+            });
+            if result.is_err() {
+                scan.restart();
+            }
+        } else {
+            panic!("not found scan");
+        }
+    });
+}
@@ -0,0 +1,128 @@
+// タイマの TRGO で ADC を 10kHz で変換
+// TIM2 の更新イベントで ADC1 ch4(PA4) の変換を開始し、結果は DMA でバッファへ取り込む。
+// 1秒ごとに、その間に受け取ったサンプル数（約10000になるはず）と最小/最大値を semihosting で出力する。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::scan::{ScanConfig, ScanDma};
+use stm32f446re_rust_example::adc::trigger::{SampleClock, TriggerEvent};
+use stm32f446re_rust_example::adc::{self, Prescaler, Resolution, SampleTime};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+use stm32f446re_rust_example::timer;
+
+const BUFFER_LEN: usize = 200;
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static SCAN: Mutex<RefCell<Option<ScanDma<stm32f446::ADC1, 0>>>> = Mutex::new(RefCell::new(None));
+// (サンプル数, 最小, 最大)
+static SUMMARY: Mutex<Cell<(u32, u16, u16)>> = Mutex::new(Cell::new((0, u16::MAX, 0)));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4
+    <stm32f446::ADC1 as adc::Instance>::enable_clock(&peripheral.RCC);
+    <stm32f446::TIM2 as timer::Instance>::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-4
+    peripheral.GPIOA.moder.modify(|_, w| w.moder4().analog()); // アナログ設定
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    // TIM2 の更新イベント(TRGO)を 10kHz で発生させる
+    let mut sample_clock = SampleClock::new(peripheral.TIM2, &clocks, TriggerEvent::Trgo).unwrap();
+    let rate = sample_clock.sample_rate(10_000).unwrap();
+    hprintln!("sample rate: {} Hz", rate).unwrap();
+
+    let buffer = cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap();
+    let config = ScanConfig {
+        channels: &[(4, SampleTime::Cycles56)],
+        resolution: Resolution::Bits12,
+        conversion: sample_clock.conversion(),
+    };
+    // DMA2 stream0 の ADC1 要求で転送する（DMA2 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let mut scan = ScanDma::new(
+        peripheral.ADC1,
+        (streams.s0, request::Adc1),
+        &config,
+        buffer,
+    )
+    .unwrap();
+    scan.start(); // トリガ待ち
+
+    cortex_m::interrupt::free(|cs| SCAN.borrow(cs).replace(Some(scan)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+    }
+
+    sample_clock.start();
+
+    loop {
+        cortex_m::asm::delay(clocks.sysclk); // 約1秒
+        let (count, min, max) =
+            cortex_m::interrupt::free(|cs| SUMMARY.borrow(cs).replace((0, u16::MAX, 0)));
+        hprintln!("{} samples, min: {}, max: {}", count, min, max).unwrap();
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        let mut scan = SCAN.borrow(cs).borrow_mut();
+        if let Some(scan) = scan.as_mut() {
+            let result = scan.on_interrupt(|_, block| {
+                let (mut count, mut min, mut max) = SUMMARY.borrow(cs).get();
+                for &value in block {
+                    min = min.min(value);
+                    max = max.max(value);
+                }
+                count += block.len() as u32;
+                SUMMARY.borrow(cs).set((count, min, max));
+            });
+            if result.is_err() {
+                scan.restart();
+            }
+        } else {
+            panic!("not found scan");
+        }
+    });
+}
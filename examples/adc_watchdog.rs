@@ -0,0 +1,119 @@
+// ADC のアナログウォッチドッグで PA4(ch4) の電圧を監視
+// ADC1 で ch4 を連続変換し、1000mV ~ 2300mV の窓から外れたら ADC 割り込みで知らせる。
+// 上に外れている間は LD2(PA5) を点灯。戻りの判定には 100mV のヒステリシスを持たせている。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+
+use stm32f446re_rust_example::adc::internal::Analog;
+use stm32f446re_rust_example::adc::watchdog::{Channels, Group, Watchdog, WatchdogConfig, Zone};
+use stm32f446re_rust_example::adc::{self, Conversion, Instance, Prescaler, SampleTime};
+use stm32f446re_rust_example::clock::config_clock;
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static WATCHDOG: Mutex<RefCell<Option<Watchdog<stm32f446::ADC1>>>> = Mutex::new(RefCell::new(None));
+static GPIOA: Mutex<RefCell<Option<stm32f446::GPIOA>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4, PA5
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-4, 5
+    peripheral.GPIOA.moder.modify(|_, w| w.moder4().analog()); // アナログ設定
+    peripheral.GPIOA.moder.modify(|_, w| w.moder5().output()); // LD2
+
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+
+    // しきい値を mV で決めるために、先に VDDA を測っておく
+    let analog = Analog::new(peripheral.ADC1, &peripheral.ADC_COMMON);
+    let vdda = analog.vdda();
+    let adc1 = analog.release();
+    hprintln!("VDDA: {} mV", vdda).unwrap();
+
+    let regs = adc1.regs();
+    adc::set_sample_time(regs, 4, SampleTime::Cycles56);
+    adc::set_regular_sequence(regs, &[4]).unwrap();
+    adc::set_conversion(regs, Conversion::Continuous);
+
+    let config = WatchdogConfig {
+        channels: Channels::Single(4),
+        group: Group::Regular,
+        low_mv: 1000,
+        high_mv: 2300,
+        hysteresis_mv: 100,
+        vdda_mv: vdda,
+    };
+    let mut watchdog = Watchdog::new(&adc1, &config).unwrap();
+    watchdog.enable();
+
+    cortex_m::interrupt::free(|cs| {
+        WATCHDOG.borrow(cs).replace(Some(watchdog));
+        GPIOA.borrow(cs).replace(Some(peripheral.GPIOA));
+    });
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::ADC);
+    }
+
+    // 連続変換開始
+    adc::power_on(regs);
+    regs.cr2.modify(|_, w| w.swstart().set_bit());
+
+    loop {}
+}
+
+#[interrupt]
+fn ADC() {
+    cortex_m::interrupt::free(|cs| {
+        let mut watchdog = WATCHDOG.borrow(cs).borrow_mut();
+        let gpioa = GPIOA.borrow(cs).borrow();
+        if let (Some(watchdog), Some(gpioa)) = (watchdog.as_mut(), gpioa.as_ref()) {
+            watchdog.on_interrupt(|zone, value| {
+                match zone {
+                    Zone::Above => gpioa.odr.modify(|_, w| w.odr5().high()),
+                    _ => gpioa.odr.modify(|_, w| w.odr5().low()),
+                }
+                hprintln!("{:?}: {}", zone, value).unwrap();
+            });
+        } else {
+            panic!("not found peripheral");
+        }
+    });
+}
@@ -0,0 +1,102 @@
+// DAC の出力を mV で指定する
+// 起動時に VREFINT から VDDA(= VREF+) を測って DAC の基準電圧にし、
+// スイッチ入力ごとに channel 1 (PA4) と channel 2 (PA5) を同時に切り替える。
+//   1回目: 1000mV / 2000mV、2回目: 2000mV / 1000mV、...
+// channel 2 は AnalogOutput トレイト経由でランプ状に上げてから切り替える例も兼ねる。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+use stm32f446re_rust_example::adc::internal::Analog;
+use stm32f446re_rust_example::adc::{self, Instance, Prescaler};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dac::{AnalogOutput, Channel, Dac};
+
+// 0mV から target[mV] まで step[mV] ずつ上げる
+fn ramp<O: AnalogOutput>(output: &mut O, target: u32, step: u32) -> Result<(), O::Error> {
+    let mut mv = 0;
+    while mv < target.min(output.max_mv()) {
+        output.set_mv(mv)?;
+        mv += step;
+        cortex_m::asm::delay(180_000); // 1ms
+    }
+    output.set_mv(target)
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4, PA5
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    peripheral.RCC.apb1enr.modify(|_, w| w.dacen().enabled()); // DAC
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-4, 5（DAC の設定より先にアナログ設定）
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder4().analog().moder5().analog());
+
+    // VREF+ (= VDDA) を測る
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+    let analog = Analog::new(peripheral.ADC1, &peripheral.ADC_COMMON);
+    let vref = analog.vdda();
+    analog.release();
+    hprintln!("VREF+: {} mV", vref).unwrap();
+
+    let mut dac = Dac::new(peripheral.DAC);
+    dac.set_vref(vref);
+    dac.enable(Channel::C1);
+    dac.enable(Channel::C2);
+
+    ramp(&mut dac.output(Channel::C2), 2000, 50).unwrap();
+    dac.write_mv(Channel::C1, 1000).unwrap();
+
+    let mut swap = false;
+    loop {
+        hprintln!(
+            "ch1: {} mV, ch2: {} mV",
+            dac.read_mv(Channel::C1),
+            dac.read_mv(Channel::C2)
+        )
+        .unwrap();
+
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        swap = !swap;
+        // DHR12RD で両チャンネルを同じタイミングで更新
+        if swap {
+            dac.write_dual_mv(2000, 1000).unwrap();
+        } else {
+            dac.write_dual_mv(1000, 2000).unwrap();
+        }
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
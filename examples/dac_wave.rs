@@ -0,0 +1,105 @@
+// DAC の波形出力
+// channel 1 (PA4): TIM2 の TRGO(100kHz) ごとに DMA で正弦波のテーブルを出力（100サンプルなので 1kHz）
+// channel 2 (PA5): TIM4 の TRGO(400kHz) ごとに内蔵の三角波生成で出力（振幅 12bit なので約 49Hz）
+// スイッチ入力ごとに channel 2 を三角波とノイズで切り替える。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dac::wave::WaveClock;
+use stm32f446re_rust_example::dac::{Amplitude, Channel, Dac, Wave};
+use stm32f446re_rust_example::dma::{self, request};
+use stm32f446re_rust_example::table;
+use stm32f446re_rust_example::timer;
+
+const SINE_LEN: usize = 100;
+
+// 正弦波 1 周期分（0 ~ 4095、コンパイル時に計算）
+static SINE: [u16; SINE_LEN] = table::to_u16(table::sine(4095));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4, PA5
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    peripheral.RCC.apb1enr.modify(|_, w| w.dacen().enabled()); // DAC
+    <stm32f446::TIM2 as timer::Instance>::enable_clock(&peripheral.RCC);
+    <stm32f446::TIM4 as timer::Instance>::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-4, 5（DAC の設定より先にアナログ設定）
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder4().analog().moder5().analog());
+
+    let sine: &'static [u16] = &SINE;
+
+    let mut clock1 = WaveClock::new(peripheral.TIM2, &clocks).unwrap();
+    let rate1 = clock1.sample_rate(100_000).unwrap();
+    let mut clock2 = WaveClock::new(peripheral.TIM4, &clocks).unwrap();
+    let rate2 = clock2.sample_rate(400_000).unwrap();
+    hprintln!("ch1: {} Hz, ch2: {} Hz", rate1, rate2).unwrap();
+
+    let mut dac = Dac::new(peripheral.DAC);
+
+    // channel 1: DMA1 stream5 の DAC1 要求で正弦波（DMA1 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA1, &peripheral.RCC);
+    dac.set_trigger(Channel::C1, Some(clock1.trigger()));
+    dac.enable(Channel::C1);
+    let mut output = dac.start_dma((streams.s5, request::Dac1), sine).unwrap();
+
+    // channel 2: 0 を基準に三角波
+    dac.write(Channel::C2, 0);
+    dac.set_trigger(Channel::C2, Some(clock2.trigger()));
+    dac.set_wave(Channel::C2, Wave::Triangle(Amplitude::Bits12));
+    dac.enable(Channel::C2);
+
+    clock1.start();
+    clock2.start();
+
+    let mut noise = false;
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        noise = !noise;
+        let wave = if noise {
+            Wave::Noise(Amplitude::Bits12)
+        } else {
+            Wave::Triangle(Amplitude::Bits12)
+        };
+        // WAVE は DAC を止めてから変更する
+        dac.disable(Channel::C2);
+        dac.set_wave(Channel::C2, wave);
+        dac.enable(Channel::C2);
+        if dac.is_underrun(Channel::C1) {
+            let (stream, sine) = dac.stop_dma(output);
+            output = dac.start_dma((stream, request::Dac1), sine).unwrap();
+        }
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
@@ -0,0 +1,91 @@
+// 遅延フォーマットのバイナリログ
+// 100ms ごとに同じ内容を info! と defer_info! で出力し、それぞれのサイクル数を DWT で計測する。
+// 1秒ごとに計測結果を defer_info! で出力する。
+// 出力先は RAM のリングバッファ(RTT)か USART2 を使う（バイナリなのでセミホスティングでは読めない）。
+//   cargo run --example deferred_log --no-default-features --features log-rtt
+//   cargo run --example deferred_log --no-default-features --features log-usart
+// ホストでは tools/binlog-decode に同じ ELF を渡して読む（info! の行はテキストのまま混ざるので、RTT で読むと見やすい）。
+//   cargo run -p binlog-decode --target x86_64-unknown-linux-gnu -- \
+//       target/thumbv7em-none-eabihf/debug/examples/deferred_log /dev/ttyACM0
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::{entry, exception};
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::systick::{self, TimeBase};
+use stm32f446re_rust_example::{defer_info, defer_warn, info, profile};
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+    let mut core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+    let time_base = TimeBase::new(core_peripheral.SYST, &clocks, 1000).unwrap();
+    let mut delay = time_base.delay();
+    profile::init(&mut core_peripheral.DCB, &mut core_peripheral.DWT);
+
+    #[cfg(feature = "log-usart")]
+    {
+        use stm32f446re_rust_example::log;
+        use stm32f446re_rust_example::serial::{self, Config};
+
+        // setting GPIOA-2（USART2_TX）
+        peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+        <stm32f446::USART2 as serial::Instance>::enable_clock(&peripheral.RCC);
+        peripheral.GPIOA.moder.modify(|_, w| w.moder2().alternate());
+        peripheral.GPIOA.afrl.modify(|_, w| w.afrl2().af7());
+        log::usart::init(peripheral.USART2, &clocks, &Config::default()).unwrap();
+    }
+
+    defer_info!("start (sysclk {} Hz)", clocks.sysclk);
+    let mut count: u32 = 0;
+    loop {
+        let voltage = 3.3f32 * (count % 100) as f32 / 100.0;
+        profile::measure("info!", || {
+            info!("count = {}, voltage = {:.3} V", count, voltage)
+        });
+        profile::measure("defer_info!", || {
+            defer_info!("count = {}, voltage = {:.3} V", count, voltage)
+        });
+
+        count += 1;
+        if count.is_multiple_of(10) {
+            for name in ["info!", "defer_info!"] {
+                if let Some(stats) = profile::stats(name) {
+                    defer_info!(
+                        "{:<12} avg {} cycles (min {}, max {})",
+                        name,
+                        stats.average(),
+                        stats.min,
+                        stats.max
+                    );
+                }
+            }
+            if systick::uptime_ms() > 60_000 {
+                defer_warn!("running for {} s", systick::uptime_ms() / 1000);
+            }
+            profile::reset();
+        }
+        delay.delay_ms(100u32);
+    }
+}
+
+#[exception]
+fn SysTick() {
+    systick::on_tick();
+}
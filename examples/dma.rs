@@ -29,15 +29,16 @@ use stm32f4::stm32f446;
 // （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
 use stm32f4::stm32f446::interrupt;
 
+use stm32f446re_rust_example::dma::{self, request, Config, Transfer};
+use stm32f446re_rust_example::table;
+
+// TIM2 の ARR（50 カウントで 1 周期）
+const ARR: u32 = 50 - 1;
+
 // const と static の違いはメモリ上に固定の位置を持つかどうか。
 // const変数への参照は常に同じアドレスを指すとは限らない。
-// 2%(1 - 1 = 0) -> 100%(50 - 1 = 49) -> 2%(1 - 1 = 0)
-static DUTY_TABLE: [u32; 100] = [
-    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
-    26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49,
-    49, 48, 47, 46, 45, 44, 43, 42, 41, 40, 39, 38, 37, 36, 35, 34, 33, 32, 31, 30, 29, 28, 27, 26,
-    25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
-];
+// 2%(1 - 1 = 0) -> 100%(ARR) -> 2%(1 - 1 = 0) の三角波（ARR を変えればテーブルも作り直される）
+static DUTY_TABLE: [u32; 100] = table::triangle(ARR);
 
 // クロックの初期設定を実施
 // SYSCLK: HSE(ST-Link 8MHz) -> PLL -> 180MHz
@@ -86,37 +87,6 @@ fn config_clock(peripheral: &stm32f4::stm32f446::Peripherals) {
     peripheral.RCC.cfgr.modify(|_, w| w.ppre2().div2());
 }
 
-fn config_dma(peripheral: &stm32f4::stm32f446::Peripherals) {
-    // DMA1 設定 stream7-channel3
-    peripheral.DMA1.st[7].cr.modify(|_, w| w.chsel().bits(3)); // channel3
-
-    // 初期値のダイレクトモードではMSIZEは無視されPSIZEとなるが一応セットする。
-    peripheral.DMA1.st[7].cr.modify(|_, w| {
-        w.msize()
-            .bits32() // メモリデータサイズ4byte
-            .psize()
-            .bits32() // ペリフェラルデータサイズ4byte
-            .minc()
-            .incremented() // メモリアドレスインクリメント
-            .circ()
-            .enabled() // サーキュラモード有効
-            .dir()
-            .memory_to_peripheral() // メモリからペリフェラルへ転送
-    });
-
-    peripheral.DMA1.st[7]
-        .ndtr
-        .modify(|_, w| w.ndt().bits(DUTY_TABLE.len() as u16)); // 転送データ数
-    peripheral.DMA1.st[7]
-        .par
-        .write(|w| unsafe { w.pa().bits(0x4000_0000 + 0x34) }); // 転送先ペリフェラルアドレス指定（TIM2_CCR1）
-    peripheral.DMA1.st[7]
-        .m0ar
-        .write(|w| unsafe { w.m0a().bits(DUTY_TABLE.as_ptr() as u32) }); // 転送元メモリアドレス指定
-
-    peripheral.DMA1.st[7].cr.modify(|_, w| w.en().enabled()); // DMA1 ストリーム7有効化
-}
-
 fn config_tim(peripheral: &stm32f4::stm32f446::Peripherals) {
     // TIM2 設定（クロックはAPB1 * 2 = 90MHz）
     peripheral
@@ -125,7 +95,7 @@ fn config_tim(peripheral: &stm32f4::stm32f446::Peripherals) {
         .modify(|_, w| w.oc1pe().enabled()); // CCR1 プリロード有効化
     peripheral.TIM2.cr1.modify(|_, w| w.arpe().enabled()); // ARR 自動プリロード有効化（これがないと再ロードできないので1パルスで止まる）
     peripheral.TIM2.psc.write(unsafe { |w| w.bits(18000 - 1) }); // プリスケーラ（何クロックで1カウントか設定）
-    peripheral.TIM2.arr.write(unsafe { |w| w.bits(50 - 1) }); // オートリロードレジスタ（カウント値設定）, 50Hz
+    peripheral.TIM2.arr.write(unsafe { |w| w.bits(ARR) }); // オートリロードレジスタ（カウント値設定）, 50Hz
     peripheral
         .TIM2
         .ccmr1_output()
@@ -145,16 +115,30 @@ fn main() -> ! {
     // 各機能へのクロック入力設定
     peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
     peripheral.RCC.apb1enr.modify(|_, w| w.tim2en().enabled());
-    peripheral.RCC.ahb1enr.modify(|_, w| w.dma1en().enabled());
 
     // setting LD2(GPIOA-5)
     peripheral.GPIOA.moder.modify(|_, w| w.moder5().alternate());
     peripheral.GPIOA.afrl.modify(|_, w| w.afrl5().af1()); // TIM2-ch1 を選択
 
-    config_dma(&peripheral);
-
     config_tim(&peripheral);
 
+    // DMA1 stream7 の TIM2_UP 要求（チャンネル3）で DUTY_TABLE を TIM2_CCR1 へ循環転送
+    // （DMA1 へのクロック供給は split() で行う）
+    let streams = dma::split(peripheral.DMA1, &peripheral.RCC);
+    let config = Config {
+        circular: true,
+        ..Default::default()
+    };
+    let mut transfer = Transfer::memory_to_peripheral(
+        streams.s7,
+        request::Tim2Up,
+        &peripheral.TIM2.ccr1,
+        &DUTY_TABLE,
+        &config,
+    )
+    .unwrap();
+    transfer.start();
+
     peripheral.TIM2.cr1.modify(|_, w| w.cen().enabled()); // カウント開始
 
     loop {}
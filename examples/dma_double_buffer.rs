@@ -0,0 +1,130 @@
+// DMA のダブルバッファモードで ADC の変換結果を途切れずに取り込む
+// ADC1 で PA0(ch0) を連続変換し、DMA2 stream0 で2つのバッファへ交互に転送する。
+// 片方が埋まるたびに DMA2_STREAM0 割り込みで、もう片方に書いている間に平均を求める。
+// 処理が間に合わなかった(Overrun)回数と合わせて、スイッチ入力ごとに semihosting で出力。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::{Cell, RefCell};
+
+use stm32f446re_rust_example::adc::{
+    self, Conversion, Instance, Prescaler, Resolution, SampleTime,
+};
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::double_buffer::DoubleBuffer;
+use stm32f446re_rust_example::dma::{self, request, Config, Priority};
+
+const BUFFER_LEN: usize = 256;
+
+type Capture = DoubleBuffer<stm32f446::DMA2, 0, u16>;
+
+static CAPTURE: Mutex<RefCell<Option<Capture>>> = Mutex::new(RefCell::new(None));
+// (平均値, 埋まったバッファの数, Overrun の回数)
+static STATS: Mutex<Cell<(u16, u32, u32)>> = Mutex::new(Cell::new((0, 0, 0)));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    stm32f446::ADC1::enable_clock(&peripheral.RCC);
+
+    // アナログ設定
+    peripheral.GPIOA.moder.modify(|_, w| w.moder0().analog());
+
+    // ADC1 ch0 を連続変換して、変換ごとに DMA 要求を出す
+    let regs = peripheral.ADC1.regs();
+    adc::set_prescaler(&peripheral.ADC_COMMON, Prescaler::Div4); // 90 / 4 = 22.5MHz
+    adc::set_sample_time(regs, 0, SampleTime::Cycles480);
+    adc::set_resolution(regs, Resolution::Bits12);
+    adc::set_regular_sequence(regs, &[0]).unwrap();
+    adc::set_conversion(regs, Conversion::Continuous);
+    regs.cr2.modify(|_, w| w.dma().set_bit().dds().set_bit());
+
+    // DMA2 stream0 の ADC1 要求で、ADC1_DR から2つのバッファへ交互に転送
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let buffers = [
+        &mut cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap()[..],
+        &mut cortex_m::singleton!(: [u16; BUFFER_LEN] = [0; BUFFER_LEN]).unwrap()[..],
+    ];
+    let config = Config {
+        priority: Priority::High,
+        transfer_complete_interrupt: true,
+        error_interrupt: true,
+        ..Default::default()
+    };
+    let mut capture =
+        DoubleBuffer::peripheral_to_memory(streams.s0, request::Adc1, &regs.dr, buffers, &config)
+            .unwrap();
+    capture.start();
+    cortex_m::interrupt::free(|cs| CAPTURE.borrow(cs).replace(Some(capture)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA2_STREAM0);
+    }
+
+    // 変換開始
+    adc::power_on(regs);
+    regs.cr2.modify(|_, w| w.swstart().set_bit());
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        let (average, count, overruns) = cortex_m::interrupt::free(|cs| STATS.borrow(cs).get());
+        hprintln!(
+            "average: {}, buffers: {}, overruns: {}",
+            average,
+            count,
+            overruns
+        )
+        .unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
+
+#[interrupt]
+fn DMA2_STREAM0() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(capture) = CAPTURE.borrow(cs).borrow_mut().as_mut() {
+            let (mut average, mut count, mut overruns) = STATS.borrow(cs).get();
+            // DMA がもう片方に書いている間に、埋まった側の平均を求める
+            let result = capture.on_interrupt(|_, buffer| {
+                let sum: u32 = buffer.iter().map(|&value| value as u32).sum();
+                average = (sum / buffer.len() as u32) as u16;
+                count += 1;
+            });
+            if result.is_err() {
+                overruns += 1;
+            }
+            STATS.borrow(cs).set((average, count, overruns));
+        } else {
+            panic!("not found capture");
+        }
+    });
+}
@@ -0,0 +1,101 @@
+// DMA2 のメモリ間転送と CPU のコピーの速さを比べる
+// 大きさごとに core::ptr::copy_nonoverlapping と MemoryDma::copy() / fill() を DWT のサイクルカウンタで計測し、
+// スイッチ入力ごとに集計結果を semihosting で出力する。
+// 最後に非同期のコピーを開始して、転送が終わるまでに CPU が回せたループの回数も出す。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::memory::{Burst, MemoryConfig, MemoryDma};
+use stm32f446re_rust_example::dma::{self, FifoThreshold};
+use stm32f446re_rust_example::profile;
+
+const WORDS: usize = 4096; // 16KB
+
+// 計測する大きさ[byte]と集計の名前
+const SIZES: [(usize, &str, &str, &str); 5] = [
+    (64, "cpu copy 64", "dma copy 64", "dma fill 64"),
+    (256, "cpu copy 256", "dma copy 256", "dma fill 256"),
+    (1024, "cpu copy 1K", "dma copy 1K", "dma fill 1K"),
+    (4096, "cpu copy 4K", "dma copy 4K", "dma fill 4K"),
+    (16384, "cpu copy 16K", "dma copy 16K", "dma fill 16K"),
+];
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+    let mut core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    profile::init(&mut core_peripheral.DCB, &mut core_peripheral.DWT);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+
+    let src = cortex_m::singleton!(: [u32; WORDS] = [0; WORDS]).unwrap();
+    let dst = cortex_m::singleton!(: [u32; WORDS] = [0; WORDS]).unwrap();
+    for (i, word) in src.iter_mut().enumerate() {
+        *word = i as u32;
+    }
+
+    // 比べやすいように CPU へのフォールバックは無しにする
+    let streams = dma::split(peripheral.DMA2, &peripheral.RCC);
+    let config = MemoryConfig {
+        burst: Burst::Incr4,
+        fifo_threshold: FifoThreshold::Full,
+        cpu_threshold: 0,
+        ..Default::default()
+    };
+    let mut memory = MemoryDma::new(streams.s0, &config);
+
+    for _ in 0..8 {
+        for &(bytes, cpu_name, copy_name, fill_name) in SIZES.iter() {
+            let words = bytes / 4;
+            profile::measure(cpu_name, || unsafe {
+                core::ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr(), words);
+            });
+            profile::measure(copy_name, || memory.copy(&src[..words], &mut dst[..words])).unwrap();
+            profile::measure(fill_name, || memory.fill(&mut dst[..words], 0xA5A5_A5A5)).unwrap();
+        }
+    }
+    memory.copy(&src[..], &mut dst[..]).unwrap();
+    if src[..] != dst[..] {
+        panic!("copy mismatch");
+    }
+
+    // 非同期: 転送中も CPU は別の処理ができる
+    let src: &'static [u32] = src;
+    let mut transfer = memory.copy_async(src, dst).unwrap();
+    let mut loops = 0u32;
+    while !transfer.update().unwrap() {
+        loops += 1;
+    }
+    let (_memory, _src, _dst) = transfer.release();
+
+    loop {
+        // スイッチ(PC13)が押されるまで待つ
+        while peripheral.GPIOC.idr.read().idr13().is_high() {}
+        profile::dump_semihosting(clocks.sysclk);
+        hprintln!("async copy 16K: {} loops while transferring", loops).unwrap();
+        while peripheral.GPIOC.idr.read().idr13().is_low() {}
+    }
+}
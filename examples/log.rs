@@ -0,0 +1,88 @@
+// ログ出力（レベル、モジュールごとのフィルタ、時刻付き）
+// 1ms ティックのタイムベースを時刻に使い、1秒ごとに各レベルのログを出力する。
+// sensor モジュールは Debug まで、それ以外は Info まで出力する。
+// 出力先は feature で選ぶ（デフォルトはセミホスティング）。
+//   cargo run --example log --no-default-features --features log-usart (USART2 115200bps 8N1)
+//   cargo run --example log --no-default-features --features log-rtt
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::{entry, exception};
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::log::{self, Level};
+use stm32f446re_rust_example::systick::{self, TimeBase};
+use stm32f446re_rust_example::{error, info, trace, warn};
+
+mod sensor {
+    use stm32f446re_rust_example::{debug, info, trace};
+
+    pub fn measure(count: u32) -> u32 {
+        let value = (count * 37) % 100;
+        trace!("raw value {}", value); // Debug までなので出ない
+        debug!("value = {}", value);
+        if value > 80 {
+            info!("value is high");
+        }
+        value
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+    let core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+    let time_base = TimeBase::new(core_peripheral.SYST, &clocks, 1000).unwrap();
+    let mut delay = time_base.delay();
+
+    #[cfg(feature = "log-usart")]
+    {
+        use stm32f446re_rust_example::serial::{self, Config};
+
+        // setting GPIOA-2（USART2_TX）
+        peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+        <stm32f446::USART2 as serial::Instance>::enable_clock(&peripheral.RCC);
+        peripheral.GPIOA.moder.modify(|_, w| w.moder2().alternate());
+        peripheral.GPIOA.afrl.modify(|_, w| w.afrl2().af7());
+        log::usart::init(peripheral.USART2, &clocks, &Config::default()).unwrap();
+    }
+
+    log::set_level(Level::Info);
+    // sensor モジュールだけ詳しく出す
+    log::set_module_level("log::sensor", Level::Debug).unwrap();
+
+    info!("start (sysclk {} Hz)", clocks.sysclk);
+    let mut count = 0;
+    loop {
+        let value = sensor::measure(count);
+        if value < 10 {
+            warn!("value is low: {}", value);
+        }
+        if count % 10 == 9 {
+            error!("count {} (error sample)", count);
+        }
+        trace!("loop {}", count); // Info までなので出ない
+        count += 1;
+        delay.delay_ms(1000u32);
+    }
+}
+
+#[exception]
+fn SysTick() {
+    systick::on_tick();
+}
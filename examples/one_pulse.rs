@@ -0,0 +1,118 @@
+// ワンパルスモードで遅延パルスを出力
+// スイッチを押してから 500ms 後に 200ms だけLEDを点灯させる。
+// 点灯中(遅延中)にもう一度押すと、そこから遅延をやり直す。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::timer::one_pulse::{OnePulse, Trigger};
+use stm32f446re_rust_example::timer::{Channel, Instance};
+
+// グローバル変数(メインと割り込み関数の両方でアクセスするため)
+static EXTI: Mutex<RefCell<Option<stm32f446::EXTI>>> = Mutex::new(RefCell::new(None));
+static ONE_PULSE: Mutex<RefCell<Option<OnePulse<stm32f446::TIM2>>>> =
+    Mutex::new(RefCell::new(None));
+
+fn config_exti(peripheral: &stm32f4::stm32f446::Peripherals) {
+    // exti line 13 でポートCを外部割り込みのソースとする
+    peripheral
+        .SYSCFG
+        .exticr4
+        .modify(|_, w| unsafe { w.exti13().bits(0b0010) });
+    // EXTI line 13 の割り込みを有効化（GPIOC-13 が ユーザスイッチ B1 に接続されている）
+    peripheral.EXTI.imr.modify(|_, w| w.mr13().unmasked());
+    // 立ち下がりエッジでトリガーする
+    peripheral.EXTI.ftsr.modify(|_, w| w.tr13().enabled());
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled());
+    peripheral.RCC.apb2enr.modify(|_, w| w.syscfgen().enabled());
+    stm32f446::TIM2::enable_clock(&peripheral.RCC);
+
+    // setting LD2(GPIOA-5)
+    peripheral.GPIOA.moder.modify(|_, w| w.moder5().alternate());
+    peripheral.GPIOA.afrl.modify(|_, w| w.afrl5().af1()); // TIM2-ch1 を選択
+
+    config_exti(&peripheral);
+
+    // スイッチ(PC13)はタイマ入力に繋がらないので、EXTI 割り込みから fire() で開始する。
+    // 外部信号を PA1(TIM2-ch2, AF1) に入れるなら Trigger::Ti2(Polarity::Rising) で
+    // 割り込みを介さずにハードウェアでトリガできる。
+    let one_pulse = OnePulse::new(
+        peripheral.TIM2,
+        &clocks,
+        Channel::C1,
+        Trigger::Software,
+        500_000,
+        200_000,
+    )
+    .unwrap();
+
+    cortex_m::interrupt::free(|cs| {
+        EXTI.borrow(cs).replace(Some(peripheral.EXTI));
+        ONE_PULSE.borrow(cs).replace(Some(one_pulse));
+    });
+
+    // 割り込み登録（グローバル変数に入れてから有効化しないと、先に来た割り込みで panic する）
+    unsafe {
+        // EXTI15_10割り込み有効化（EXTI13で割り込みが発生するので）
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::EXTI15_10);
+    }
+
+    loop {}
+}
+
+#[interrupt]
+fn EXTI15_10() {
+    cortex_m::interrupt::free(|cs| {
+        let exti = EXTI.borrow(cs).borrow();
+        let mut one_pulse = ONE_PULSE.borrow(cs).borrow_mut();
+        if let (Some(exti), Some(one_pulse)) = (exti.as_ref(), one_pulse.as_mut()) {
+            if exti.pr.read().pr13().is_not_pending() {
+                return;
+            }
+            exti.pr.modify(|_, w| w.pr13().set_bit());
+            one_pulse.fire();
+        } else {
+            panic!("not found peripheral");
+        }
+    });
+}
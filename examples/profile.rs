@@ -0,0 +1,142 @@
+// DWT サイクルカウンタで ISR の処理時間と割り込み遅延を計測
+// adc.rs と同じくスイッチ入力で ADC 変換し、EXTI15_10 の処理時間（ADC 変換待ちを含む）を計測する。
+// また、1秒ごとに EXTI0 をソフトウェアで保留にし、保留から ISR に入るまでのサイクル数を計測する。
+// 集計結果は1秒ごとに semihosting で出力する。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+// 割り込み関数の定義に必要
+// （デフォルトは何もしないことが定義されていて、そこに上書きする感じ）
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::profile;
+
+// グローバル変数(メインと割り込み関数の両方でペリフェラルアクセスするため)
+static PERIPHERAL: Mutex<RefCell<Option<stm32f446::Peripherals>>> = Mutex::new(RefCell::new(None));
+
+fn config_exti(peripheral: &stm32f4::stm32f446::Peripherals) {
+    // exti line 13 でポートCを外部割り込みのソースとする
+    peripheral
+        .SYSCFG
+        .exticr4
+        .modify(|_, w| unsafe { w.exti13().bits(0b0010) });
+    // EXTI line 13 の割り込みを有効化（GPIOC-13 が ユーザスイッチ B1 に接続されている）
+    peripheral.EXTI.imr.modify(|_, w| w.mr13().unmasked());
+    // 立ち下がりエッジでトリガーする
+    peripheral.EXTI.ftsr.modify(|_, w| w.tr13().enabled());
+
+    // 割り込み登録
+    unsafe {
+        // EXTI15_10割り込み有効化（EXTI13で割り込みが発生するので）
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::EXTI15_10);
+        // EXTI0 はピンには使わず、遅延計測用にソフトウェアで保留にする
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::EXTI0);
+    }
+}
+
+// ADC1 - channel 4
+fn config_adc(peripheral: &stm32f4::stm32f446::Peripherals) {
+    peripheral.ADC_COMMON.ccr.modify(|_, w| w.adcpre().div4()); // 90 / 4 = 22.5MHz
+    peripheral
+        .ADC1
+        .cr2
+        .modify(|_, w| w.adon().enabled().eocs().each_conversion());
+    peripheral.ADC1.smpr2.modify(|_, w| w.smp4().cycles56());
+
+    peripheral.ADC1.sqr1.modify(|_, w| w.l().bits(1)); // 1 channel だけなので1
+    peripheral
+        .ADC1
+        .sqr3
+        .modify(|_, w| unsafe { w.sq1().bits(4) }); // 変換1番目にchannel 4 を設定
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+    let mut core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    profile::init(&mut core_peripheral.DCB, &mut core_peripheral.DWT);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA4
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    peripheral.RCC.apb2enr.modify(|_, w| w.syscfgen().enabled()); // 割り込み設定用
+    peripheral.RCC.apb2enr.modify(|_, w| w.adc1en().enabled()); // ADC1
+
+    // setting GPIOA-4
+    peripheral.GPIOA.moder.modify(|_, w| w.moder4().analog()); // アナログ設定
+
+    config_adc(&peripheral);
+
+    config_exti(&peripheral);
+
+    // peripheral を グローバル変数にmove(つまり、以降peripheralの操作はグローバル変数使用必須)
+    cortex_m::interrupt::free(|cs| PERIPHERAL.borrow(cs).replace(Some(peripheral)));
+
+    loop {
+        cortex_m::asm::delay(clocks.sysclk); // 約1秒
+        profile::pend(stm32f446::Interrupt::EXTI0);
+        profile::dump_semihosting(clocks.sysclk);
+    }
+}
+
+#[interrupt]
+fn EXTI0() {
+    profile::mark_entry("EXTI0 latency");
+}
+
+#[interrupt]
+fn EXTI15_10() {
+    let _scope = profile::scope("EXTI15_10");
+    cortex_m::interrupt::free(|cs| {
+        // peripheral access
+        let peripheral = PERIPHERAL.borrow(cs).borrow();
+        let peripheral = peripheral.as_ref();
+        if let Some(peripheral) = peripheral {
+            if peripheral.EXTI.pr.read().pr13().is_not_pending() {
+                return;
+            }
+            // ADC開始と待ち（変換待ちだけの時間も別に計測）
+            let ad_value = profile::measure("ADC conversion", || {
+                peripheral.ADC1.cr2.modify(|_, w| w.swstart().start());
+                while peripheral.ADC1.sr.read().eoc().is_not_complete() {}
+                peripheral.ADC1.dr.read().data().bits() // DRレジスタリード（EOCも自動でクリア）
+            });
+            peripheral.ADC1.sr.modify(|_, w| w.strt().not_started()); // 変換開始フラグをクリア
+            let _ = ad_value;
+
+            peripheral.EXTI.pr.modify(|_, w| w.pr13().set_bit());
+        } else {
+            panic!("not found peripheral");
+        }
+    });
+}
@@ -0,0 +1,112 @@
+// USART2 (ST-Link の仮想 COM ポート) でシリアル通信
+// PC のターミナル (115200bps, 8N1) で受信した文字をそのまま返し、改行で受信した行の長さを出力する。
+// スイッチ入力ごとに、押した回数を出力する（デバッガが無くても動く）。
+// 受信エラー（オーバーラン、フレーミング、ノイズ、パリティ）があれば、その内容も出力する。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::entry;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::serial::{self, Config, Serial};
+
+static SERIAL: Mutex<RefCell<Option<Serial<stm32f446::USART2>>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA2, PA3
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    <stm32f446::USART2 as serial::Instance>::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-2, 3（USART2_TX, USART2_RX）
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder2().alternate().moder3().alternate());
+    peripheral
+        .GPIOA
+        .afrl
+        .modify(|_, w| w.afrl2().af7().afrl3().af7());
+    peripheral.GPIOA.pupdr.modify(|_, w| w.pupdr3().pull_up()); // 未接続時に RX がばたつかないように
+
+    let mut serial = Serial::new(peripheral.USART2, &clocks, &Config::default()).unwrap();
+    writeln!(serial, "\r\nhello (baud: {})\r", serial.baud()).ok();
+    cortex_m::interrupt::free(|cs| SERIAL.borrow(cs).replace(Some(serial)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::USART2);
+    }
+
+    let mut line_len = 0;
+    let mut presses = 0;
+    let mut pressed = false;
+    loop {
+        cortex_m::interrupt::free(|cs| {
+            let mut serial = SERIAL.borrow(cs).borrow_mut();
+            let serial = serial.as_mut().unwrap();
+
+            // 受信した文字をエコー
+            loop {
+                match serial.read() {
+                    Ok(b'\r') | Ok(b'\n') => {
+                        write!(serial, "\r\nline: {} bytes\r\n", line_len).ok();
+                        line_len = 0;
+                    }
+                    Ok(byte) => {
+                        serial.write_all(&[byte]);
+                        line_len += 1;
+                    }
+                    Err(nb::Error::Other(error)) => {
+                        write!(serial, "\r\nerror: {:?}\r\n", error).ok();
+                    }
+                    Err(nb::Error::WouldBlock) => break,
+                }
+            }
+
+            // スイッチ(PC13)が押されたら出力
+            let is_pressed = peripheral.GPIOC.idr.read().idr13().is_low();
+            if is_pressed && !pressed {
+                presses += 1;
+                write!(serial, "button: {}\r\n", presses).ok();
+            }
+            pressed = is_pressed;
+        });
+    }
+}
+
+#[interrupt]
+fn USART2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            serial.on_interrupt();
+        } else {
+            panic!("not found serial");
+        }
+    });
+}
@@ -0,0 +1,164 @@
+// USART2 の DMA 受信（IDLE 検出）と DMA 送信
+// PC のターミナル (115200bps, 8N1) から送ったデータを、受信が途切れるまでを1つのフレームとして受け取り、
+// フレームの長さと内容を送り返す。1バイトごとの割り込みは入らない。
+//   受信: DMA1 stream5 (USART2_RX)、送信: DMA1 stream6 (USART2_TX)
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::entry;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dma::{self, request};
+use stm32f446re_rust_example::serial::dma::SerialDma;
+use stm32f446re_rust_example::serial::{self, Config};
+
+const FRAME_LEN: usize = 64;
+
+type Uart = SerialDma<stm32f446::USART2, stm32f446::DMA1, 5, 6>;
+
+// 受信中のフレーム
+struct Frame {
+    data: [u8; FRAME_LEN],
+    len: usize,
+    // 入りきらなかったバイト数
+    dropped: usize,
+}
+
+static UART: Mutex<RefCell<Option<Uart>>> = Mutex::new(RefCell::new(None));
+static FRAME: Mutex<RefCell<Frame>> = Mutex::new(RefCell::new(Frame {
+    data: [0; FRAME_LEN],
+    len: 0,
+    dropped: 0,
+}));
+// 受信し終わったフレーム（main で送り返す）
+static RECEIVED: Mutex<RefCell<Option<Frame>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA2, PA3
+    <stm32f446::USART2 as serial::Instance>::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-2, 3（USART2_TX, USART2_RX）
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder2().alternate().moder3().alternate());
+    peripheral
+        .GPIOA
+        .afrl
+        .modify(|_, w| w.afrl2().af7().afrl3().af7());
+    peripheral.GPIOA.pupdr.modify(|_, w| w.pupdr3().pull_up());
+
+    let streams = dma::split(peripheral.DMA1, &peripheral.RCC);
+    let rx_buffer = cortex_m::singleton!(: [u8; 256] = [0; 256]).unwrap();
+    let tx_buffer = cortex_m::singleton!(: [u8; 256] = [0; 256]).unwrap();
+    let mut uart = SerialDma::new(
+        peripheral.USART2,
+        &clocks,
+        &Config::default(),
+        (streams.s5, request::Usart2Rx),
+        (streams.s6, request::Usart2Tx),
+        (rx_buffer, tx_buffer),
+    )
+    .unwrap();
+    writeln!(uart, "\r\nsend some text\r").ok();
+    cortex_m::interrupt::free(|cs| UART.borrow(cs).replace(Some(uart)));
+
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::USART2);
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA1_STREAM5);
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::DMA1_STREAM6);
+    }
+
+    loop {
+        cortex_m::interrupt::free(|cs| {
+            if let Some(frame) = RECEIVED.borrow(cs).borrow_mut().take() {
+                let mut uart = UART.borrow(cs).borrow_mut();
+                let uart = uart.as_mut().unwrap();
+                write!(uart, "frame: {} bytes", frame.len + frame.dropped).ok();
+                if frame.dropped > 0 {
+                    write!(uart, " ({} dropped)", frame.dropped).ok();
+                }
+                uart.write_all(b"\r\n> ").ok();
+                uart.write_all(&frame.data[..frame.len]).ok();
+                uart.write_all(b"\r\n").ok();
+            }
+        });
+        cortex_m::asm::wfi();
+    }
+}
+
+// USART2 の IDLE/エラーと、DMA の受信/送信の割り込みで同じ処理をする
+fn on_uart_interrupt() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(uart) = UART.borrow(cs).borrow_mut().as_mut() {
+            let mut frame = FRAME.borrow(cs).borrow_mut();
+            let result = uart.on_interrupt(|data, frame_end| {
+                let count = data.len().min(FRAME_LEN - frame.len);
+                let len = frame.len;
+                frame.data[len..len + count].copy_from_slice(&data[..count]);
+                frame.len += count;
+                frame.dropped += data.len() - count;
+                if frame_end {
+                    let done = core::mem::replace(
+                        &mut *frame,
+                        Frame {
+                            data: [0; FRAME_LEN],
+                            len: 0,
+                            dropped: 0,
+                        },
+                    );
+                    RECEIVED.borrow(cs).replace(Some(done));
+                }
+            });
+            // 受信エラーのあったフレームは捨てる
+            if result.is_err() {
+                frame.len = 0;
+                frame.dropped = 0;
+            }
+        } else {
+            panic!("not found uart");
+        }
+    });
+}
+
+#[interrupt]
+fn USART2() {
+    on_uart_interrupt();
+}
+
+#[interrupt]
+fn DMA1_STREAM5() {
+    on_uart_interrupt();
+}
+
+#[interrupt]
+fn DMA1_STREAM6() {
+    on_uart_interrupt();
+}
@@ -0,0 +1,164 @@
+// シリアルポートのコマンドシェル
+// PC のターミナル (115200bps, 8N1) から、書き込み直さずに GPIO/ADC/DAC/PWM/レジスタを操作する。
+//   gpio PC13            ユーザスイッチ B1 を読む
+//   pwm TIM2 1 25        LD2 (PA5, TIM2_CH1) を Duty 25% で点灯
+//   pwm TIM2 freq 1000   PWM を 1kHz に
+//   adc 0                PA0 (ADC1_IN0) の電圧
+//   dac 1 1650mV         PA4 (DAC_OUT1) に 1.65V を出力
+//   peek GPIOA.ODR / peek TIM2 / poke GPIOA.BSRR 0x20 / clock
+// ↑↓ で履歴、Tab でコマンドやレジスタ名を補完できる。help でコマンドの一覧。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+use cortex_m_rt::{entry, exception};
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+use stm32f4::stm32f446;
+
+// interrupt マクロ が使えるようになる
+use stm32f4::stm32f446::interrupt;
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::dac::Dac;
+use stm32f446re_rust_example::serial::{self, Config, Serial};
+use stm32f446re_rust_example::shell::builtin::{self, Board};
+use stm32f446re_rust_example::shell::{Command, CommandError, CommandResult, Shell};
+use stm32f446re_rust_example::systick::{self, TimeBase};
+
+static SERIAL: Mutex<RefCell<Option<Serial<stm32f446::USART2>>>> = Mutex::new(RefCell::new(None));
+
+// シェルの出力先（1回の書き込みごとに SERIAL を借りる）
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        cortex_m::interrupt::free(|cs| match SERIAL.borrow(cs).borrow_mut().as_mut() {
+            Some(serial) => serial.write_str(s),
+            None => Err(fmt::Error),
+        })
+    }
+}
+
+// アプリ独自のコマンド
+static COMMANDS: &[Command<Board>] = &[
+    Command {
+        name: "uptime",
+        usage: "",
+        help: "show time since reset",
+        run: uptime,
+        complete: None,
+    },
+    Command {
+        name: "reset",
+        usage: "",
+        help: "reset the MCU",
+        run: reset,
+        complete: None,
+    },
+];
+
+fn uptime(_: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    if args.len() != 1 {
+        return Err(CommandError::Usage);
+    }
+    let ms = systick::uptime_ms();
+    writeln!(out, "{}.{:03} s\r", ms / 1000, ms % 1000).ok();
+    Ok(())
+}
+
+fn reset(_: &mut Board, _: &[&str], _: &mut dyn Write) -> CommandResult {
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+    let core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let clocks = config_clock(&peripheral);
+    let _time_base = TimeBase::new(core_peripheral.SYST, &clocks, 1000).unwrap();
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled()); // PA0, PA2 ~ PA5
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpiocen().enabled()); // USER SW B1
+    peripheral.RCC.apb1enr.modify(|_, w| w.tim2en().enabled());
+    peripheral.RCC.apb1enr.modify(|_, w| w.dacen().enabled());
+    peripheral.RCC.apb2enr.modify(|_, w| w.adc1en().enabled());
+    <stm32f446::USART2 as serial::Instance>::enable_clock(&peripheral.RCC);
+
+    // setting GPIOA-2, 3（USART2_TX, USART2_RX）
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder2().alternate().moder3().alternate());
+    peripheral
+        .GPIOA
+        .afrl
+        .modify(|_, w| w.afrl2().af7().afrl3().af7());
+    peripheral.GPIOA.pupdr.modify(|_, w| w.pupdr3().pull_up());
+    // GPIOA-0（ADC1_IN0）, GPIOA-4（DAC_OUT1）はアナログ、GPIOA-5（LD2）は TIM2-ch1
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder0().analog().moder4().analog().moder5().alternate());
+    peripheral.GPIOA.afrl.modify(|_, w| w.afrl5().af1());
+
+    let mut board = Board::new(clocks);
+    board.set_adc(peripheral.ADC1, &peripheral.ADC_COMMON);
+    board.set_dac(Dac::new(peripheral.DAC));
+    board.add_pwm("TIM2", peripheral.TIM2, 10_000).unwrap();
+
+    let serial = Serial::new(peripheral.USART2, &clocks, &Config::default()).unwrap();
+    cortex_m::interrupt::free(|cs| SERIAL.borrow(cs).replace(Some(serial)));
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(stm32f446::Interrupt::USART2);
+    }
+
+    let mut shell: Shell<Board> = Shell::new("f446> ");
+    shell.register(builtin::COMMANDS).unwrap();
+    shell.register(COMMANDS).unwrap();
+    write!(Console, "\r\nstm32f446re shell (help for commands)").ok();
+    shell.start(&mut Console).ok();
+
+    loop {
+        let received =
+            cortex_m::interrupt::free(|cs| SERIAL.borrow(cs).borrow_mut().as_mut().unwrap().read());
+        match received {
+            // コマンドは割り込み許可のまま実行する（出力は Console が都度 SERIAL を借りる）
+            Ok(byte) => shell.feed(byte, &mut board, &mut Console).ok(),
+            Err(nb::Error::Other(error)) => writeln!(Console, "\r\nerror: {:?}\r", error).ok(),
+            Err(nb::Error::WouldBlock) => None,
+        };
+    }
+}
+
+#[interrupt]
+fn USART2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(serial) = SERIAL.borrow(cs).borrow_mut().as_mut() {
+            serial.on_interrupt();
+        } else {
+            panic!("not found serial");
+        }
+    });
+}
+
+#[exception]
+fn SysTick() {
+    systick::on_tick();
+}
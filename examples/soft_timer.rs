@@ -0,0 +1,104 @@
+// ソフトウェアタイマで複数の周期処理を動かす
+// SysTick(1ms) をティックとして、ハードウェアタイマを増やさずに
+// ・500ms 周期で LED(LD2) を点滅（フラグをメインループで処理）
+// ・1s 周期でカウントアップ（割り込み内のコールバックで処理）
+// ・5s 後に1回だけメッセージ出力（ワンショット）
+// を行う。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア共通の機能を提供
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::{entry, exception};
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::soft_timer::{Action, TimerWheel};
+
+// 64スロット、最大8タイマ
+static WHEEL: Mutex<RefCell<TimerWheel<64, 8>>> = Mutex::new(RefCell::new(TimerWheel::new()));
+
+static LED_FLAG: AtomicBool = AtomicBool::new(false);
+static MESSAGE_FLAG: AtomicBool = AtomicBool::new(false);
+static SECONDS: AtomicU32 = AtomicU32::new(0);
+
+fn count_up() {
+    SECONDS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+
+    // setting LD2(GPIOA-5)
+    peripheral.GPIOA.moder.modify(|_, w| w.moder5().output());
+
+    cortex_m::interrupt::free(|cs| {
+        let mut wheel = WHEEL.borrow(cs).borrow_mut();
+        wheel.periodic(500, Action::Flag(&LED_FLAG)).unwrap();
+        wheel.periodic(1000, Action::Callback(count_up)).unwrap();
+        wheel.one_shot(5000, Action::Flag(&MESSAGE_FLAG)).unwrap();
+    });
+
+    let core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    // systick interupt setting
+    let mut syst = core_peripheral.SYST;
+    // Core: 180MHz -> 1ms: 180_000 count
+    syst.set_clock_source(cortex_m::peripheral::syst::SystClkSource::Core);
+    syst.set_reload(180_000 - 1);
+    syst.clear_current();
+    syst.enable_counter();
+    syst.enable_interrupt();
+
+    loop {
+        if LED_FLAG.swap(false, Ordering::Acquire) {
+            if peripheral.GPIOA.odr.read().odr5().is_high() {
+                peripheral.GPIOA.odr.modify(|_, w| w.odr5().low());
+            } else {
+                peripheral.GPIOA.odr.modify(|_, w| w.odr5().high());
+            }
+        }
+        if MESSAGE_FLAG.swap(false, Ordering::Acquire) {
+            hprintln!(
+                "5 s elapsed ({} s counted)",
+                SECONDS.load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    cortex_m::interrupt::free(|cs| {
+        WHEEL.borrow(cs).borrow_mut().tick();
+    });
+}
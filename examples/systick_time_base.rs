@@ -0,0 +1,82 @@
+// SysTick のタイムベースで起動からの時間とディレイを使う
+// HAL で設定したクロックから 1ms ティックを作り、
+// ティックフックで 500ms ごとに LED(LD2) を点滅、メインループでは1秒ごとに起動時間を出力する。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::{entry, exception};
+use cortex_m_semihosting::hprintln;
+
+use stm32f4xx_hal as hal;
+
+use hal::{pac, prelude::*};
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use stm32f446re_rust_example::clock::Clocks;
+use stm32f446re_rust_example::systick::{self, TimeBase};
+
+static LED_TOGGLE: AtomicBool = AtomicBool::new(false);
+
+fn blink_hook(ticks: u64) {
+    if ticks.is_multiple_of(500) {
+        LED_TOGGLE.store(true, Ordering::Release);
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = pac::Peripherals::take().unwrap();
+    let core_peripheral = cortex_m::Peripherals::take().unwrap();
+
+    let rcc = peripheral.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .bypass_hse_oscillator()
+        .sysclk(180.MHz())
+        .pclk1(45.MHz()) // peripheral clock 1
+        .freeze();
+
+    let gpioa = peripheral.GPIOA.split();
+    let mut led = gpioa.pa5.into_push_pull_output(); // 出力設定 & push-pull モード
+
+    // 1kHz(1ms) ティック
+    let time_base = TimeBase::new(core_peripheral.SYST, &Clocks::from(&clocks), 1000).unwrap();
+    let mut delay = time_base.delay();
+
+    systick::add_hook(blink_hook).unwrap();
+
+    let mut next_print = 1000;
+    loop {
+        delay.delay_ms(10_u32);
+        if LED_TOGGLE.swap(false, Ordering::Acquire) {
+            led.toggle();
+        }
+        if systick::uptime_ms() >= next_print {
+            next_print += 1000;
+            hprintln!(
+                "uptime: {} ms ({} us)",
+                systick::uptime_ms(),
+                systick::uptime_us()
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    systick::on_tick();
+}
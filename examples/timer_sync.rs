@@ -0,0 +1,90 @@
+// TIM2 と TIM3 の PWM を位相を揃えてスタート
+// TIM2(マスタ)のカウント開始を TRGO で TIM3(スレーブ)に伝え、同じクロックで動き出させる。
+// PA5(LD2, TIM2-ch1) と PA6(TIM3-ch1) をオシロで見ると立ち上がりが揃っている。
+
+#![no_std]
+#![no_main]
+
+// pick a panicking behavior
+use panic_halt as _; // you can put a breakpoint on `rust_begin_unwind` to catch panics
+                     // use panic_abort as _; // requires nightly
+                     // use panic_itm as _; // logs messages over ITM; requires ITM support
+                     // use panic_semihosting as _; // logs messages to the host stderr; requires a debugger
+
+// cortex-m コア向けのスタートアップ処理を提供
+// メモリの初期化から例外テーブルのシンボル登録（リセット以外はダミーの定義）まで実施してくれる。
+use cortex_m_rt::entry;
+
+use cortex_m_semihosting::hprintln;
+
+// このデバイスクレートをuseすることで、割り込みベクタテーブルのシンボル定義が自動登録される。
+// 依存にデバイスクレート追加後、これ無しでビルドすると、cortex-m-rtの "device" features
+// 　がONになり、テーブル定義が空になるので怒られる。（OFFの時はダミーの定義入れてくれる）
+// デバイスクレートの "rt" features を外せば "device" がONにされないので回避できるが、
+// 　自前でテーブル定義する必要が出てくる。
+//　（デバイス固有のペリフェラルアクセスだけ利用したい場合用だが、あまり使う機会は無さそう）
+use stm32f4::stm32f446;
+
+use stm32f446re_rust_example::clock::config_clock;
+use stm32f446re_rust_example::timer::master_slave::{self, MasterMode, SlaveMode};
+use stm32f446re_rust_example::timer::Instance;
+
+// 1kHz の PWM を ch1 に出す（どちらのタイマも APB1 で 90MHz）
+fn config_pwm<TIM: Instance>(tim: &TIM, duty: u32) {
+    let regs = tim.regs();
+    regs.ccmr1_output()
+        .modify(|_, w| w.oc1pe().enabled().oc1m().pwm_mode1()); // CCR1 プリロード有効化 & PWM mode 1
+    regs.cr1.modify(|_, w| w.arpe().enabled()); // ARR 自動プリロード有効化
+    regs.psc.write(|w| unsafe { w.bits(90 - 1) }); // 1MHz でカウント
+    regs.arr.write(|w| unsafe { w.bits(1000 - 1) }); // 1kHz
+    regs.ccr1.write(|w| unsafe { w.bits(duty) });
+    regs.egr.write(|w| w.ug().update()); // 更新生成（プリロード値を初期化、カウンタも0になる）
+    regs.ccer.modify(|_, w| w.cc1e().set_bit()); // OC出力有効化
+}
+
+#[entry]
+fn main() -> ! {
+    let peripheral = stm32f446::Peripherals::take().unwrap();
+
+    config_clock(&peripheral);
+
+    // 各機能へのクロック入力設定
+    peripheral.RCC.ahb1enr.modify(|_, w| w.gpioaen().enabled());
+    stm32f446::TIM2::enable_clock(&peripheral.RCC);
+    stm32f446::TIM3::enable_clock(&peripheral.RCC);
+
+    // PA5: TIM2-ch1(AF1), PA6: TIM3-ch1(AF2)
+    peripheral
+        .GPIOA
+        .moder
+        .modify(|_, w| w.moder5().alternate().moder6().alternate());
+    peripheral
+        .GPIOA
+        .afrl
+        .modify(|_, w| w.afrl5().af1().afrl6().af2());
+
+    config_pwm(&peripheral.TIM2, 250); // Duty 25%
+    config_pwm(&peripheral.TIM3, 750); // Duty 75%
+
+    // TIM2: CEN を TRGO に出す、TIM3: ITR1(TIM2) の立ち上がりでカウント開始
+    master_slave::set_master_mode(&peripheral.TIM2, MasterMode::Enable, true);
+    master_slave::set_slave_mode(&peripheral.TIM3, &peripheral.TIM2, SlaveMode::Trigger).unwrap();
+
+    // F446 では TIM8 の TRGO は TIM3 の ITR に繋がっていないのでエラーになる
+    if master_slave::set_slave_mode(&peripheral.TIM3, &peripheral.TIM8, SlaveMode::Trigger).is_err()
+    {
+        hprintln!("TIM8 -> TIM3 is not routed").unwrap();
+    }
+
+    // マスタだけ開始すれば、スレーブも同時に開始される
+    peripheral.TIM2.cr1.modify(|_, w| w.cen().enabled());
+
+    hprintln!(
+        "TIM2 CNT: {}, TIM3 CNT: {}",
+        peripheral.TIM2.cnt.read().bits(),
+        peripheral.TIM3.cnt.read().bits()
+    )
+    .unwrap();
+
+    loop {}
+}
@@ -0,0 +1,346 @@
+// ADC(ADC1 ~ ADC3)の共通処理
+// 入力ピンのアナログ設定（GPIOx.MODER = analog）は呼び出し側で行うこと。
+
+use stm32f4::stm32f446;
+use stm32f446::adc1;
+
+use crate::clock::Clocks;
+use crate::dma::request;
+
+pub mod injected;
+pub mod internal;
+pub mod multi;
+pub mod scan;
+pub mod trigger;
+pub mod watchdog;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // チャンネル数が範囲外（レギュラーは1 ~ 16）、またはチャンネル番号が18を超えている
+    InvalidSequence,
+    // バッファ長がシーケンス長の偶数倍になっていない
+    InvalidBuffer,
+    // DMA が間に合わず変換結果を取りこぼした(ADC OVR)
+    Overrun,
+    // DMA の転送エラー
+    Transfer,
+    // そのタイマのイベントは ADC の外部トリガに選べない
+    NoTrigger,
+    // サンプリング周波数がタイマで作れない
+    InvalidRate,
+    // アナログウォッチドッグのしきい値が不正（下限 > 上限）
+    InvalidThreshold,
+    // マルチ ADC モードの組み合わせが不正（ADC の数、DMA モード、DELAY）
+    InvalidMode,
+    // 信号源のインピーダンスが高すぎて、最長のサンプリング時間でも足りない
+    SourceImpedance,
+}
+
+// サンプリング時間 (SMPx)
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum SampleTime {
+    Cycles3 = 0b000,
+    Cycles15 = 0b001,
+    Cycles28 = 0b010,
+    Cycles56 = 0b011,
+    Cycles84 = 0b100,
+    Cycles112 = 0b101,
+    Cycles144 = 0b110,
+    Cycles480 = 0b111,
+}
+
+// サンプリング時間の計算に使う ADC の内部定数（データシート ADC characteristics の R_ADC, C_ADC）
+const R_ADC_OHMS: f32 = 6_000.0;
+const C_ADC_FARADS: f32 = 4.0e-12;
+
+impl SampleTime {
+    const ALL: [SampleTime; 8] = [
+        SampleTime::Cycles3,
+        SampleTime::Cycles15,
+        SampleTime::Cycles28,
+        SampleTime::Cycles56,
+        SampleTime::Cycles84,
+        SampleTime::Cycles112,
+        SampleTime::Cycles144,
+        SampleTime::Cycles480,
+    ];
+
+    pub const fn cycles(self) -> u32 {
+        match self {
+            SampleTime::Cycles3 => 3,
+            SampleTime::Cycles15 => 15,
+            SampleTime::Cycles28 => 28,
+            SampleTime::Cycles56 => 56,
+            SampleTime::Cycles84 => 84,
+            SampleTime::Cycles112 => 112,
+            SampleTime::Cycles144 => 144,
+            SampleTime::Cycles480 => 480,
+        }
+    }
+
+    // 信号源のインピーダンス[Ω]から、必要な最短のサンプリング時間を求める
+    // データシートの式 R_AIN < (k - 0.5) / (f_ADC * C_ADC * ln(2^(N + 2))) - R_ADC を k について解いたもの。
+    // （誤差 1/4 LSB 以内でサンプリング容量が充電される時間）
+    pub fn for_source(
+        source_ohms: u32,
+        adc_clock: u32,
+        resolution: Resolution,
+    ) -> Result<SampleTime, Error> {
+        let ln = (resolution.bits() + 2) as f32 * core::f32::consts::LN_2;
+        let cycles = (source_ohms as f32 + R_ADC_OHMS) * adc_clock as f32 * C_ADC_FARADS * ln + 0.5;
+        SampleTime::ALL
+            .iter()
+            .copied()
+            .find(|st| st.cycles() as f32 >= cycles)
+            .ok_or(Error::SourceImpedance)
+    }
+}
+
+// 分解能 (CR1.RES)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Bits12 = 0b00,
+    Bits10 = 0b01,
+    Bits8 = 0b10,
+    Bits6 = 0b11,
+}
+
+impl Resolution {
+    pub const fn bits(self) -> u32 {
+        match self {
+            Resolution::Bits12 => 12,
+            Resolution::Bits10 => 10,
+            Resolution::Bits8 => 8,
+            Resolution::Bits6 => 6,
+        }
+    }
+
+    // 変換結果の最大値（フルスケール）
+    pub const fn max_code(self) -> u32 {
+        match self {
+            Resolution::Bits12 => 4095,
+            Resolution::Bits10 => 1023,
+            Resolution::Bits8 => 255,
+            Resolution::Bits6 => 63,
+        }
+    }
+}
+
+// ADC クロックの分周 (ADC_CCR.ADCPRE、PCLK2 を分周する。最大36MHz)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prescaler {
+    Div2 = 0b00,
+    Div4 = 0b01,
+    Div6 = 0b10,
+    Div8 = 0b11,
+}
+
+impl Prescaler {
+    pub const fn divider(self) -> u32 {
+        match self {
+            Prescaler::Div2 => 2,
+            Prescaler::Div4 => 4,
+            Prescaler::Div6 => 6,
+            Prescaler::Div8 => 8,
+        }
+    }
+
+    // ADC クロック[Hz]
+    pub fn adc_clock(self, clocks: &Clocks) -> u32 {
+        clocks.pclk2 / self.divider()
+    }
+}
+
+// レギュラー変換の外部トリガ (CR2.EXTSEL)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExternalTrigger {
+    Tim1Cc1 = 0b0000,
+    Tim1Cc2 = 0b0001,
+    Tim1Cc3 = 0b0010,
+    Tim2Cc2 = 0b0011,
+    Tim2Cc3 = 0b0100,
+    Tim2Cc4 = 0b0101,
+    Tim2Trgo = 0b0110,
+    Tim3Cc1 = 0b0111,
+    Tim3Trgo = 0b1000,
+    Tim4Cc4 = 0b1001,
+    Tim5Cc1 = 0b1010,
+    Tim5Cc2 = 0b1011,
+    Tim5Cc3 = 0b1100,
+    Tim8Cc1 = 0b1101,
+    Tim8Trgo = 0b1110,
+    Exti11 = 0b1111,
+}
+
+// 外部トリガの有効エッジ (CR2.EXTEN)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEdge {
+    Rising = 0b01,
+    Falling = 0b10,
+    Both = 0b11,
+}
+
+// 変換の開始方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Conversion {
+    // ソフトウェアで1回開始した後は連続変換
+    Continuous,
+    // 外部トリガごとにシーケンスを1回変換
+    Triggered(ExternalTrigger, TriggerEdge),
+}
+
+pub trait Instance {
+    // DMA2 の要求（使えるストリームとチャンネルは dma::request の割り当て表で決まる）
+    type DmaRequest;
+
+    fn ptr() -> *const adc1::RegisterBlock;
+
+    fn regs(&self) -> &adc1::RegisterBlock {
+        unsafe { &*Self::ptr() }
+    }
+
+    // RCC からクロック供給
+    fn enable_clock(rcc: &stm32f446::RCC);
+}
+
+macro_rules! instance {
+    ($ADC:ident, $adcen:ident, $request:ident) => {
+        impl Instance for stm32f446::$ADC {
+            type DmaRequest = request::$request;
+
+            fn ptr() -> *const adc1::RegisterBlock {
+                stm32f446::$ADC::ptr() as *const adc1::RegisterBlock
+            }
+
+            fn enable_clock(rcc: &stm32f446::RCC) {
+                rcc.apb2enr.modify(|_, w| w.$adcen().enabled());
+            }
+        }
+    };
+}
+
+instance!(ADC1, adc1en, Adc1);
+instance!(ADC2, adc2en, Adc2);
+instance!(ADC3, adc3en, Adc3);
+
+// 全ADC共通のクロック分周を設定
+pub fn set_prescaler(common: &stm32f446::ADC_COMMON, prescaler: Prescaler) {
+    common
+        .ccr
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 16)) | ((prescaler as u32) << 16)) });
+}
+
+// チャンネルごとのサンプリング時間を設定（ch 0 ~ 9 は SMPR2、ch 10 ~ 18 は SMPR1）
+pub fn set_sample_time(adc: &adc1::RegisterBlock, channel: u8, sample_time: SampleTime) {
+    let value = sample_time as u32;
+    if channel < 10 {
+        let shift = channel as u32 * 3;
+        adc.smpr2
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0b111 << shift)) | (value << shift)) });
+    } else {
+        let shift = (channel as u32 - 10) * 3;
+        adc.smpr1
+            .modify(|r, w| unsafe { w.bits((r.bits() & !(0b111 << shift)) | (value << shift)) });
+    }
+}
+
+pub fn set_resolution(adc: &adc1::RegisterBlock, resolution: Resolution) {
+    adc.cr1
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 24)) | ((resolution as u32) << 24)) });
+}
+
+// レギュラーシーケンスを設定（SQ1 から順に channels を並べる）
+pub fn set_regular_sequence(adc: &adc1::RegisterBlock, channels: &[u8]) -> Result<(), Error> {
+    if channels.is_empty() || channels.len() > 16 || channels.iter().any(|&ch| ch > 18) {
+        return Err(Error::InvalidSequence);
+    }
+    let mut sqr = [0u32; 3]; // SQR3, SQR2, SQR1 の順
+    for (i, &channel) in channels.iter().enumerate() {
+        sqr[i / 6] |= (channel as u32) << ((i % 6) * 5);
+    }
+    // L は変換数 - 1
+    sqr[2] |= (channels.len() as u32 - 1) << 20;
+    adc.sqr3.write(|w| unsafe { w.bits(sqr[0]) });
+    adc.sqr2.write(|w| unsafe { w.bits(sqr[1]) });
+    adc.sqr1.write(|w| unsafe { w.bits(sqr[2]) });
+    Ok(())
+}
+
+// 変換の開始方法を設定
+pub fn set_conversion(adc: &adc1::RegisterBlock, conversion: Conversion) {
+    adc.cr2.modify(|r, w| unsafe {
+        let bits = r.bits() & !((0b11 << 28) | (0b1111 << 24) | (1 << 1));
+        match conversion {
+            Conversion::Continuous => w.bits(bits | (1 << 1)), // CONT
+            Conversion::Triggered(trigger, edge) => {
+                w.bits(bits | ((edge as u32) << 28) | ((trigger as u32) << 24))
+            }
+        }
+    });
+}
+
+// ADC ON（安定するまで tSTAB = 3us 待つ）
+pub fn power_on(adc: &adc1::RegisterBlock) {
+    if adc.cr2.read().adon().bit_is_clear() {
+        adc.cr2.modify(|_, w| w.adon().set_bit());
+        cortex_m::asm::delay(180 * 3); // 180MHz で 3us
+    }
+}
+
+// 1チャンネルだけをソフトウェア開始で1回変換し、結果を待って返す
+// レギュラーシーケンス、CONT、外部トリガは上書きされる。
+pub fn convert(adc: &adc1::RegisterBlock, channel: u8) -> u16 {
+    adc.cr2
+        .modify(|r, w| unsafe { w.bits(r.bits() & !((0b11 << 28) | (1 << 1))) }); // 外部トリガ, CONT を無効
+    adc.sqr1.write(|w| unsafe { w.bits(0) }); // L = 0 (1変換)
+    adc.sqr3.write(|w| unsafe { w.bits(channel as u32) });
+    power_on(adc);
+    adc.cr2.modify(|_, w| w.swstart().set_bit());
+    while adc.sr.read().eoc().bit_is_clear() {}
+    let value = adc.dr.read().bits() as u16; // DRレジスタリード（EOCも自動でクリア）
+    adc.sr.modify(|_, w| w.strt().clear_bit()); // 変換開始フラグをクリア
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADC_CLOCK: u32 = 90_000_000 / 4;
+
+    #[test]
+    fn sample_time_for_source() {
+        // 22.5MHz, 12bit で 10kΩ なら約14.5サイクル
+        assert_eq!(
+            SampleTime::for_source(10_000, ADC_CLOCK, Resolution::Bits12),
+            Ok(SampleTime::Cycles15)
+        );
+        assert_eq!(
+            SampleTime::for_source(50_000, ADC_CLOCK, Resolution::Bits12),
+            Ok(SampleTime::Cycles56)
+        );
+        // 分解能が低いほど短くてよい
+        assert_eq!(
+            SampleTime::for_source(0, 12_000_000, Resolution::Bits6),
+            Ok(SampleTime::Cycles3)
+        );
+        assert_eq!(
+            SampleTime::for_source(0, 12_000_000, Resolution::Bits12),
+            Ok(SampleTime::Cycles15)
+        );
+    }
+
+    #[test]
+    fn sample_time_saturates_at_480() {
+        // 144 サイクルでは足りないものは 480 サイクル
+        assert_eq!(
+            SampleTime::for_source(200_000, ADC_CLOCK, Resolution::Bits12),
+            Ok(SampleTime::Cycles480)
+        );
+        // 480 サイクルでも足りない
+        assert_eq!(
+            SampleTime::for_source(1_000_000, ADC_CLOCK, Resolution::Bits12),
+            Err(Error::SourceImpedance)
+        );
+    }
+}
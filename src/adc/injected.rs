@@ -0,0 +1,148 @@
+// ADC のインジェクテッドグループ（最大4チャンネル）
+// レギュラー変換(スキャン + DMA)の途中でも、トリガが来ると割り込んで先に変換される。
+// 結果は JDR1 ~ JDR4 に JOFRx のオフセットを引いた符号付きの値で入り、JEOC 割り込みで読み出す。
+//
+// レギュラー側のドライバ(ScanDma)が ADC を所有していても使えるように、
+// ここでは ADC を所有せず、作成時に参照を受け取るだけにしている。
+
+use core::marker::PhantomData;
+
+use super::{Error, Instance, SampleTime, TriggerEdge};
+
+// インジェクテッド変換の外部トリガ (CR2.JEXTSEL)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InjectedTrigger {
+    Tim1Cc4 = 0b0000,
+    Tim1Trgo = 0b0001,
+    Tim2Cc1 = 0b0010,
+    Tim2Trgo = 0b0011,
+    Tim3Cc2 = 0b0100,
+    Tim3Cc4 = 0b0101,
+    Tim4Cc1 = 0b0110,
+    Tim4Cc2 = 0b0111,
+    Tim4Cc3 = 0b1000,
+    Tim4Trgo = 0b1001,
+    Tim5Cc4 = 0b1010,
+    Tim5Trgo = 0b1011,
+    Tim8Cc2 = 0b1100,
+    Tim8Cc3 = 0b1101,
+    Tim8Cc4 = 0b1110,
+    Exti15 = 0b1111,
+}
+
+// インジェクテッドチャンネルの設定
+#[derive(Clone, Copy, Debug)]
+pub struct InjectedChannel {
+    pub channel: u8,
+    pub sample_time: SampleTime,
+    // 変換結果から引く値(JOFRx, 0 ~ 4095)
+    pub offset: u16,
+}
+
+// 1回分の変換結果（変換順）
+#[derive(Clone, Copy, Debug)]
+pub struct InjectedResult {
+    values: [i16; 4],
+    len: usize,
+}
+
+impl InjectedResult {
+    pub fn as_slice(&self) -> &[i16] {
+        &self.values[..self.len]
+    }
+}
+
+pub struct Injected<ADC> {
+    len: usize,
+    _adc: PhantomData<ADC>,
+}
+
+impl<ADC: Instance> Injected<ADC> {
+    // trigger が None の場合は start() でソフトウェア開始
+    pub fn new(
+        adc: &ADC,
+        channels: &[InjectedChannel],
+        trigger: Option<(InjectedTrigger, TriggerEdge)>,
+    ) -> Result<Self, Error> {
+        let len = channels.len();
+        if len == 0 || len > 4 || channels.iter().any(|c| c.channel > 18 || c.offset > 0xFFF) {
+            return Err(Error::InvalidSequence);
+        }
+
+        let regs = adc.regs();
+        // JL < 3 の場合は JSQ4 側から詰めて使う（JL = 1 なら JSQ3, JSQ4 の順に変換）
+        let mut jsqr = (len as u32 - 1) << 20;
+        for (i, c) in channels.iter().enumerate() {
+            let slot = 4 - len + i;
+            jsqr |= (c.channel as u32) << (slot * 5);
+            super::set_sample_time(regs, c.channel, c.sample_time);
+        }
+        regs.jsqr.write(|w| unsafe { w.bits(jsqr) });
+
+        // オフセットは変換順(ランク)ごと
+        let offsets = [&regs.jofr1, &regs.jofr2, &regs.jofr3, &regs.jofr4];
+        for (jofr, c) in offsets.iter().zip(channels) {
+            jofr.write(|w| unsafe { w.bits(c.offset as u32) });
+        }
+
+        regs.cr1.modify(|_, w| w.scan().set_bit()); // 複数チャンネルならスキャンモードが必要
+        regs.cr2.modify(|r, w| unsafe {
+            let bits = r.bits() & !((0b11 << 20) | (0b1111 << 16));
+            match trigger {
+                Some((trigger, edge)) => {
+                    w.bits(bits | ((edge as u32) << 20) | ((trigger as u32) << 16))
+                }
+                None => w.bits(bits),
+            }
+        });
+
+        Ok(Injected {
+            len,
+            _adc: PhantomData,
+        })
+    }
+
+    fn regs(&self) -> &super::adc1::RegisterBlock {
+        unsafe { &*ADC::ptr() }
+    }
+
+    // JEOC 割り込みを有効化（NVIC の ADC 割り込み許可は呼び出し側で行うこと）
+    pub fn listen(&mut self) {
+        self.regs().cr1.modify(|_, w| w.jeocie().set_bit());
+    }
+
+    pub fn unlisten(&mut self) {
+        self.regs().cr1.modify(|_, w| w.jeocie().clear_bit());
+    }
+
+    // ソフトウェアで変換開始（ADC ON が必要）
+    pub fn start(&mut self) {
+        super::power_on(self.regs());
+        self.regs().cr2.modify(|_, w| w.jswstart().set_bit());
+    }
+
+    // ADC 割り込みから呼ぶ。JEOC が立っていれば結果を返す。
+    pub fn on_interrupt(&mut self) -> Option<InjectedResult> {
+        let regs = self.regs();
+        if regs.sr.read().jeoc().bit_is_clear() {
+            return None;
+        }
+        let data = [
+            regs.jdr1.read().bits(),
+            regs.jdr2.read().bits(),
+            regs.jdr3.read().bits(),
+            regs.jdr4.read().bits(),
+        ];
+        // JEOC(bit2) と JSTRT(bit3) をクリア（0 書き込みでクリア、1 は影響なし）
+        regs.sr.write(|w| unsafe { w.bits(!((1 << 2) | (1 << 3))) });
+
+        let mut values = [0; 4];
+        for (value, &data) in values.iter_mut().zip(data.iter()).take(self.len) {
+            *value = data as u16 as i16; // 右寄せなら符号拡張されている
+        }
+        Some(InjectedResult {
+            values,
+            len: self.len,
+        })
+    }
+}
@@ -0,0 +1,246 @@
+// 内部チャンネル（温度センサ、VREFINT、VBAT）と工場出荷時のキャリブレーション値を使った換算
+// VREFINT を測って実際の VDDA を求めれば、任意のチャンネルの値を mV に換算できる。
+// （VDDA = 3.3V 固定として計算すると、電源電圧のずれがそのまま誤差になる）
+//
+// 内部チャンネルは ADC1 にしかつながっていない。
+//   ch17: VREFINT
+//   ch18: 温度センサ / VBAT（VBATE が優先される。VBAT は内部で 1/4 に分圧されている）
+// 温度センサは 10us 以上のサンプリング時間が必要なので、内部チャンネルは 480 サイクルで変換する。
+//
+// 換算の計算はレジスタに触らない関数に分けてあるので、ホスト側でも確認できる。
+
+use stm32f4::stm32f446;
+
+use super::{Instance, Resolution, SampleTime};
+
+pub const VREFINT_CHANNEL: u8 = 17;
+pub const TEMPERATURE_CHANNEL: u8 = 18;
+pub const VBAT_CHANNEL: u8 = 18;
+
+// キャリブレーション値の格納アドレス（システムメモリ）
+const VREFINT_CAL_ADDR: usize = 0x1FFF_7A2A;
+const TS_CAL1_ADDR: usize = 0x1FFF_7A2C;
+const TS_CAL2_ADDR: usize = 0x1FFF_7A2E;
+
+// キャリブレーション値を測定した条件
+pub const CAL_VDDA_MV: u32 = 3300;
+pub const TS_CAL1_TEMP: i32 = 30;
+pub const TS_CAL2_TEMP: i32 = 110;
+
+// VBAT の内部分圧比
+const VBAT_DIVIDER: u32 = 4;
+
+// ADC_CCR のビット位置
+const TSVREFE: u32 = 1 << 23;
+const VBATE: u32 = 1 << 22;
+
+// 工場出荷時のキャリブレーション値（VDDA = 3.3V, 12bit で測定した生の値）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    // 30℃ での VREFINT
+    pub vrefint: u16,
+    // 30℃ での温度センサ
+    pub ts_cal1: u16,
+    // 110℃ での温度センサ
+    pub ts_cal2: u16,
+}
+
+impl Calibration {
+    // システムメモリから読み出す
+    pub fn read() -> Self {
+        unsafe {
+            Calibration {
+                vrefint: core::ptr::read_volatile(VREFINT_CAL_ADDR as *const u16),
+                ts_cal1: core::ptr::read_volatile(TS_CAL1_ADDR as *const u16),
+                ts_cal2: core::ptr::read_volatile(TS_CAL2_ADDR as *const u16),
+            }
+        }
+    }
+}
+
+// VREFINT の変換結果(12bit)から実際の VDDA[mV] を求める
+pub fn vdda_mv(vrefint_cal: u16, vrefint_raw: u16) -> u32 {
+    if vrefint_raw == 0 {
+        return 0;
+    }
+    (CAL_VDDA_MV * vrefint_cal as u32 + vrefint_raw as u32 / 2) / vrefint_raw as u32
+}
+
+// 変換結果を mV に換算する
+pub fn to_mv(raw: u16, vdda_mv: u32, resolution: Resolution) -> u32 {
+    let max = resolution.max_code();
+    (raw as u32 * vdda_mv + max / 2) / max
+}
+
+// mV を変換結果の値に換算する（to_mv の逆、フルスケールで頭打ち）
+pub fn from_mv(mv: u32, vdda_mv: u32, resolution: Resolution) -> u16 {
+    if vdda_mv == 0 {
+        return 0;
+    }
+    let max = resolution.max_code();
+    ((mv * max + vdda_mv / 2) / vdda_mv).min(max) as u16
+}
+
+// 温度センサの変換結果(12bit)から温度[℃]を求める
+// キャリブレーション値は VDDA = 3.3V での値なので、変換結果をその条件に合わせてから直線補間する。
+pub fn temperature(raw: u16, vdda_mv: u32, cal: &Calibration) -> f32 {
+    let raw = raw as f32 * vdda_mv as f32 / CAL_VDDA_MV as f32;
+    let slope = (TS_CAL2_TEMP - TS_CAL1_TEMP) as f32 / (cal.ts_cal2 as f32 - cal.ts_cal1 as f32);
+    (raw - cal.ts_cal1 as f32) * slope + TS_CAL1_TEMP as f32
+}
+
+// VBAT チャンネルの変換結果(12bit)から VBAT[mV] を求める
+pub fn vbat_mv(raw: u16, vdda_mv: u32) -> u32 {
+    to_mv(raw, vdda_mv, Resolution::Bits12) * VBAT_DIVIDER
+}
+
+// ADC1 で内部チャンネルと外部チャンネルを1回ずつ変換して換算する
+// 分解能は 12bit 固定。ADC のクロック供給と分周設定は呼び出し側で済ませておくこと。
+pub struct Analog {
+    adc: stm32f446::ADC1,
+    cal: Calibration,
+    vdda_mv: u32,
+}
+
+impl Analog {
+    pub fn new(adc: stm32f446::ADC1, common: &stm32f446::ADC_COMMON) -> Self {
+        let regs = adc.regs();
+        super::set_resolution(regs, Resolution::Bits12);
+        super::set_sample_time(regs, VREFINT_CHANNEL, SampleTime::Cycles480);
+        super::set_sample_time(regs, TEMPERATURE_CHANNEL, SampleTime::Cycles480);
+        regs.cr1.modify(|_, w| w.scan().clear_bit());
+        // 温度センサと VREFINT を有効化（起動に最大10us）
+        common
+            .ccr
+            .modify(|r, w| unsafe { w.bits((r.bits() & !VBATE) | TSVREFE) });
+        cortex_m::asm::delay(180 * 10);
+
+        let mut analog = Analog {
+            adc,
+            cal: Calibration::read(),
+            vdda_mv: CAL_VDDA_MV,
+        };
+        analog.measure_vdda();
+        analog
+    }
+
+    fn common(&self) -> &stm32f446::adc_common::RegisterBlock {
+        unsafe { &*stm32f446::ADC_COMMON::ptr() }
+    }
+
+    pub fn calibration(&self) -> &Calibration {
+        &self.cal
+    }
+
+    // チャンネルを1回変換して生の値を返す（外部チャンネルのサンプリング時間は呼び出し側で設定）
+    pub fn read_raw(&mut self, channel: u8) -> u16 {
+        super::convert(self.adc.regs(), channel)
+    }
+
+    // VREFINT を測り直して VDDA[mV] を更新する
+    pub fn measure_vdda(&mut self) -> u32 {
+        let raw = self.read_raw(VREFINT_CHANNEL);
+        self.vdda_mv = vdda_mv(self.cal.vrefint, raw);
+        self.vdda_mv
+    }
+
+    // 直近に測った VDDA[mV]
+    pub fn vdda(&self) -> u32 {
+        self.vdda_mv
+    }
+
+    // チャンネルを変換して mV で返す
+    pub fn read_mv(&mut self, channel: u8) -> u32 {
+        let raw = self.read_raw(channel);
+        to_mv(raw, self.vdda_mv, Resolution::Bits12)
+    }
+
+    // チップ内部の温度[℃]
+    pub fn temperature(&mut self) -> f32 {
+        let raw = self.read_raw(TEMPERATURE_CHANNEL);
+        temperature(raw, self.vdda_mv, &self.cal)
+    }
+
+    // VBAT[mV]
+    // VBATE を入れたままだとバッテリを消費し、温度センサも読めないので、測定後に無効化する。
+    pub fn vbat(&mut self) -> u32 {
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() | VBATE) });
+        let raw = self.read_raw(VBAT_CHANNEL);
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !VBATE) });
+        vbat_mv(raw, self.vdda_mv)
+    }
+
+    // 内部チャンネルを無効化して ADC1 を返す
+    pub fn release(self) -> stm32f446::ADC1 {
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(TSVREFE | VBATE)) });
+        self.adc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // データシートの代表値に近いキャリブレーション値
+    const CAL: Calibration = Calibration {
+        vrefint: 1500,
+        ts_cal1: 940,
+        ts_cal2: 1200,
+    };
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.05,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn vdda_from_vrefint() {
+        // 校正時と同じ値なら 3.3V
+        assert_eq!(vdda_mv(CAL.vrefint, CAL.vrefint), 3300);
+        // VDDA = 3.0V なら VREFINT の変換結果は 3.3/3.0 倍になる
+        assert_eq!(vdda_mv(CAL.vrefint, 1650), 3000);
+        assert_eq!(vdda_mv(CAL.vrefint, 0), 0);
+    }
+
+    #[test]
+    fn temperature_at_calibration_points() {
+        assert_near(temperature(CAL.ts_cal1, 3300, &CAL), 30.0);
+        assert_near(temperature(CAL.ts_cal2, 3300, &CAL), 110.0);
+        assert_near(temperature(1070, 3300, &CAL), 70.0);
+        // VDDA = 3.0V では同じ温度でも変換結果が 3.3/3.0 倍になる
+        assert_near(temperature(1034, 3000, &CAL), 30.0);
+        assert_near(temperature(1320, 3000, &CAL), 110.0);
+    }
+
+    #[test]
+    fn millivolt_conversion() {
+        assert_eq!(to_mv(0, 3300, Resolution::Bits12), 0);
+        assert_eq!(to_mv(4095, 3300, Resolution::Bits12), 3300);
+        assert_eq!(to_mv(2048, 3300, Resolution::Bits12), 1650);
+        assert_eq!(to_mv(255, 3000, Resolution::Bits8), 3000);
+
+        assert_eq!(from_mv(3300, 3300, Resolution::Bits12), 4095);
+        assert_eq!(from_mv(1650, 3300, Resolution::Bits12), 2048);
+        // VDDA を超える値はフルスケールで頭打ち
+        assert_eq!(from_mv(5000, 3300, Resolution::Bits12), 4095);
+        assert_eq!(from_mv(1000, 0, Resolution::Bits12), 0);
+    }
+
+    #[test]
+    fn vbat_is_divided_by_four() {
+        // VBAT = 3.0V は 750mV として変換される
+        assert_eq!(vbat_mv(931, 3300), 3000);
+        assert_eq!(vbat_mv(4095, 3300), 13200);
+        assert_eq!(vbat_mv(0, 3300), 0);
+    }
+}
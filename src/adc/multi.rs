@@ -0,0 +1,428 @@
+// デュアル/トリプル ADC モード（ADC1 がマスター、ADC2/ADC3 がスレーブ）
+// ・同時変換(Simultaneous): 各 ADC が別々のシーケンスを同じタイミングで変換する（複数チャンネルの同時サンプリング）
+// ・インターリーブ(Interleaved): 全 ADC が同じ1チャンネルを DELAY ずつずらして変換する（1チャンネルを高速サンプリング）
+//   ADCCLK 36MHz, 12bit なら DELAY 5 サイクルのトリプルで約7MSPS（このリポジトリの設定 22.5MHz では約4.5MSPS）
+//
+// 変換結果は ADC_CDR から ADC1 のストリーム(DMA2 stream 0/4, channel 0)で循環バッファへ転送する。
+//   DMA mode 1: 1回の要求で 16bit を1つ（ADC1, ADC2, ADC3, ADC1, ... の順）
+//   DMA mode 2: 1回の要求で 16bit を2つ（32bit）。並び順は mode 1 と同じになる
+//   DMA mode 3: 6/8bit の結果を 16bit に2つ詰める（下位バイトが先）。インターリーブ専用
+// どのモードでもバッファの並びは「ランクごとに ADC1, ADC2, (ADC3)」になるので、
+// チャンネルごとの値は samples() や deinterleave() で取り出す。
+// インターリーブ（シーケンス長1）の場合は、バッファの並びがそのまま時間順になっている。
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use stm32f4::stm32f446;
+use stm32f446::{adc1, DMA2};
+
+use super::scan::Half;
+use super::{Conversion, Error, Instance, Resolution, SampleTime};
+use crate::dma::request::{Adc1, Request};
+use crate::dma::{self, FifoThreshold, Priority, Stream};
+
+// ADC_CSR の OVR1 ~ OVR3
+const OVR_MASK: u32 = (1 << 5) | (1 << 13) | (1 << 21);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    DualSimultaneous,
+    DualInterleaved,
+    TripleSimultaneous,
+    TripleInterleaved,
+}
+
+impl Mode {
+    // ADC_CCR.MULTI
+    const fn bits(self) -> u32 {
+        match self {
+            Mode::DualSimultaneous => 0b00110,
+            Mode::DualInterleaved => 0b00111,
+            Mode::TripleSimultaneous => 0b10110,
+            Mode::TripleInterleaved => 0b10111,
+        }
+    }
+
+    // 使う ADC の数
+    pub const fn adcs(self) -> usize {
+        match self {
+            Mode::DualSimultaneous | Mode::DualInterleaved => 2,
+            Mode::TripleSimultaneous | Mode::TripleInterleaved => 3,
+        }
+    }
+
+    pub const fn is_interleaved(self) -> bool {
+        matches!(self, Mode::DualInterleaved | Mode::TripleInterleaved)
+    }
+}
+
+// ADC_CCR.DMA
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DmaMode {
+    Mode1 = 0b01,
+    Mode2 = 0b10,
+    Mode3 = 0b11,
+}
+
+pub struct MultiConfig<'a> {
+    pub mode: Mode,
+    pub dma_mode: DmaMode,
+    // ADC ごとの (チャンネル番号, サンプリング時間) のシーケンス（ADC1, ADC2, ADC3 の順）
+    // 全 ADC で同じ長さにすること。インターリーブでは全 ADC で同じ1チャンネルにする。
+    pub sequences: &'a [&'a [(u8, SampleTime)]],
+    pub resolution: Resolution,
+    // ADC1 の変換開始方法（スレーブは ADC1 に合わせて変換される）
+    pub conversion: Conversion,
+    // インターリーブでの変換の間隔[ADCCLK]（5 ~ 20）
+    pub delay: u8,
+}
+
+pub struct MultiDma<const S: usize> {
+    adc1: stm32f446::ADC1,
+    adc2: stm32f446::ADC2,
+    adc3: Option<stm32f446::ADC3>,
+    mode: Mode,
+    dma_mode: DmaMode,
+    stream: Stream<DMA2, S>,
+    sequence_len: usize,
+    buffer: &'static mut [u16],
+}
+
+impl<const S: usize> MultiDma<S>
+where
+    Adc1: Request<DMA2, S>,
+{
+    // dma は ADC1 の DMA ストリームと要求（DMA2 stream 0 か 4 と dma::request::Adc1）
+    // トリプルモードの場合は adc3 が必要
+    pub fn new(
+        adc1: stm32f446::ADC1,
+        adc2: stm32f446::ADC2,
+        adc3: Option<stm32f446::ADC3>,
+        dma: (Stream<DMA2, S>, Adc1),
+        config: &MultiConfig,
+        buffer: &'static mut [u16],
+    ) -> Result<Self, Error> {
+        let (stream, _) = dma;
+        let mode = config.mode;
+        let adcs = mode.adcs();
+        if config.sequences.len() != adcs || (adcs == 3 && adc3.is_none()) {
+            return Err(Error::InvalidMode);
+        }
+        // DMA mode 2 はデュアル同時変換とインターリーブ、mode 3 は 6/8bit のインターリーブのみ
+        let dma_ok = match config.dma_mode {
+            DmaMode::Mode1 => true,
+            DmaMode::Mode2 => mode != Mode::TripleSimultaneous,
+            DmaMode::Mode3 => {
+                mode.is_interleaved()
+                    && matches!(config.resolution, Resolution::Bits8 | Resolution::Bits6)
+            }
+        };
+        if !dma_ok || !(5..=20).contains(&config.delay) {
+            return Err(Error::InvalidMode);
+        }
+
+        let sequence_len = config.sequences[0].len();
+        if sequence_len == 0
+            || sequence_len > 16
+            || config.sequences.iter().any(|s| s.len() != sequence_len)
+            || (mode.is_interleaved()
+                && (sequence_len != 1
+                    || config
+                        .sequences
+                        .iter()
+                        .any(|s| s[0].0 != config.sequences[0][0].0)))
+        {
+            return Err(Error::InvalidSequence);
+        }
+        // バッファの前半/後半にそれぞれ全ランク・全 ADC の組が整数個入るように
+        // （mode 2 は 32bit 単位で転送するので、さらに偶数）
+        let frame = adcs * sequence_len;
+        if !buffer.len().is_multiple_of(frame * 2)
+            || (config.dma_mode == DmaMode::Mode2 && !buffer.len().is_multiple_of(4))
+            || buffer.len() > u16::MAX as usize
+        {
+            return Err(Error::InvalidBuffer);
+        }
+
+        let mut multi = MultiDma {
+            adc1,
+            adc2,
+            adc3,
+            mode,
+            dma_mode: config.dma_mode,
+            stream,
+            sequence_len,
+            buffer,
+        };
+
+        for (regs, sequence) in multi.adc_regs().zip(config.sequences.iter()) {
+            let mut channels = [0u8; 16];
+            for (i, &(channel, sample_time)) in sequence.iter().enumerate() {
+                channels[i] = channel;
+                if channel <= 18 {
+                    super::set_sample_time(regs, channel, sample_time);
+                }
+            }
+            super::set_regular_sequence(regs, &channels[..sequence_len])?;
+            super::set_resolution(regs, config.resolution);
+            regs.cr1.modify(|_, w| w.scan().bit(sequence_len > 1));
+            // 個別の DMA 要求は使わない（ADC_CCR.DMA で CDR から転送する）
+            regs.cr2
+                .modify(|_, w| w.dma().clear_bit().dds().clear_bit());
+            match config.conversion {
+                Conversion::Continuous => super::set_conversion(regs, Conversion::Continuous),
+                // スレーブは外部トリガを使わない（ADC1 に合わせて変換される）
+                Conversion::Triggered(..) => regs
+                    .cr2
+                    .modify(|r, w| unsafe { w.bits(r.bits() & !((0b11 << 28) | (1 << 1))) }),
+            }
+        }
+        super::set_conversion(multi.adc1.regs(), config.conversion);
+
+        multi.common().ccr.modify(|r, w| unsafe {
+            let bits = r.bits() & !((0b11 << 14) | (1 << 13) | (0b1111 << 8) | 0b11111);
+            w.bits(
+                bits | ((config.dma_mode as u32) << 14)
+                    | (1 << 13) // DDS: 循環モードでも DMA 要求を出し続ける
+                    | (((config.delay - 5) as u32) << 8)
+                    | mode.bits(),
+            )
+        });
+
+        multi.config_dma()?;
+        Ok(multi)
+    }
+
+    fn common(&self) -> &stm32f446::adc_common::RegisterBlock {
+        unsafe { &*stm32f446::ADC_COMMON::ptr() }
+    }
+
+    // 使っている ADC のレジスタ（ADC1, ADC2, ADC3 の順）
+    fn adc_regs(&self) -> impl Iterator<Item = &adc1::RegisterBlock> {
+        [
+            Some(self.adc1.regs()),
+            Some(self.adc2.regs()),
+            self.adc3.as_ref().map(|adc| adc.regs()),
+        ]
+        .into_iter()
+        .take(self.mode.adcs())
+        .flatten()
+    }
+
+    // ADC_CDR からバッファへの循環転送を設定（半分/全部転送とエラーで割り込み）
+    fn config_dma(&mut self) -> Result<(), Error> {
+        let mut config = dma::Config {
+            priority: Priority::VeryHigh, // 高速なので取りこぼさないように
+            circular: true,
+            half_transfer_interrupt: true,
+            transfer_complete_interrupt: true,
+            error_interrupt: true,
+            ..Default::default()
+        };
+        let cdr = dma::address(&self.common().cdr); // 転送元 ADC_CDR
+        let channel = <Adc1 as Request<DMA2, S>>::CHANNEL;
+        let result = if self.dma_mode == DmaMode::Mode2 {
+            // mode 2 は CDR を 32bit で読み、FIFO で 16bit 2つに分けて書く（転送データ数は 32bit 単位）
+            config.fifo = Some(FifoThreshold::Full);
+            self.stream
+                .configure_sizes::<u32, u16>(channel, 0b00, cdr, &self.buffer[..], &config)
+        } else {
+            self.stream
+                .configure(channel, 0b00, cdr, &self.buffer[..], &config)
+        };
+        result.map_err(|_| Error::InvalidBuffer)
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn sequence_len(&self) -> usize {
+        self.sequence_len
+    }
+
+    // 変換開始（外部トリガの場合はトリガ待ちになる）
+    pub fn start(&mut self) {
+        self.stream.enable();
+        for regs in self.adc_regs() {
+            super::power_on(regs);
+        }
+        let regs = self.adc1.regs();
+        if regs.cr2.read().cont().bit_is_set() {
+            regs.cr2.modify(|_, w| w.swstart().set_bit());
+        }
+    }
+
+    pub fn stop(&mut self) {
+        for regs in self.adc_regs() {
+            regs.cr2
+                .modify(|r, w| unsafe { w.bits(r.bits() & !((0b11 << 28) | (1 << 1))) });
+        }
+        self.stream.disable();
+    }
+
+    // オーバーランや転送エラーの後に、バッファの先頭から取り直す
+    pub fn restart(&mut self) {
+        let cr2 = self.adc1.regs().cr2.read().bits();
+        let (exten, cont) = (cr2 & (0b11 << 28), cr2 & (1 << 1));
+        self.stop();
+        // ADC の DMA 要求を作り直すために CCR.DMA を一度落とす
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << 14)) });
+        // バッファは new() で確認済みなので失敗しない
+        self.config_dma().ok();
+        for regs in self.adc_regs() {
+            regs.sr.modify(|_, w| w.ovr().clear_bit());
+            if cont != 0 {
+                regs.cr2.modify(|r, w| unsafe { w.bits(r.bits() | cont) });
+            }
+        }
+        self.adc1
+            .regs()
+            .cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() | exten) });
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() | ((self.dma_mode as u32) << 14)) });
+        self.start();
+    }
+
+    // DMA2 のストリーム割り込みから呼ぶ
+    // 埋まった側のブロックを f に渡す。エラーの場合は restart() で取り直すこと。
+    pub fn on_interrupt<F: FnMut(Half, &[u16])>(&mut self, mut f: F) -> Result<(), Error> {
+        let flags = self.stream.flags();
+        self.stream.clear_flags(flags);
+
+        if flags.is_transfer_error() || flags.is_direct_mode_error() {
+            return Err(Error::Transfer);
+        }
+        if self.common().csr.read().bits() & OVR_MASK != 0 {
+            return Err(Error::Overrun);
+        }
+
+        // DMA が書いた内容をこの後で読む
+        compiler_fence(Ordering::Acquire);
+        let (first, second) = self.buffer.split_at(self.buffer.len() / 2);
+        if flags.is_half_transfer() {
+            f(Half::First, first);
+        }
+        if flags.is_transfer_complete() {
+            f(Half::Second, second);
+        }
+        Ok(())
+    }
+
+    // マルチモードを解除して各 ADC、ストリーム、バッファを返す
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        mut self,
+    ) -> (
+        (stm32f446::ADC1, stm32f446::ADC2, Option<stm32f446::ADC3>),
+        Stream<DMA2, S>,
+        &'static mut [u16],
+    ) {
+        self.stop();
+        self.stream.clear_all_flags();
+        self.common()
+            .ccr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !((0b11 << 14) | (1 << 13) | 0b11111)) });
+        ((self.adc1, self.adc2, self.adc3), self.stream, self.buffer)
+    }
+}
+
+// ブロックから、ある ADC のあるランクの値だけを取り出す
+// adcs は ADC の数(2 or 3)、sequence_len はシーケンス長、adc は 0 = ADC1 ~ 2 = ADC3
+pub fn samples(
+    block: &[u16],
+    adcs: usize,
+    sequence_len: usize,
+    adc: usize,
+    rank: usize,
+) -> impl Iterator<Item = u16> + '_ {
+    block
+        .iter()
+        .skip(rank * adcs + adc)
+        .step_by(adcs * sequence_len)
+        .copied()
+}
+
+// ブロックを ADC ごとのバッファに振り分ける（outputs は ADC1, ADC2, ADC3 の順、各 ADC のシーケンス順に並ぶ）
+// 書き込んだ1バッファあたりの個数を返す
+pub fn deinterleave(block: &[u16], outputs: &mut [&mut [u16]]) -> usize {
+    let adcs = outputs.len();
+    if adcs == 0 {
+        return 0;
+    }
+    let len = outputs
+        .iter()
+        .map(|out| out.len())
+        .min()
+        .unwrap_or(0)
+        .min(block.len() / adcs);
+    for (i, frame) in block.chunks_exact(adcs).take(len).enumerate() {
+        for (out, &value) in outputs.iter_mut().zip(frame) {
+            out[i] = value;
+        }
+    }
+    len
+}
+
+// DMA mode 3 のブロック(16bit に 8bit x 2)を変換順のバイト列として取り出す
+pub fn unpack_bytes(block: &[u16]) -> impl Iterator<Item = u8> + '_ {
+    block
+        .iter()
+        .flat_map(|&value| [value as u8, (value >> 8) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dual_simultaneous_samples() {
+        // シーケンス長 2: ランク0 の ADC1, ADC2、ランク1 の ADC1, ADC2 の順に並ぶ
+        let block = [10, 20, 11, 21, 12, 22, 13, 23];
+        let adc2_rank0: Vec<u16> = samples(&block, 2, 2, 1, 0).collect();
+        assert_eq!(adc2_rank0, [20, 22]);
+        let adc1_rank1: Vec<u16> = samples(&block, 2, 2, 0, 1).collect();
+        assert_eq!(adc1_rank1, [11, 13]);
+    }
+
+    #[test]
+    fn triple_interleaved_layout() {
+        // インターリーブではバッファの並びがそのまま時間順（ADC1, ADC2, ADC3, ADC1, ...）
+        let block: Vec<u16> = (0..10).collect();
+        let adc3: Vec<u16> = samples(&block, 3, 1, 2, 0).collect();
+        assert_eq!(adc3, [2, 5, 8]);
+
+        let mut adc1 = [0; 4];
+        let mut adc2 = [0; 4];
+        let mut adc3 = [0; 4];
+        // 3つ揃っている 3 フレームだけ書き込む
+        let len = deinterleave(&block, &mut [&mut adc1, &mut adc2, &mut adc3]);
+        assert_eq!(len, 3);
+        assert_eq!(adc1[..len], [0, 3, 6]);
+        assert_eq!(adc2[..len], [1, 4, 7]);
+        assert_eq!(adc3[..len], [2, 5, 8]);
+    }
+
+    #[test]
+    fn dual_interleaved_deinterleave_stops_at_shortest_output() {
+        let block = [100, 200, 101, 201, 102, 202];
+        let mut adc1 = [0; 2];
+        let mut adc2 = [0; 8];
+        assert_eq!(deinterleave(&block, &mut [&mut adc1, &mut adc2]), 2);
+        assert_eq!(adc1, [100, 101]);
+        assert_eq!(adc2[..2], [200, 201]);
+        assert_eq!(deinterleave(&block, &mut []), 0);
+    }
+
+    #[test]
+    fn mode3_bytes_in_conversion_order() {
+        // 下位バイトが先に変換された値
+        let block = [0x2211, 0x4433, 0x00FF];
+        let bytes: Vec<u8> = unpack_bytes(&block).collect();
+        assert_eq!(bytes, [0x11, 0x22, 0x33, 0x44, 0xFF, 0x00]);
+    }
+}
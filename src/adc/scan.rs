@@ -0,0 +1,224 @@
+// ADC のスキャン変換(最大16チャンネル)を DMA2 で循環バッファへ転送
+// バッファの前半/後半が埋まるたびに（DMA の HT/TC 割り込み）、そのブロックをコールバックに渡す。
+// ブロックにはシーケンス順に並んだサンプルが (バッファ長 / 2 / チャンネル数) 組入っている。
+// DMA2 のストリームは dma::split で取り出したものを渡す。NVIC の割り込み許可は呼び出し側で行うこと。
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use stm32f4::stm32f446::DMA2;
+
+use super::{Conversion, Error, Instance, Resolution, SampleTime};
+use crate::dma::request::Request;
+use crate::dma::{self, Priority, Stream};
+
+// 埋まったのがバッファのどちら側か
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Half {
+    First,
+    Second,
+}
+
+pub struct ScanConfig<'a> {
+    // (チャンネル番号, サンプリング時間) を変換順に並べる
+    // 信号源のインピーダンスが分かっていれば、channels_for_sources() で作れる。
+    pub channels: &'a [(u8, SampleTime)],
+    pub resolution: Resolution,
+    pub conversion: Conversion,
+}
+
+// (チャンネル番号, 信号源のインピーダンス[Ω]) を変換順に並べたものから、ScanConfig::channels を作る
+// adc_clock は Prescaler::adc_clock() で求めた ADC のクロック[Hz]
+pub fn channels_for_sources<const N: usize>(
+    sources: [(u8, u32); N],
+    adc_clock: u32,
+    resolution: Resolution,
+) -> Result<[(u8, SampleTime); N], Error> {
+    let mut channels = [(0, SampleTime::Cycles3); N];
+    for (channel, &(number, ohms)) in channels.iter_mut().zip(sources.iter()) {
+        *channel = (number, SampleTime::for_source(ohms, adc_clock, resolution)?);
+    }
+    Ok(channels)
+}
+
+pub struct ScanDma<ADC, const S: usize> {
+    adc: ADC,
+    resolution: Resolution,
+    stream: Stream<DMA2, S>,
+    buffer: &'static mut [u16],
+}
+
+impl<ADC: Instance, const S: usize> ScanDma<ADC, S>
+where
+    ADC::DmaRequest: Request<DMA2, S>,
+{
+    // dma は DMA2 のストリームと ADC の要求（ADC1 なら stream 0 か 4 と dma::request::Adc1）
+    pub fn new(
+        adc: ADC,
+        dma: (Stream<DMA2, S>, ADC::DmaRequest),
+        config: &ScanConfig,
+        buffer: &'static mut [u16],
+    ) -> Result<Self, Error> {
+        let (stream, _) = dma;
+        let count = config.channels.len();
+        if count == 0 || count > 16 {
+            return Err(Error::InvalidSequence);
+        }
+        if !buffer.len().is_multiple_of(count * 2) || buffer.len() > u16::MAX as usize {
+            return Err(Error::InvalidBuffer);
+        }
+
+        let regs = adc.regs();
+        let mut sequence = [0u8; 16];
+        for (i, &(channel, sample_time)) in config.channels.iter().enumerate() {
+            sequence[i] = channel;
+            if channel <= 18 {
+                super::set_sample_time(regs, channel, sample_time);
+            }
+        }
+        super::set_regular_sequence(regs, &sequence[..count])?;
+        super::set_resolution(regs, config.resolution);
+        regs.cr1.modify(|_, w| w.scan().set_bit()); // スキャンモード
+        super::set_conversion(regs, config.conversion);
+        // DMA 要求を毎回出す(DDS)ので循環モードでも止まらない
+        regs.cr2.modify(|_, w| w.dma().set_bit().dds().set_bit());
+
+        let mut scan = ScanDma {
+            adc,
+            resolution: config.resolution,
+            stream,
+            buffer,
+        };
+        scan.config_dma()?;
+        Ok(scan)
+    }
+
+    // ADC_DR からバッファへの循環転送を設定（半分/全部転送とエラーで割り込み）
+    fn config_dma(&mut self) -> Result<(), Error> {
+        let config = dma::Config {
+            priority: Priority::High,
+            circular: true,
+            half_transfer_interrupt: true,
+            transfer_complete_interrupt: true,
+            error_interrupt: true,
+            ..Default::default()
+        };
+        self.stream
+            .configure(
+                <ADC::DmaRequest as Request<DMA2, S>>::CHANNEL,
+                0b00,
+                dma::address(&self.adc.regs().dr),
+                &self.buffer[..],
+                &config,
+            )
+            .map_err(|_| Error::InvalidBuffer)
+    }
+
+    // チャンネルのサンプリング時間を、信号源のインピーダンス[Ω]から決め直す（設定した値を返す）
+    // adc_clock は Prescaler::adc_clock() で求めた ADC のクロック[Hz]
+    pub fn set_source_impedance(
+        &mut self,
+        channel: u8,
+        source_ohms: u32,
+        adc_clock: u32,
+    ) -> Result<SampleTime, Error> {
+        if channel > 18 {
+            return Err(Error::InvalidSequence);
+        }
+        let sample_time = SampleTime::for_source(source_ohms, adc_clock, self.resolution)?;
+        super::set_sample_time(self.adc.regs(), channel, sample_time);
+        Ok(sample_time)
+    }
+
+    // 変換開始（外部トリガの場合はトリガ待ちになる）
+    pub fn start(&mut self) {
+        self.stream.enable();
+        let regs = self.adc.regs();
+        super::power_on(regs);
+        if regs.cr2.read().cont().bit_is_set() {
+            regs.cr2.modify(|_, w| w.swstart().set_bit());
+        }
+    }
+
+    pub fn stop(&mut self) {
+        let regs = self.adc.regs();
+        // CONT と外部トリガを止めてから DMA を止める
+        regs.cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() & !((0b11 << 28) | (1 << 1))) });
+        self.stream.disable();
+    }
+
+    // オーバーランや転送エラーの後に、バッファの先頭から取り直す
+    pub fn restart(&mut self) {
+        let cont = self.adc.regs().cr2.read().cont().bit_is_set();
+        let exten = self.adc.regs().cr2.read().bits() & (0b11 << 28);
+        self.stop();
+        // バッファは new() で確認済みなので失敗しない
+        self.config_dma().ok();
+        let regs = self.adc.regs();
+        regs.sr.modify(|_, w| w.ovr().clear_bit());
+        regs.cr2
+            .modify(|r, w| unsafe { w.bits(r.bits() | exten | if cont { 1 << 1 } else { 0 }) });
+        self.start();
+    }
+
+    // DMA2 のストリーム割り込みから呼ぶ
+    // 埋まった側のブロックを f に渡す。エラーの場合は restart() で取り直すこと。
+    pub fn on_interrupt<F: FnMut(Half, &[u16])>(&mut self, mut f: F) -> Result<(), Error> {
+        let flags = self.stream.flags();
+        self.stream.clear_flags(flags);
+
+        if flags.is_transfer_error() || flags.is_direct_mode_error() {
+            return Err(Error::Transfer);
+        }
+        if self.adc.regs().sr.read().ovr().bit_is_set() {
+            return Err(Error::Overrun);
+        }
+
+        // DMA が書いた内容をこの後で読む
+        compiler_fence(Ordering::Acquire);
+        let (first, second) = self.buffer.split_at(self.buffer.len() / 2);
+        if flags.is_half_transfer() {
+            f(Half::First, first);
+        }
+        if flags.is_transfer_complete() {
+            f(Half::Second, second);
+        }
+        Ok(())
+    }
+
+    // 止めて、ADC、ストリーム、バッファを返す
+    pub fn release(mut self) -> (ADC, Stream<DMA2, S>, &'static mut [u16]) {
+        self.stop();
+        self.stream.clear_all_flags();
+        (self.adc, self.stream, self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 90MHz / 4
+    const ADC_CLOCK: u32 = 22_500_000;
+
+    #[test]
+    fn channels_from_source_impedance() {
+        assert_eq!(
+            channels_for_sources(
+                [(0, 10_000), (1, 50_000), (4, 200_000)],
+                ADC_CLOCK,
+                Resolution::Bits12
+            ),
+            Ok([
+                (0, SampleTime::Cycles15),
+                (1, SampleTime::Cycles56),
+                (4, SampleTime::Cycles480)
+            ])
+        );
+        // 1つでも 480 サイクルで足りないチャンネルがあればエラー
+        assert_eq!(
+            channels_for_sources([(0, 10_000), (1, 1_000_000)], ADC_CLOCK, Resolution::Bits12),
+            Err(Error::SourceImpedance)
+        );
+    }
+}
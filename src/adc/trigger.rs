@@ -0,0 +1,120 @@
+// タイマのイベント(TRGO/CCx)で ADC のレギュラー変換を一定周期で開始させる
+// タイマの更新周期 = サンプリング周期 になるように PSC/ARR を決め、
+// ADC 側は EXTSEL でそのイベントを選び、立ち上がりエッジ(EXTEN)で変換を開始する。
+// CONT = 0 なので、トリガ1回でシーケンス全体を1回変換する。
+
+use super::{Conversion, Error, ExternalTrigger, Instance, TriggerEdge};
+use crate::clock::Clocks;
+use crate::timer::master_slave::{self, MasterMode};
+use crate::timer::{self, timer_period, Channel, TimerId};
+
+// ADC のトリガに使うタイマのイベント
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEvent {
+    // 更新イベントを TRGO に出す
+    Trgo,
+    // CCx の比較一致（PWM mode 1 で周期の半分の位置）
+    Compare(Channel),
+}
+
+// タイマのイベントに対応する EXTSEL を返す（レギュラー変換のトリガに無ければ None）
+pub const fn external_trigger(timer: TimerId, event: TriggerEvent) -> Option<ExternalTrigger> {
+    match (timer, event) {
+        (TimerId::Tim1, TriggerEvent::Compare(Channel::C1)) => Some(ExternalTrigger::Tim1Cc1),
+        (TimerId::Tim1, TriggerEvent::Compare(Channel::C2)) => Some(ExternalTrigger::Tim1Cc2),
+        (TimerId::Tim1, TriggerEvent::Compare(Channel::C3)) => Some(ExternalTrigger::Tim1Cc3),
+        (TimerId::Tim2, TriggerEvent::Compare(Channel::C2)) => Some(ExternalTrigger::Tim2Cc2),
+        (TimerId::Tim2, TriggerEvent::Compare(Channel::C3)) => Some(ExternalTrigger::Tim2Cc3),
+        (TimerId::Tim2, TriggerEvent::Compare(Channel::C4)) => Some(ExternalTrigger::Tim2Cc4),
+        (TimerId::Tim2, TriggerEvent::Trgo) => Some(ExternalTrigger::Tim2Trgo),
+        (TimerId::Tim3, TriggerEvent::Compare(Channel::C1)) => Some(ExternalTrigger::Tim3Cc1),
+        (TimerId::Tim3, TriggerEvent::Trgo) => Some(ExternalTrigger::Tim3Trgo),
+        (TimerId::Tim4, TriggerEvent::Compare(Channel::C4)) => Some(ExternalTrigger::Tim4Cc4),
+        (TimerId::Tim5, TriggerEvent::Compare(Channel::C1)) => Some(ExternalTrigger::Tim5Cc1),
+        (TimerId::Tim5, TriggerEvent::Compare(Channel::C2)) => Some(ExternalTrigger::Tim5Cc2),
+        (TimerId::Tim5, TriggerEvent::Compare(Channel::C3)) => Some(ExternalTrigger::Tim5Cc3),
+        (TimerId::Tim8, TriggerEvent::Compare(Channel::C1)) => Some(ExternalTrigger::Tim8Cc1),
+        (TimerId::Tim8, TriggerEvent::Trgo) => Some(ExternalTrigger::Tim8Trgo),
+        _ => None,
+    }
+}
+
+pub struct SampleClock<TIM> {
+    tim: TIM,
+    event: TriggerEvent,
+    trigger: ExternalTrigger,
+    timclk: u32,
+    rate: u32,
+}
+
+impl<TIM: timer::Instance> SampleClock<TIM> {
+    // タイマのクロック供給は呼び出し側で済ませておくこと
+    pub fn new(tim: TIM, clocks: &Clocks, event: TriggerEvent) -> Result<Self, Error> {
+        let trigger = external_trigger(TIM::ID, event).ok_or(Error::NoTrigger)?;
+        let regs = tim.regs();
+        regs.cr1.modify(|_, w| w.cen().clear_bit().arpe().set_bit());
+        match event {
+            TriggerEvent::Trgo => {
+                master_slave::set_master_mode(&tim, MasterMode::Update, false);
+            }
+            TriggerEvent::Compare(channel) => {
+                timer::set_output_mode(regs, channel, 0b110); // PWM mode 1
+                timer::enable_channel(regs, channel, true);
+                tim.enable_outputs();
+            }
+        }
+        Ok(SampleClock {
+            tim,
+            event,
+            trigger,
+            timclk: TIM::clock(clocks),
+            rate: 0,
+        })
+    }
+
+    // サンプリング周波数[Hz]を設定し、実際に設定できた周波数を返す
+    pub fn sample_rate(&mut self, rate: u32) -> Result<u32, Error> {
+        let (psc, arr, actual) =
+            timer_period(self.timclk, rate, TIM::COUNTER_BITS).ok_or(Error::InvalidRate)?;
+        let regs = self.tim.regs();
+        regs.psc.write(|w| unsafe { w.bits(psc as u32) });
+        regs.arr.write(|w| unsafe { w.bits(arr) });
+        if let TriggerEvent::Compare(channel) = self.event {
+            timer::set_compare(regs, channel, arr.div_ceil(2));
+        }
+        if regs.cr1.read().cen().bit_is_clear() {
+            regs.egr.write(|w| w.ug().set_bit()); // プリロード値を反映
+            regs.sr.write(|w| unsafe { w.bits(0) });
+        }
+        self.rate = actual;
+        Ok(actual)
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    // ADC の変換開始方法として渡す（ScanConfig::conversion など）
+    pub fn conversion(&self) -> Conversion {
+        Conversion::Triggered(self.trigger, TriggerEdge::Rising)
+    }
+
+    // ADC の EXTSEL/EXTEN をこのタイマのイベントに設定
+    pub fn connect<ADC: Instance>(&self, adc: &ADC) {
+        super::set_conversion(adc.regs(), self.conversion());
+    }
+
+    // タイマを動かしてサンプリング開始
+    pub fn start(&mut self) {
+        self.tim.regs().cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    pub fn stop(&mut self) {
+        self.tim.regs().cr1.modify(|_, w| w.cen().clear_bit());
+    }
+
+    pub fn release(mut self) -> TIM {
+        self.stop();
+        self.tim
+    }
+}
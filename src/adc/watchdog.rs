@@ -0,0 +1,217 @@
+// ADC のアナログウォッチドッグ
+// 変換結果が [下限, 上限] の窓から外れたら AWD 割り込みで知らせる（ポーリング不要）。
+// 監視はハードウェアで変換ごとに行われるので、窓の外に居続けると毎回割り込みが入ってしまう。
+// そこで、窓から出たら「戻ってきたこと」を検出する窓に張り替え、
+// 戻りの判定にはヒステリシスを持たせて境界付近でのばたつきを抑える。
+//   窓の中:   [low, high] の外で割り込み
+//   上に外れ: high - hysteresis を下回ったら割り込み
+//   下に外れ: low + hysteresis を上回ったら割り込み
+//
+// しきい値の比較は 12bit の生の値で行われるので、分解能 12bit・右寄せで使うこと。
+// インジェクテッド側を監視する場合、JOFRx のオフセットは 0 にしておくこと。
+
+use core::marker::PhantomData;
+
+use super::internal;
+use super::{Error, Instance, Resolution};
+
+// CR1 のビット位置
+const AWDCH_MASK: u32 = 0b11111;
+const AWDIE: u32 = 1 << 6;
+const AWDSGL: u32 = 1 << 9;
+const JAWDEN: u32 = 1 << 22;
+const AWDEN: u32 = 1 << 23;
+// SR.AWD
+const AWD: u32 = 1 << 0;
+
+const MAX_CODE: u16 = 4095;
+
+// 監視するチャンネル
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channels {
+    Single(u8),
+    All,
+}
+
+// 監視する変換グループ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    Regular,
+    Injected,
+    Both,
+}
+
+// 入力電圧が窓に対してどこにあるか
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    Inside,
+    Above,
+    Below,
+}
+
+pub struct WatchdogConfig {
+    pub channels: Channels,
+    pub group: Group,
+    // しきい値とヒステリシス[mV]
+    pub low_mv: u32,
+    pub high_mv: u32,
+    pub hysteresis_mv: u32,
+    // しきい値の換算に使う VDDA[mV]（internal::Analog::vdda() などで測った値）
+    pub vdda_mv: u32,
+}
+
+pub struct Watchdog<ADC> {
+    group: Group,
+    low: u16,
+    high: u16,
+    hysteresis: u16,
+    zone: Zone,
+    _adc: PhantomData<ADC>,
+}
+
+impl<ADC: Instance> Watchdog<ADC> {
+    // ADC を所有しないので、スキャンやインジェクテッドのドライバと併用できる
+    pub fn new(adc: &ADC, config: &WatchdogConfig) -> Result<Self, Error> {
+        if let Channels::Single(channel) = config.channels {
+            if channel > 18 {
+                return Err(Error::InvalidSequence);
+            }
+        }
+        let mut watchdog = Watchdog {
+            group: config.group,
+            low: 0,
+            high: MAX_CODE,
+            hysteresis: 0,
+            zone: Zone::Inside,
+            _adc: PhantomData,
+        };
+        watchdog.set_thresholds(
+            config.low_mv,
+            config.high_mv,
+            config.hysteresis_mv,
+            config.vdda_mv,
+        )?;
+
+        adc.regs().cr1.modify(|r, w| unsafe {
+            let mut bits = r.bits() & !(AWDCH_MASK | AWDSGL | JAWDEN | AWDEN);
+            if let Channels::Single(channel) = config.channels {
+                bits |= AWDSGL | channel as u32;
+            }
+            w.bits(bits)
+        });
+        Ok(watchdog)
+    }
+
+    fn regs(&self) -> &super::adc1::RegisterBlock {
+        unsafe { &*ADC::ptr() }
+    }
+
+    // しきい値を変更する（状態は窓の中に戻る）
+    pub fn set_thresholds(
+        &mut self,
+        low_mv: u32,
+        high_mv: u32,
+        hysteresis_mv: u32,
+        vdda_mv: u32,
+    ) -> Result<(), Error> {
+        if low_mv > high_mv {
+            return Err(Error::InvalidThreshold);
+        }
+        self.low = internal::from_mv(low_mv, vdda_mv, Resolution::Bits12);
+        self.high = internal::from_mv(high_mv, vdda_mv, Resolution::Bits12);
+        self.hysteresis = internal::from_mv(hysteresis_mv, vdda_mv, Resolution::Bits12);
+        self.zone = Zone::Inside;
+        self.apply_window();
+        Ok(())
+    }
+
+    // 現在の状態に合わせて HTR/LTR を設定
+    fn apply_window(&self) {
+        let (low, high) = match self.zone {
+            Zone::Inside => (self.low, self.high),
+            Zone::Above => (self.high.saturating_sub(self.hysteresis), MAX_CODE),
+            Zone::Below => (0, (self.low + self.hysteresis).min(MAX_CODE)),
+        };
+        let regs = self.regs();
+        regs.ltr.write(|w| unsafe { w.bits(low as u32) });
+        regs.htr.write(|w| unsafe { w.bits(high as u32) });
+    }
+
+    // 監視と AWD 割り込みを有効化（NVIC の ADC 割り込み許可は呼び出し側で行うこと）
+    pub fn enable(&mut self) {
+        let enable = match self.group {
+            Group::Regular => AWDEN,
+            Group::Injected => JAWDEN,
+            Group::Both => AWDEN | JAWDEN,
+        };
+        let regs = self.regs();
+        regs.sr.write(|w| unsafe { w.bits(!AWD) });
+        regs.cr1
+            .modify(|r, w| unsafe { w.bits(r.bits() | enable | AWDIE) });
+    }
+
+    pub fn disable(&mut self) {
+        self.regs()
+            .cr1
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(AWDEN | JAWDEN | AWDIE)) });
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    // 割り込みを起こした変換結果（と思われる値）を読む
+    // レギュラーは DR、インジェクテッドは JDR1 ~ JDRx のうち窓の外にあるもの。
+    fn last_value(&self) -> u16 {
+        let regs = self.regs();
+        let (low, high) = (regs.ltr.read().bits() as u16, regs.htr.read().bits() as u16);
+        let outside = |value: u16| value < low || value > high;
+        if self.group != Group::Injected {
+            let value = regs.dr.read().bits() as u16;
+            if outside(value) || self.group == Group::Regular {
+                return value;
+            }
+        }
+        let count = ((regs.jsqr.read().bits() >> 20) & 0b11) as usize + 1;
+        let data = [
+            regs.jdr1.read().bits() as u16,
+            regs.jdr2.read().bits() as u16,
+            regs.jdr3.read().bits() as u16,
+            regs.jdr4.read().bits() as u16,
+        ];
+        data[..count]
+            .iter()
+            .copied()
+            .find(|&value| outside(value))
+            .unwrap_or(data[0])
+    }
+
+    // ADC 割り込みから呼ぶ。AWD が立っていれば状態を更新し、
+    // 状態が変わった場合は f(新しい状態, 変換結果) を呼ぶ。AWD で無ければ false を返す。
+    pub fn on_interrupt<F: FnMut(Zone, u16)>(&mut self, mut f: F) -> bool {
+        if self.regs().sr.read().bits() & AWD == 0 {
+            return false;
+        }
+        let value = self.last_value();
+        self.regs().sr.write(|w| unsafe { w.bits(!AWD) });
+
+        let zone = if value > self.high {
+            Zone::Above
+        } else if value < self.low {
+            Zone::Below
+        } else {
+            Zone::Inside
+        };
+        // 上(下)に外れた状態からの割り込みは、ヒステリシス分戻ってきたことを意味する
+        let zone = match (self.zone, zone) {
+            (Zone::Above, Zone::Above) | (Zone::Below, Zone::Below) => Zone::Inside,
+            (_, zone) => zone,
+        };
+        if zone != self.zone {
+            self.zone = zone;
+            self.apply_window();
+            f(zone, value);
+        }
+        true
+    }
+}
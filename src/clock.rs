@@ -0,0 +1,99 @@
+// クロック設定と、設定後の各バスのクロック周波数
+
+use stm32f4::stm32f446;
+use stm32f4xx_hal as hal;
+
+// 設定済みのクロック周波数[Hz]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clocks {
+    pub sysclk: u32,
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+}
+
+impl Clocks {
+    // APB1 タイマクロック（APB の分周が1以外ならPCLKの2倍になる）
+    pub fn timclk1(&self) -> u32 {
+        if self.pclk1 == self.hclk {
+            self.pclk1
+        } else {
+            self.pclk1 * 2
+        }
+    }
+
+    // APB2 タイマクロック
+    pub fn timclk2(&self) -> u32 {
+        if self.pclk2 == self.hclk {
+            self.pclk2
+        } else {
+            self.pclk2 * 2
+        }
+    }
+}
+
+// HAL で設定(freeze)したクロックから変換
+impl From<&hal::rcc::Clocks> for Clocks {
+    fn from(clocks: &hal::rcc::Clocks) -> Self {
+        Clocks {
+            sysclk: clocks.sysclk().raw(),
+            hclk: clocks.hclk().raw(),
+            pclk1: clocks.pclk1().raw(),
+            pclk2: clocks.pclk2().raw(),
+        }
+    }
+}
+
+// クロックの初期設定を実施
+// SYSCLK: HSE(ST-Link 8MHz) -> PLL -> 180MHz
+// APB1: 45MHz
+// APB2: 90MHz
+pub fn config_clock(peripheral: &stm32f446::Peripherals) -> Clocks {
+    // HSEはBypassモード(ST-Linkからの 8 MHz を使える)
+    peripheral.RCC.cr.modify(|_, w| w.hsebyp().bypassed());
+    // HSE ON
+    peripheral.RCC.cr.modify(|_, w| w.hseon().on());
+    // HSE の準備完了待ち
+    while peripheral.RCC.cr.read().hserdy().is_not_ready() {}
+
+    // クロック設定
+    // PLL の ソースクロックをHSE(8MHz)とする
+    peripheral.RCC.pllcfgr.modify(|_, w| w.pllsrc().hse());
+    // PLL へ入るクロックを分周（1 ~ 2MHzなので、2MHzとする）
+    peripheral
+        .RCC
+        .pllcfgr
+        .modify(|_, w| unsafe { w.pllm().bits(4) });
+    // 上の続きで逓倍できる(100 ~ 432MHzなので、360MHzとする)
+    peripheral
+        .RCC
+        .pllcfgr
+        .modify(|_, w| unsafe { w.plln().bits(180) });
+    // PLL クロック確定(最大180MHzなので、180MHzとする)
+    peripheral.RCC.pllcfgr.modify(|_, w| w.pllp().div2());
+
+    // PLL ON
+    peripheral.RCC.cr.modify(|_, w| w.pllon().on());
+    // PLL の準備完了待ち
+    while peripheral.RCC.cr.read().pllrdy().is_not_ready() {}
+
+    // フラッシュの読み出し遅延設定（180MHzだと5WS）
+    peripheral.FLASH.acr.modify(|_, w| w.latency().ws5());
+
+    // PLLPをシステムクロックとして使う設定
+    peripheral.RCC.cfgr.modify(|_, w| w.sw().pll());
+    while !peripheral.RCC.cfgr.read().sws().is_pll() {}
+
+    // APB1を分周（最大45MHz）
+    peripheral.RCC.cfgr.modify(|_, w| w.ppre1().div4());
+
+    // APB2を分周（最大90MHz）
+    peripheral.RCC.cfgr.modify(|_, w| w.ppre2().div2());
+
+    Clocks {
+        sysclk: 180_000_000,
+        hclk: 180_000_000,
+        pclk1: 45_000_000,
+        pclk2: 90_000_000,
+    }
+}
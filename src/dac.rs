@@ -0,0 +1,419 @@
+// DAC（channel 1: PA4, channel 2: PA5）
+// PA4/PA5 のアナログ設定を済ませてから DAC を有効化すること（マニュアルの手順）。
+// DAC のクロック供給(APB1ENR.DACEN)も呼び出し側で行う。
+//
+// 出力電圧は VREF+ * DOR / 4095。write_mv() は VREF+（set_vref() で設定、初期値 3.3V）から値を決める。
+// 出力は DHR に書いた値が、トリガ無しなら APB1 の1クロック後、トリガ有りならトリガで DOR に移って出力される。
+// トリガ有りの場合は、内蔵の三角波/ノイズ生成や DMA での波形出力が使える（wave モジュール）。
+
+use stm32f4::stm32f446;
+use stm32f446::{dac, DMA1};
+
+use crate::dma::request::{self, Request};
+use crate::dma::{self, Stream, Transfer};
+
+pub mod wave;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // サンプリング周波数がタイマで作れない
+    InvalidRate,
+    // そのタイマは DAC のトリガに選べない
+    NoTrigger,
+    // DMA の設定エラー（バッファが空、または長すぎる）
+    Dma(dma::Error),
+    // 出力できない電圧（VREF+ を超えている）
+    OutOfRange,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    C1,
+    C2,
+}
+
+impl Channel {
+    // CR, SWTRIGR, SR でのビット位置のずれ
+    const fn shift(self) -> u32 {
+        match self {
+            Channel::C1 => 0,
+            Channel::C2 => 16,
+        }
+    }
+}
+
+// DAC のチャンネルの DMA 要求（channel 1 は DMA1 stream 5、channel 2 は stream 6）
+pub trait DmaRequest {
+    const CHANNEL: Channel;
+}
+
+impl DmaRequest for request::Dac1 {
+    const CHANNEL: Channel = Channel::C1;
+}
+
+impl DmaRequest for request::Dac2 {
+    const CHANNEL: Channel = Channel::C2;
+}
+
+// CR のビット位置（channel 1 の場合）
+const EN: u32 = 1 << 0;
+const BOFF: u32 = 1 << 1;
+const TEN: u32 = 1 << 2;
+const TSEL_MASK: u32 = 0b111 << 3;
+const WAVE_MASK: u32 = 0b11 << 6;
+const MAMP_MASK: u32 = 0b1111 << 8;
+const DMAEN: u32 = 1 << 12;
+// SR
+const DMAUDR: u32 = 1 << 13;
+
+const FULL_SCALE: u32 = 4095;
+const DEFAULT_VREF_MV: u32 = 3300;
+
+// データの書き方（どれも DHR12Rx と同じく 12bit の DOR になる）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alignment {
+    // 12bit 右寄せ (DHR12Rx)
+    Right12,
+    // 12bit 左寄せ (DHR12Lx、u16 の上位 12bit)
+    Left12,
+    // 8bit 右寄せ (DHR8Rx、下位 4bit は 0)
+    Right8,
+}
+
+// アナログ出力の共通インターフェース（DAC のチャンネルなど）
+pub trait AnalogOutput {
+    type Error;
+
+    // 出力電圧を mV で設定
+    fn set_mv(&mut self, mv: u32) -> Result<(), Self::Error>;
+
+    // 出力できる最大電圧[mV]
+    fn max_mv(&self) -> u32;
+}
+
+// mV を 12bit の DAC の値に換算（四捨五入、範囲外は None）
+pub fn code_for_mv(mv: u32, vref_mv: u32) -> Option<u16> {
+    if vref_mv == 0 || mv > vref_mv {
+        return None;
+    }
+    Some(((mv * FULL_SCALE + vref_mv / 2) / vref_mv) as u16)
+}
+
+// 12bit の DAC の値を mV に換算
+pub fn mv_for_code(code: u16, vref_mv: u32) -> u32 {
+    ((code as u32).min(FULL_SCALE) * vref_mv + FULL_SCALE / 2) / FULL_SCALE
+}
+
+// 変換のトリガ (CR.TSELx)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    Tim6Trgo = 0b000,
+    Tim8Trgo = 0b001,
+    Tim7Trgo = 0b010,
+    Tim5Trgo = 0b011,
+    Tim2Trgo = 0b100,
+    Tim4Trgo = 0b101,
+    Exti9 = 0b110,
+    Software = 0b111,
+}
+
+// 三角波の振幅 / ノイズのマスク (CR.MAMPx)
+// 三角波は 0 ~ 2^n - 1、ノイズは下位 n ビットが LFSR の値になる。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Amplitude {
+    Bits1 = 0,
+    Bits2 = 1,
+    Bits3 = 2,
+    Bits4 = 3,
+    Bits5 = 4,
+    Bits6 = 5,
+    Bits7 = 6,
+    Bits8 = 7,
+    Bits9 = 8,
+    Bits10 = 9,
+    Bits11 = 10,
+    Bits12 = 11,
+}
+
+// 内蔵の波形生成 (CR.WAVEx)
+// どちらも DHR の値を基準にして、トリガごとに値が更新される。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wave {
+    Disabled,
+    Noise(Amplitude),
+    Triangle(Amplitude),
+}
+
+pub struct Dac {
+    dac: stm32f446::DAC,
+    vref_mv: u32,
+}
+
+impl Dac {
+    pub fn new(dac: stm32f446::DAC) -> Self {
+        Dac {
+            dac,
+            vref_mv: DEFAULT_VREF_MV,
+        }
+    }
+
+    // VREF+[mV] を設定（Nucleo では VDDA と同じなので、adc::internal::Analog::vdda() で測った値が使える）
+    pub fn set_vref(&mut self, vref_mv: u32) {
+        self.vref_mv = vref_mv;
+    }
+
+    pub fn vref(&self) -> u32 {
+        self.vref_mv
+    }
+
+    fn regs(&self) -> &dac::RegisterBlock {
+        &self.dac
+    }
+
+    fn modify_cr(&self, channel: Channel, mask: u32, value: u32) {
+        let shift = channel.shift();
+        self.regs().cr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(mask << shift)) | ((value & mask) << shift))
+        });
+    }
+
+    pub fn enable(&mut self, channel: Channel) {
+        self.modify_cr(channel, EN, EN);
+    }
+
+    pub fn disable(&mut self, channel: Channel) {
+        self.modify_cr(channel, EN, 0);
+    }
+
+    // 出力バッファ（初期値は有効）
+    // 有効だと負荷を駆動できるが、0V/VREF+ 付近まで振り切れない。無効だと出力インピーダンスが高くなる。
+    pub fn set_buffer(&mut self, channel: Channel, enabled: bool) {
+        self.modify_cr(channel, BOFF, if enabled { 0 } else { BOFF });
+    }
+
+    // None ならトリガ無し（DHR に書くとすぐ出力）
+    pub fn set_trigger(&mut self, channel: Channel, trigger: Option<Trigger>) {
+        match trigger {
+            Some(trigger) => {
+                self.modify_cr(channel, TEN | TSEL_MASK, TEN | ((trigger as u32) << 3))
+            }
+            None => self.modify_cr(channel, TEN | TSEL_MASK, 0),
+        }
+    }
+
+    // 内蔵の波形生成を設定（トリガを有効にしておくこと）
+    pub fn set_wave(&mut self, channel: Channel, wave: Wave) {
+        let value = match wave {
+            Wave::Disabled => 0,
+            Wave::Noise(amplitude) => (0b01 << 6) | ((amplitude as u32) << 8),
+            Wave::Triangle(amplitude) => (0b10 << 6) | ((amplitude as u32) << 8),
+        };
+        self.modify_cr(channel, WAVE_MASK | MAMP_MASK, value);
+    }
+
+    // 12bit 右寄せで出力値を書く（三角波/ノイズの場合は基準値）
+    pub fn write(&mut self, channel: Channel, value: u16) {
+        let value = (value & 0xFFF) as u32;
+        match channel {
+            Channel::C1 => self.regs().dhr12r1.write(|w| unsafe { w.bits(value) }),
+            Channel::C2 => self.regs().dhr12r2.write(|w| unsafe { w.bits(value) }),
+        }
+    }
+
+    // 指定した寄せ方で出力値を書く
+    pub fn write_aligned(&mut self, channel: Channel, value: u16, alignment: Alignment) {
+        let regs = self.regs();
+        let value = value as u32;
+        match (channel, alignment) {
+            (_, Alignment::Right12) => self.write(channel, value as u16),
+            (Channel::C1, Alignment::Left12) => {
+                regs.dhr12l1.write(|w| unsafe { w.bits(value & 0xFFF0) })
+            }
+            (Channel::C2, Alignment::Left12) => {
+                regs.dhr12l2.write(|w| unsafe { w.bits(value & 0xFFF0) })
+            }
+            (Channel::C1, Alignment::Right8) => {
+                regs.dhr8r1.write(|w| unsafe { w.bits(value & 0xFF) })
+            }
+            (Channel::C2, Alignment::Right8) => {
+                regs.dhr8r2.write(|w| unsafe { w.bits(value & 0xFF) })
+            }
+        }
+    }
+
+    // 出力電圧を mV で設定し、書いた値を返す
+    pub fn write_mv(&mut self, channel: Channel, mv: u32) -> Result<u16, Error> {
+        let code = code_for_mv(mv, self.vref_mv).ok_or(Error::OutOfRange)?;
+        self.write(channel, code);
+        Ok(code)
+    }
+
+    // 両チャンネルを同時に更新する (DHR12RD)
+    pub fn write_dual(&mut self, value1: u16, value2: u16) {
+        let bits = ((value2 as u32 & 0xFFF) << 16) | (value1 as u32 & 0xFFF);
+        self.regs().dhr12rd.write(|w| unsafe { w.bits(bits) });
+    }
+
+    // 両チャンネルを mV で同時に更新する
+    pub fn write_dual_mv(&mut self, mv1: u32, mv2: u32) -> Result<(), Error> {
+        let code1 = code_for_mv(mv1, self.vref_mv).ok_or(Error::OutOfRange)?;
+        let code2 = code_for_mv(mv2, self.vref_mv).ok_or(Error::OutOfRange)?;
+        self.write_dual(code1, code2);
+        Ok(())
+    }
+
+    // 現在の出力電圧[mV]
+    pub fn read_mv(&self, channel: Channel) -> u32 {
+        mv_for_code(self.read(channel), self.vref_mv)
+    }
+
+    // チャンネルごとの AnalogOutput
+    pub fn output(&mut self, channel: Channel) -> Output<'_> {
+        Output { dac: self, channel }
+    }
+
+    // 現在の出力値(DOR)
+    pub fn read(&self, channel: Channel) -> u16 {
+        match channel {
+            Channel::C1 => self.regs().dor1.read().bits() as u16,
+            Channel::C2 => self.regs().dor2.read().bits() as u16,
+        }
+    }
+
+    // ソフトウェアトリガ（Trigger::Software の場合）
+    pub fn trigger(&mut self, channel: Channel) {
+        let bit = match channel {
+            Channel::C1 => 1 << 0,
+            Channel::C2 => 1 << 1,
+        };
+        self.regs().swtrigr.write(|w| unsafe { w.bits(bit) });
+    }
+
+    // samples を DMA で循環させて、トリガごとに1サンプルずつ出力する
+    // dma は DMA1 のストリームと要求（channel 1 なら stream 5 + Dac1、channel 2 なら stream 6 + Dac2）。
+    // トリガを設定してから呼ぶこと。止めるときは stop_dma() でストリームとバッファを返してもらう。
+    pub fn start_dma<REQ: DmaRequest + Request<DMA1, S>, const S: usize>(
+        &mut self,
+        dma: (Stream<DMA1, S>, REQ),
+        samples: &'static [u16],
+    ) -> Result<DmaOutput<S>, Error> {
+        let (stream, request) = dma;
+        let channel = <REQ as DmaRequest>::CHANNEL;
+        self.modify_cr(channel, DMAEN, 0);
+
+        let config = dma::Config {
+            circular: true,
+            ..Default::default()
+        };
+        // 転送先 DHR12Rx
+        let mut transfer = match channel {
+            Channel::C1 => Transfer::memory_to_peripheral(
+                stream,
+                request,
+                &self.regs().dhr12r1,
+                samples,
+                &config,
+            ),
+            Channel::C2 => Transfer::memory_to_peripheral(
+                stream,
+                request,
+                &self.regs().dhr12r2,
+                samples,
+                &config,
+            ),
+        }
+        .map_err(Error::Dma)?;
+        transfer.start();
+
+        self.regs()
+            .sr
+            .write(|w| unsafe { w.bits(DMAUDR << channel.shift()) });
+        self.modify_cr(channel, DMAEN, DMAEN);
+        Ok(DmaOutput { channel, transfer })
+    }
+
+    // DMA での出力を止めて、ストリームとバッファを返す（DAC の出力は最後の値のまま）
+    pub fn stop_dma<const S: usize>(
+        &mut self,
+        output: DmaOutput<S>,
+    ) -> (Stream<DMA1, S>, &'static [u16]) {
+        self.modify_cr(output.channel, DMAEN, 0);
+        output.transfer.release()
+    }
+
+    // トリガが速すぎて DMA が間に合わなかった（DMA 要求は止まるので stop_dma/start_dma でやり直す）
+    pub fn is_underrun(&self, channel: Channel) -> bool {
+        self.regs().sr.read().bits() & (DMAUDR << channel.shift()) != 0
+    }
+
+    pub fn release(self) -> stm32f446::DAC {
+        self.dac
+    }
+}
+
+// DMA で波形を出力中のチャンネル（Dac::start_dma() で作る）
+pub struct DmaOutput<const S: usize> {
+    channel: Channel,
+    transfer: Transfer<DMA1, S, &'static [u16]>,
+}
+
+impl<const S: usize> DmaOutput<S> {
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+}
+
+// Dac の1チャンネル分（Dac::output() で作る）
+pub struct Output<'a> {
+    dac: &'a mut Dac,
+    channel: Channel,
+}
+
+impl AnalogOutput for Output<'_> {
+    type Error = Error;
+
+    fn set_mv(&mut self, mv: u32) -> Result<(), Error> {
+        self.dac.write_mv(self.channel, mv).map(|_| ())
+    }
+
+    fn max_mv(&self) -> u32 {
+        self.dac.vref_mv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn millivolt_conversion_clamps() {
+        assert_eq!(code_for_mv(0, 3300), Some(0));
+        assert_eq!(code_for_mv(3300, 3300), Some(4095));
+        assert_eq!(code_for_mv(1650, 3300), Some(2048));
+        assert_eq!(code_for_mv(3301, 3300), None);
+        assert_eq!(code_for_mv(0, 0), None);
+        // 12bit を超える値は最大値として扱う
+        assert_eq!(mv_for_code(4095, 3300), 3300);
+        assert_eq!(mv_for_code(u16::MAX, 3300), 3300);
+        assert_eq!(mv_for_code(0, 3300), 0);
+    }
+
+    #[test]
+    fn millivolt_round_trip() {
+        // mV の分解能は 1LSB（約0.8mV）より粗いので、どちらの向きも ±1 以内で戻る
+        for vref_mv in [2500, 3000, 3300] {
+            for mv in 0..=vref_mv {
+                let code = code_for_mv(mv, vref_mv).unwrap();
+                assert!(mv_for_code(code, vref_mv).abs_diff(mv) <= 1, "{} mV", mv);
+            }
+            for code in 0..=4095 {
+                let mv = mv_for_code(code, vref_mv);
+                assert!(
+                    code_for_mv(mv, vref_mv).unwrap().abs_diff(code) <= 1,
+                    "{}",
+                    code
+                );
+            }
+        }
+    }
+}
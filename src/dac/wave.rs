@@ -0,0 +1,86 @@
+// DAC の波形出力
+// タイマの更新イベント(TRGO)を DAC のトリガにして、一定のサンプリング周波数で出力を更新する。
+// ・内蔵の三角波/ノイズ生成: トリガごとに DAC 自身が値を更新する（CPU も DMA も不要）
+// ・DMA: 任意のバッファをトリガごとに1サンプルずつ出力する
+//   出力周波数 = サンプリング周波数 / バッファ長
+//   波形のテーブルは table モジュールでコンパイル時に作れる。
+//     static SINE: [u16; 64] = table::to_u16(table::sine(4095));
+//     static SAWTOOTH: [u16; 64] = table::to_u16(table!(64, |i| table::scale(table::ramp(i, 64), 4095)));
+
+use super::{Error, Trigger};
+use crate::clock::Clocks;
+use crate::timer::master_slave::{self, MasterMode};
+use crate::timer::{self, timer_period, TimerId};
+
+// タイマの TRGO に対応する DAC のトリガ（TIM6/TIM7 は timer::Instance に無いので対象外）
+pub const fn dac_trigger(timer: TimerId) -> Option<Trigger> {
+    match timer {
+        TimerId::Tim2 => Some(Trigger::Tim2Trgo),
+        TimerId::Tim4 => Some(Trigger::Tim4Trgo),
+        TimerId::Tim5 => Some(Trigger::Tim5Trgo),
+        TimerId::Tim8 => Some(Trigger::Tim8Trgo),
+        _ => None,
+    }
+}
+
+// DAC のトリガを出すタイマ
+pub struct WaveClock<TIM> {
+    tim: TIM,
+    trigger: Trigger,
+    timclk: u32,
+    rate: u32,
+}
+
+impl<TIM: timer::Instance> WaveClock<TIM> {
+    // タイマのクロック供給は呼び出し側で済ませておくこと
+    pub fn new(tim: TIM, clocks: &Clocks) -> Result<Self, Error> {
+        let trigger = dac_trigger(TIM::ID).ok_or(Error::NoTrigger)?;
+        tim.regs()
+            .cr1
+            .modify(|_, w| w.cen().clear_bit().arpe().set_bit());
+        master_slave::set_master_mode(&tim, MasterMode::Update, false);
+        Ok(WaveClock {
+            tim,
+            trigger,
+            timclk: TIM::clock(clocks),
+            rate: 0,
+        })
+    }
+
+    // サンプリング周波数[Hz]を設定し、実際に設定できた周波数を返す
+    pub fn sample_rate(&mut self, rate: u32) -> Result<u32, Error> {
+        let (psc, arr, actual) =
+            timer_period(self.timclk, rate, TIM::COUNTER_BITS).ok_or(Error::InvalidRate)?;
+        let regs = self.tim.regs();
+        regs.psc.write(|w| unsafe { w.bits(psc as u32) });
+        regs.arr.write(|w| unsafe { w.bits(arr) });
+        if regs.cr1.read().cen().bit_is_clear() {
+            regs.egr.write(|w| w.ug().set_bit()); // プリロード値を反映
+            regs.sr.write(|w| unsafe { w.bits(0) });
+        }
+        self.rate = actual;
+        Ok(actual)
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    // Dac::set_trigger に渡す
+    pub fn trigger(&self) -> Trigger {
+        self.trigger
+    }
+
+    pub fn start(&mut self) {
+        self.tim.regs().cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    pub fn stop(&mut self) {
+        self.tim.regs().cr1.modify(|_, w| w.cen().clear_bit());
+    }
+
+    pub fn release(mut self) -> TIM {
+        self.stop();
+        self.tim
+    }
+}
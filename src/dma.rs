@@ -0,0 +1,481 @@
+// DMA(DMA1, DMA2)の転送
+// ストリームは型で区別し（Stream<DMA1, 7> など）、split() で1回だけ取り出せる。
+// 転送(Transfer)はストリームと 'static なバッファを所有し、release() で両方を返す。
+// 転送中のバッファには CPU から触れないので、DMA と CPU が同時に読み書きすることは無い。
+// ペリフェラル側のアドレスはレジスタの参照から求める（&TIM2.ccr1 など）。
+// ストリームとチャンネルの組み合わせは request の割り当て表で検査する（使えない組み合わせはコンパイルエラー）。
+// メモリ間の転送（DMA2 のみ）は memory、ダブルバッファは double_buffer。
+
+pub mod double_buffer;
+pub mod memory;
+pub mod request;
+
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use stm32f4::stm32f446;
+use stm32f446::dma2;
+
+use request::Request;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // 転送エラー（バスエラー、不正なアドレス）
+    Transfer,
+    // ダイレクトモードエラー
+    DirectMode,
+    // FIFO のオーバーラン/アンダーラン
+    Fifo,
+    // バッファが空、または 65535 個を超えている
+    InvalidBuffer,
+    // ダブルバッファで、アプリの処理中に DMA が次のバッファまで進んでしまった
+    Overrun,
+}
+
+// LISR/HISR の各ストリームのフラグ位置
+const FLAG_SHIFT: [u32; 4] = [0, 6, 16, 22];
+const FEIF: u32 = 1 << 0;
+const DMEIF: u32 = 1 << 2;
+const TEIF: u32 = 1 << 3;
+const HTIF: u32 = 1 << 4;
+const TCIF: u32 = 1 << 5;
+const ALL_FLAGS: u32 = FEIF | DMEIF | TEIF | HTIF | TCIF;
+
+// SxCR のビット位置
+const CR_EN: u32 = 1 << 0;
+const CR_DMEIE: u32 = 1 << 1;
+const CR_TEIE: u32 = 1 << 2;
+const CR_HTIE: u32 = 1 << 3;
+const CR_TCIE: u32 = 1 << 4;
+const CR_CIRC: u32 = 1 << 8;
+const CR_PINC: u32 = 1 << 9;
+const CR_MINC: u32 = 1 << 10;
+const CR_DBM: u32 = 1 << 18;
+const CR_CT: u32 = 1 << 19;
+// SxFCR
+const FCR_DMDIS: u32 = 1 << 2;
+const FCR_FEIE: u32 = 1 << 7;
+
+pub trait Instance {
+    // DMA の番号 (1, 2)
+    const NUMBER: u8;
+
+    fn ptr() -> *const dma2::RegisterBlock;
+
+    // RCC からクロック供給
+    fn enable_clock(rcc: &stm32f446::RCC);
+}
+
+impl Instance for stm32f446::DMA1 {
+    const NUMBER: u8 = 1;
+
+    fn ptr() -> *const dma2::RegisterBlock {
+        stm32f446::DMA1::ptr()
+    }
+
+    fn enable_clock(rcc: &stm32f446::RCC) {
+        rcc.ahb1enr.modify(|_, w| w.dma1en().enabled());
+    }
+}
+
+impl Instance for stm32f446::DMA2 {
+    const NUMBER: u8 = 2;
+
+    fn ptr() -> *const dma2::RegisterBlock {
+        stm32f446::DMA2::ptr()
+    }
+
+    fn enable_clock(rcc: &stm32f446::RCC) {
+        rcc.ahb1enr.modify(|_, w| w.dma2en().enabled());
+    }
+}
+
+// 転送するデータの型（PSIZE/MSIZE）
+pub trait Word: Copy {
+    const SIZE: u32;
+}
+
+impl Word for u8 {
+    const SIZE: u32 = 0b00;
+}
+
+impl Word for u16 {
+    const SIZE: u32 = 0b01;
+}
+
+impl Word for u32 {
+    const SIZE: u32 = 0b10;
+}
+
+// レジスタの参照からアドレスを求める（SxPAR に設定する値）
+pub fn address<R>(register: &R) -> u32 {
+    register as *const R as u32
+}
+
+// DMA 要求のチャンネル (SxCR.CHSEL、request の割り当て表で決まる)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    C0 = 0,
+    C1 = 1,
+    C2 = 2,
+    C3 = 3,
+    C4 = 4,
+    C5 = 5,
+    C6 = 6,
+    C7 = 7,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    Low = 0b00,
+    Medium = 0b01,
+    High = 0b10,
+    VeryHigh = 0b11,
+}
+
+// FIFO のしきい値 (SxFCR.FTH)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FifoThreshold {
+    Quarter = 0b00,
+    Half = 0b01,
+    ThreeQuarters = 0b10,
+    Full = 0b11,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub priority: Priority,
+    // 最後まで転送したら先頭に戻って続ける
+    pub circular: bool,
+    // ペリフェラル側のアドレスをインクリメントする
+    pub peripheral_increment: bool,
+    // None ならダイレクトモード
+    pub fifo: Option<FifoThreshold>,
+    // 割り込み（NVIC の許可は呼び出し側で行うこと）
+    pub half_transfer_interrupt: bool,
+    pub transfer_complete_interrupt: bool,
+    pub error_interrupt: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            priority: Priority::Medium,
+            circular: false,
+            peripheral_increment: false,
+            fifo: None,
+            half_transfer_interrupt: false,
+            transfer_complete_interrupt: false,
+            error_interrupt: false,
+        }
+    }
+}
+
+// ストリームのフラグ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flags(u32);
+
+impl Flags {
+    pub fn is_transfer_complete(&self) -> bool {
+        self.0 & TCIF != 0
+    }
+
+    pub fn is_half_transfer(&self) -> bool {
+        self.0 & HTIF != 0
+    }
+
+    pub fn is_transfer_error(&self) -> bool {
+        self.0 & TEIF != 0
+    }
+
+    pub fn is_direct_mode_error(&self) -> bool {
+        self.0 & DMEIF != 0
+    }
+
+    pub fn is_fifo_error(&self) -> bool {
+        self.0 & FEIF != 0
+    }
+}
+
+// DMA のストリーム（DMA1/DMA2 の stream 0 ~ 7）
+pub struct Stream<DMA, const N: usize> {
+    _dma: PhantomData<DMA>,
+}
+
+impl<DMA: Instance, const N: usize> Stream<DMA, N> {
+    fn dma() -> &'static dma2::RegisterBlock {
+        unsafe { &*DMA::ptr() }
+    }
+
+    fn st() -> &'static dma2::ST {
+        &Self::dma().st[N]
+    }
+
+    pub fn flags(&self) -> Flags {
+        let dma = Self::dma();
+        let isr = if N < 4 {
+            dma.lisr.read().bits()
+        } else {
+            dma.hisr.read().bits()
+        };
+        Flags((isr >> FLAG_SHIFT[N % 4]) & ALL_FLAGS)
+    }
+
+    pub fn clear_flags(&mut self, flags: Flags) {
+        let dma = Self::dma();
+        let bits = (flags.0 & ALL_FLAGS) << FLAG_SHIFT[N % 4];
+        if N < 4 {
+            dma.lifcr.write(|w| unsafe { w.bits(bits) });
+        } else {
+            dma.hifcr.write(|w| unsafe { w.bits(bits) });
+        }
+    }
+
+    pub fn clear_all_flags(&mut self) {
+        self.clear_flags(Flags(ALL_FLAGS));
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        Self::st().cr.read().bits() & CR_EN != 0
+    }
+
+    pub(crate) fn enable(&mut self) {
+        // バッファへの書き込みを済ませてから DMA を動かす
+        compiler_fence(Ordering::Release);
+        Self::st()
+            .cr
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR_EN) });
+    }
+
+    // ストリームを止めて、止まるまで待つ
+    pub fn disable(&mut self) {
+        let st = Self::st();
+        st.cr.modify(|r, w| unsafe { w.bits(r.bits() & !CR_EN) });
+        while st.cr.read().bits() & CR_EN != 0 {}
+    }
+
+    // 残りの転送データ数
+    pub fn remaining(&self) -> u16 {
+        Self::st().ndtr.read().bits() as u16
+    }
+
+    // エラーフラグを確認してクリアする（FIFO エラーは FIFO を使っている場合のみ）
+    pub(crate) fn check_errors(&mut self, fifo: bool) -> Result<(), Error> {
+        let flags = self.flags();
+        let errors = Flags(flags.0 & (TEIF | DMEIF | if fifo { FEIF } else { 0 }));
+        self.clear_flags(Flags(flags.0 & (TEIF | DMEIF | FEIF)));
+        if errors.is_transfer_error() {
+            Err(Error::Transfer)
+        } else if errors.is_direct_mode_error() {
+            Err(Error::DirectMode)
+        } else if errors.is_fifo_error() {
+            Err(Error::Fifo)
+        } else {
+            Ok(())
+        }
+    }
+
+    // 転送の設定を書き込む（ストリームは止めてから）
+    pub(crate) fn configure<W: Word>(
+        &mut self,
+        channel: Channel,
+        direction: u32,
+        peripheral: u32,
+        memory: &[W],
+        config: &Config,
+    ) -> Result<(), Error> {
+        self.configure_sizes::<W, W>(channel, direction, peripheral, memory, config)
+    }
+
+    // ペリフェラル側(P)とメモリ側(W)でデータサイズが違う転送の設定（FIFO を使うこと）
+    // 転送データ数(NDTR)はペリフェラル側の単位になるので、バッファはその整数倍の大きさにする。
+    pub(crate) fn configure_sizes<P: Word, W: Word>(
+        &mut self,
+        channel: Channel,
+        direction: u32,
+        peripheral: u32,
+        memory: &[W],
+        config: &Config,
+    ) -> Result<(), Error> {
+        let bytes = core::mem::size_of_val(memory);
+        let items = bytes / core::mem::size_of::<P>();
+        if items == 0
+            || items > u16::MAX as usize
+            || !bytes.is_multiple_of(core::mem::size_of::<P>())
+        {
+            return Err(Error::InvalidBuffer);
+        }
+        self.disable();
+        self.clear_all_flags();
+
+        let st = Self::st();
+        st.par.write(|w| unsafe { w.bits(peripheral) });
+        st.m0ar.write(|w| unsafe { w.bits(memory.as_ptr() as u32) });
+        st.ndtr.write(|w| unsafe { w.bits(items as u32) });
+
+        let fcr = match config.fifo {
+            Some(threshold) if config.error_interrupt => FCR_DMDIS | FCR_FEIE | threshold as u32,
+            Some(threshold) => FCR_DMDIS | threshold as u32,
+            None => 0,
+        };
+        st.fcr.write(|w| unsafe { w.bits(fcr) });
+
+        let mut cr = ((channel as u32) << 25)
+            | ((config.priority as u32) << 16)
+            | (W::SIZE << 13) // MSIZE
+            | (P::SIZE << 11) // PSIZE
+            | CR_MINC
+            | (direction << 6);
+        if config.circular {
+            cr |= CR_CIRC;
+        }
+        if config.peripheral_increment {
+            cr |= CR_PINC;
+        }
+        if config.half_transfer_interrupt {
+            cr |= CR_HTIE;
+        }
+        if config.transfer_complete_interrupt {
+            cr |= CR_TCIE;
+        }
+        if config.error_interrupt {
+            cr |= CR_TEIE | CR_DMEIE;
+        }
+        st.cr.write(|w| unsafe { w.bits(cr) });
+        Ok(())
+    }
+}
+
+// DMA のストリームをまとめて取り出す
+pub struct Streams<DMA> {
+    pub s0: Stream<DMA, 0>,
+    pub s1: Stream<DMA, 1>,
+    pub s2: Stream<DMA, 2>,
+    pub s3: Stream<DMA, 3>,
+    pub s4: Stream<DMA, 4>,
+    pub s5: Stream<DMA, 5>,
+    pub s6: Stream<DMA, 6>,
+    pub s7: Stream<DMA, 7>,
+}
+
+// DMA にクロックを供給してストリームに分ける
+pub fn split<DMA: Instance>(_dma: DMA, rcc: &stm32f446::RCC) -> Streams<DMA> {
+    DMA::enable_clock(rcc);
+    Streams {
+        s0: Stream { _dma: PhantomData },
+        s1: Stream { _dma: PhantomData },
+        s2: Stream { _dma: PhantomData },
+        s3: Stream { _dma: PhantomData },
+        s4: Stream { _dma: PhantomData },
+        s5: Stream { _dma: PhantomData },
+        s6: Stream { _dma: PhantomData },
+        s7: Stream { _dma: PhantomData },
+    }
+}
+
+// DMA の転送（ストリームとバッファを所有する）
+pub struct Transfer<DMA, const N: usize, BUF> {
+    stream: Stream<DMA, N>,
+    buffer: BUF,
+    fifo: bool,
+    // 転送データ数（stop() で NDTR を戻す値）
+    items: u16,
+}
+
+impl<DMA: Instance, const N: usize, W: Word> Transfer<DMA, N, &'static mut [W]> {
+    // ペリフェラルのレジスタ(register)からバッファへ転送する
+    pub fn peripheral_to_memory<REQ: Request<DMA, N>, R>(
+        mut stream: Stream<DMA, N>,
+        _request: REQ,
+        register: &R,
+        buffer: &'static mut [W],
+        config: &Config,
+    ) -> Result<Self, Error> {
+        stream.configure(REQ::CHANNEL, 0b00, address(register), buffer, config)?;
+        Ok(Transfer {
+            items: stream.remaining(),
+            stream,
+            buffer,
+            fifo: config.fifo.is_some(),
+        })
+    }
+}
+
+impl<DMA: Instance, const N: usize, W: Word> Transfer<DMA, N, &'static [W]> {
+    // バッファからペリフェラルのレジスタ(register)へ転送する
+    pub fn memory_to_peripheral<REQ: Request<DMA, N>, R>(
+        mut stream: Stream<DMA, N>,
+        _request: REQ,
+        register: &R,
+        buffer: &'static [W],
+        config: &Config,
+    ) -> Result<Self, Error> {
+        stream.configure(REQ::CHANNEL, 0b01, address(register), buffer, config)?;
+        Ok(Transfer {
+            items: stream.remaining(),
+            stream,
+            buffer,
+            fifo: config.fifo.is_some(),
+        })
+    }
+}
+
+impl<DMA: Instance, const N: usize, BUF> Transfer<DMA, N, BUF> {
+    // 転送開始
+    pub fn start(&mut self) {
+        self.stream.enable();
+    }
+
+    // 転送を止める（start() で再開するとバッファの先頭からやり直しになる）
+    // EN を落とした後に入れ直しても、M0AR/PAR は進んでいないので途中からの続きにはできない。
+    // そのため NDTR も最初の転送データ数に戻しておく。
+    pub fn stop(&mut self) {
+        self.stream.disable();
+        Stream::<DMA, N>::st()
+            .ndtr
+            .write(|w| unsafe { w.bits(self.items as u32) });
+        compiler_fence(Ordering::Acquire);
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.stream.flags()
+    }
+
+    pub fn clear_flags(&mut self, flags: Flags) {
+        self.stream.clear_flags(flags);
+    }
+
+    // エラーフラグを確認してクリアする（FIFO エラーは FIFO を使っている場合のみ）
+    pub fn check_errors(&mut self) -> Result<(), Error> {
+        self.stream.check_errors(self.fifo)
+    }
+
+    // 転送完了（循環モードでは1周ごと）
+    pub fn is_complete(&self) -> bool {
+        self.stream.flags().is_transfer_complete()
+    }
+
+    pub fn remaining(&self) -> u16 {
+        self.stream.remaining()
+    }
+
+    // 転送完了かエラーまで待つ（循環モードでは1周分）
+    pub fn wait(&mut self) -> Result<(), Error> {
+        loop {
+            self.check_errors()?;
+            let flags = self.stream.flags();
+            if flags.is_transfer_complete() {
+                self.stream.clear_flags(Flags(TCIF | HTIF));
+                compiler_fence(Ordering::Acquire);
+                return Ok(());
+            }
+        }
+    }
+
+    // 転送を止めて、ストリームとバッファを返す
+    pub fn release(mut self) -> (Stream<DMA, N>, BUF) {
+        self.stop();
+        self.stream.clear_all_flags();
+        (self.stream, self.buffer)
+    }
+}
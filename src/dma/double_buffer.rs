@@ -0,0 +1,182 @@
+// ダブルバッファモード(DBM)の DMA 転送
+// 2つのバッファ(M0AR/M1AR)を DMA が交互に使い、1つ埋まる(出し終わる)たびに TC 割り込みが入る。
+// 割り込みでは DMA が使っていない側のバッファ（CT が指していない方）をアプリに渡すので、
+// ADC の取り込み、DAC の出力、UART の受信などを途切れずに続けられる。
+// アプリの処理中に DMA がもう一方のバッファも終えてしまった場合（処理が間に合わない）は Overrun を返す。
+// TC ごとに CT が切り替わるはずなので、次の TC の後の CT を覚えておき、割り込みの遅れで TC を取りこぼした場合も検出する。
+// ただし CT は2つの値しか取らないので、分かるのは取りこぼした TC が奇数回の場合だけ（偶数回は区別できない）。
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use super::request::Request;
+use super::{address, Channel, Config, Error, Flags, Instance, Stream, Word, CR_CT, CR_DBM, TCIF};
+
+// DMA が使っている/アプリに渡すバッファ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Memory {
+    // M0AR（buffers[0]）
+    M0,
+    // M1AR（buffers[1]）
+    M1,
+}
+
+impl Memory {
+    fn index(self) -> usize {
+        match self {
+            Memory::M0 => 0,
+            Memory::M1 => 1,
+        }
+    }
+
+    fn other(self) -> Memory {
+        match self {
+            Memory::M0 => Memory::M1,
+            Memory::M1 => Memory::M0,
+        }
+    }
+}
+
+pub struct DoubleBuffer<DMA, const N: usize, W: 'static> {
+    stream: Stream<DMA, N>,
+    buffers: [&'static mut [W]; 2],
+    fifo: bool,
+    // 次の TC の後に DMA が使っているはずのバッファ
+    next: Memory,
+}
+
+impl<DMA: Instance, const N: usize, W: Word> DoubleBuffer<DMA, N, W> {
+    // ペリフェラルのレジスタ(register)から2つのバッファへ交互に転送する
+    pub fn peripheral_to_memory<REQ: Request<DMA, N>, R>(
+        stream: Stream<DMA, N>,
+        _request: REQ,
+        register: &R,
+        buffers: [&'static mut [W]; 2],
+        config: &Config,
+    ) -> Result<Self, Error> {
+        Self::new(
+            stream,
+            REQ::CHANNEL,
+            0b00,
+            address(register),
+            buffers,
+            config,
+        )
+    }
+
+    // 2つのバッファから交互にペリフェラルのレジスタ(register)へ転送する
+    // 最初は M0 から出力するので、start() の前に両方のバッファを埋めておくこと。
+    pub fn memory_to_peripheral<REQ: Request<DMA, N>, R>(
+        stream: Stream<DMA, N>,
+        _request: REQ,
+        register: &R,
+        buffers: [&'static mut [W]; 2],
+        config: &Config,
+    ) -> Result<Self, Error> {
+        Self::new(
+            stream,
+            REQ::CHANNEL,
+            0b01,
+            address(register),
+            buffers,
+            config,
+        )
+    }
+
+    fn new(
+        mut stream: Stream<DMA, N>,
+        channel: Channel,
+        direction: u32,
+        peripheral: u32,
+        buffers: [&'static mut [W]; 2],
+        config: &Config,
+    ) -> Result<Self, Error> {
+        // 両方のバッファは同じ長さ（NDTR は共通）
+        if buffers[0].len() != buffers[1].len() {
+            return Err(Error::InvalidBuffer);
+        }
+        stream.configure(channel, direction, peripheral, buffers[0], config)?;
+        let st = Stream::<DMA, N>::st();
+        st.m1ar
+            .write(|w| unsafe { w.bits(buffers[1].as_ptr() as u32) });
+        // DBM では循環モードも有効になる。CT = 0 (M0 から開始)
+        st.cr
+            .modify(|r, w| unsafe { w.bits((r.bits() | CR_DBM) & !CR_CT) });
+        Ok(DoubleBuffer {
+            stream,
+            buffers,
+            fifo: config.fifo.is_some(),
+            next: Memory::M1,
+        })
+    }
+
+    pub fn start(&mut self) {
+        self.stream.enable();
+    }
+
+    pub fn stop(&mut self) {
+        self.stream.disable();
+        compiler_fence(Ordering::Acquire);
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.stream.flags()
+    }
+
+    // DMA が今使っているバッファ (SxCR.CT)
+    pub fn current(&self) -> Memory {
+        if Stream::<DMA, N>::st().cr.read().bits() & CR_CT != 0 {
+            Memory::M1
+        } else {
+            Memory::M0
+        }
+    }
+
+    // アプリに渡せるバッファがあるか（TC フラグ）
+    pub fn is_ready(&self) -> bool {
+        self.stream.flags().is_transfer_complete()
+    }
+
+    // DMA のストリーム割り込み（またはポーリング）から呼ぶ
+    // TC が立っていれば、DMA が使い終わった側のバッファを f に渡して Ok(true) を返す。
+    // f の中では、受信なら届いたデータを読み、送信なら次に出すデータを書く。
+    // f の処理中に DMA がもう一方のバッファを終えて f に渡した側に進んだ場合は Err(Overrun)。
+    // 呼ばれるのが遅れて前回から CT が偶数回（2回、4回…）切り替わっていた場合も、f を呼ばずに Err(Overrun)。
+    // （その間にバッファは DMA に上書き/出力されているので、データの欠落として扱うこと）
+    // 3回、5回…切り替わった場合は1回と区別できないので検出できない。割り込みの遅れは1バッファ分の時間より短くすること。
+    pub fn on_interrupt<F: FnOnce(Memory, &mut [W])>(&mut self, f: F) -> Result<bool, Error> {
+        self.stream.check_errors(self.fifo)?;
+        if !self.stream.flags().is_transfer_complete() {
+            return Ok(false);
+        }
+        self.stream.clear_flags(Flags(TCIF));
+
+        let active = self.current();
+        if active != self.next {
+            // 切り替わりの回数が合わないので、どちらのバッファが使い終わったものか分からない
+            // （next はそのままにしておけば、次の TC で CT と合う）
+            return Err(Error::Overrun);
+        }
+        self.next = active.other();
+        let inactive = active.other();
+        // DMA が書いた内容をこの後で読む
+        compiler_fence(Ordering::Acquire);
+        f(inactive, &mut *self.buffers[inactive.index()]);
+        // アプリが書いた内容を DMA が読む前に済ませる
+        compiler_fence(Ordering::Release);
+
+        if self.current() != active {
+            return Err(Error::Overrun);
+        }
+        Ok(true)
+    }
+
+    // 転送を止めて、ストリームと2つのバッファを返す
+    pub fn release(mut self) -> (Stream<DMA, N>, [&'static mut [W]; 2]) {
+        self.stop();
+        self.stream.clear_all_flags();
+        let st = Stream::<DMA, N>::st();
+        st.cr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(CR_DBM | CR_CT)) });
+        (self.stream, self.buffers)
+    }
+}
@@ -0,0 +1,425 @@
+// DMA2 によるメモリ間の転送（コピー、フィル）
+// メモリ間転送ができるのは DMA2 だけ。FIFO は必須で、バーストは FIFO のしきい値に収まる範囲で使う。
+// ・転送の単位(バイト/ハーフワード/ワード)は、転送元と転送先のアドレスのアラインメントから決める。
+// ・バーストはアドレスがバーストの大きさに揃っている場合だけ使う（1KB 境界をまたがないように）。
+// ・NDTR(最大 65535 個)に収まらない分は分けて転送し、バーストの端数と単位未満の端数は CPU でコピーする。
+// ・cpu_threshold バイト未満は DMA の設定の方が遅いので、最初から CPU でコピーする。
+//
+// copy()/fill() は完了まで待つ。copy_async()/fill_async() は 'static なバッファを所有する MemoryTransfer を返し、
+// update() を呼ぶか（ポーリングや TC 割り込みから）、Future として await すれば進む。
+// フィルは先頭の 16 バイトを CPU で埋めて、そこを転送元（アドレス固定）にして残りを DMA で埋める。
+
+use core::future::Future;
+use core::mem::{size_of, size_of_val};
+use core::pin::Pin;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::{Context, Poll};
+
+use stm32f4::stm32f446::DMA2;
+
+use super::{
+    Error, FifoThreshold, Flags, Priority, Stream, Word, CR_MINC, CR_PINC, CR_TCIE, CR_TEIE,
+    FCR_DMDIS, TCIF,
+};
+
+const FIFO_BYTES: usize = 16;
+const MAX_ITEMS: usize = u16::MAX as usize;
+
+// バースト (SxCR.MBURST/PBURST)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Burst {
+    Single = 0b00,
+    Incr4 = 0b01,
+    Incr8 = 0b10,
+    Incr16 = 0b11,
+}
+
+impl Burst {
+    pub const fn beats(self) -> usize {
+        match self {
+            Burst::Single => 1,
+            Burst::Incr4 => 4,
+            Burst::Incr8 => 8,
+            Burst::Incr16 => 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryConfig {
+    pub priority: Priority,
+    // 使ってよい最大のバースト（転送の単位やアドレスによっては小さくする）
+    pub burst: Burst,
+    pub fifo_threshold: FifoThreshold,
+    // これより小さい転送(バイト数)は CPU でコピーする
+    pub cpu_threshold: usize,
+    // 転送完了/エラーで割り込みを出す（MemoryTransfer::update() を ISR から呼ぶ場合）
+    pub interrupt: bool,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig {
+            priority: Priority::Medium,
+            burst: Burst::Incr4,
+            fifo_threshold: FifoThreshold::Full,
+            cpu_threshold: 64,
+            interrupt: false,
+        }
+    }
+}
+
+impl MemoryConfig {
+    // 転送の単位が size バイトで、転送元/転送先のアドレスが src/dst の場合に使えるバースト
+    // バーストのバイト数が FIFO のしきい値を割り切れること（RM0390 の FIFO threshold の表）と、
+    // アドレスがバーストのバイト数に揃っていること。
+    fn burst_for(&self, size: usize, src: usize, dst: usize) -> Burst {
+        let threshold = (self.fifo_threshold as usize + 1) * FIFO_BYTES / 4;
+        [Burst::Incr16, Burst::Incr8, Burst::Incr4]
+            .iter()
+            .copied()
+            .filter(|burst| burst.beats() <= self.burst.beats())
+            .find(|burst| {
+                let bytes = burst.beats() * size;
+                threshold.is_multiple_of(bytes)
+                    && src.is_multiple_of(bytes)
+                    && dst.is_multiple_of(bytes)
+            })
+            .unwrap_or(Burst::Single)
+    }
+}
+
+// 進行中の転送（アドレスはバイト単位）
+#[derive(Clone, Copy, Debug)]
+struct Job {
+    // フィルの場合は転送元を動かさない
+    fill: bool,
+    src: usize,
+    dst: usize,
+    len: usize,
+    // 転送の単位(バイト)とバースト
+    size: usize,
+    burst: Burst,
+}
+
+impl Job {
+    fn new(config: &MemoryConfig, fill: bool, src: usize, dst: usize, len: usize) -> Self {
+        let mut job = Job {
+            fill,
+            src,
+            dst,
+            len,
+            size: 1,
+            burst: Burst::Single,
+        };
+        if !fill {
+            job.align_head();
+        }
+        let align = if fill { job.dst } else { job.src | job.dst };
+        let size = if align % 4 == 0 {
+            4
+        } else if align % 2 == 0 {
+            2
+        } else {
+            1
+        };
+        job.size = size;
+        job.burst = config.burst_for(size, if fill { 0 } else { job.src }, job.dst);
+        job
+    }
+
+    // 転送元と転送先のずれが同じなら、先頭の端数を CPU でコピーしてアドレスを揃える
+    // （16 バイトに揃えばバーストも使える）
+    fn align_head(&mut self) {
+        let head = [FIFO_BYTES, 4, 2]
+            .iter()
+            .find(|&&align| self.src % align == self.dst % align)
+            .map(|&align| (align - self.dst % align) % align)
+            .unwrap_or(0)
+            .min(self.len);
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.src as *const u8, self.dst as *mut u8, head);
+        }
+        self.src += head;
+        self.dst += head;
+        self.len -= head;
+    }
+
+    // 次に DMA で転送する個数（バーストの倍数、0 なら残りは CPU で）
+    fn next_items(&self) -> usize {
+        let beats = self.burst.beats();
+        (self.len / self.size).min(MAX_ITEMS) / beats * beats
+    }
+
+    // 残りを CPU で転送する
+    fn finish_on_cpu(&mut self) {
+        unsafe {
+            if self.fill {
+                // 転送元(先頭の 16 バイト)と同じ位置関係で埋める
+                let offset = self.dst - self.src;
+                for i in 0..self.len {
+                    let value = *((self.src + (offset + i) % 4) as *const u8);
+                    *((self.dst + i) as *mut u8) = value;
+                }
+            } else {
+                core::ptr::copy_nonoverlapping(
+                    self.src as *const u8,
+                    self.dst as *mut u8,
+                    self.len,
+                );
+            }
+        }
+        self.len = 0;
+    }
+}
+
+// DMA2 のストリームでメモリ間の転送をする
+pub struct MemoryDma<const N: usize> {
+    stream: Stream<DMA2, N>,
+    config: MemoryConfig,
+}
+
+impl<const N: usize> MemoryDma<N> {
+    pub fn new(stream: Stream<DMA2, N>, config: &MemoryConfig) -> Self {
+        MemoryDma {
+            stream,
+            config: *config,
+        }
+    }
+
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
+
+    // src から dst へコピーして、完了まで待つ（長さが違えば InvalidBuffer）
+    pub fn copy<W: Word>(&mut self, src: &[W], dst: &mut [W]) -> Result<(), Error> {
+        if src.len() != dst.len() {
+            return Err(Error::InvalidBuffer);
+        }
+        let len = size_of_val(src);
+        if len < self.config.cpu_threshold {
+            dst.copy_from_slice(src);
+            return Ok(());
+        }
+        let mut job = Job::new(
+            &self.config,
+            false,
+            src.as_ptr() as usize,
+            dst.as_mut_ptr() as usize,
+            len,
+        );
+        self.run(&mut job)
+    }
+
+    // dst を value で埋めて、完了まで待つ
+    pub fn fill<W: Word>(&mut self, dst: &mut [W], value: W) -> Result<(), Error> {
+        match fill_head(&self.config, dst, value) {
+            Some(mut job) => self.run(&mut job),
+            None => Ok(()),
+        }
+    }
+
+    // src から dst へのコピーを開始する（完了は MemoryTransfer で確認）
+    pub fn copy_async<W: Word>(
+        mut self,
+        src: &'static [W],
+        dst: &'static mut [W],
+    ) -> Result<MemoryTransfer<N, &'static [W], &'static mut [W]>, Error> {
+        if src.len() != dst.len() {
+            return Err(Error::InvalidBuffer);
+        }
+        let len = size_of_val(src);
+        let job = if len < self.config.cpu_threshold {
+            dst.copy_from_slice(src);
+            None
+        } else {
+            let mut job = Job::new(
+                &self.config,
+                false,
+                src.as_ptr() as usize,
+                dst.as_mut_ptr() as usize,
+                len,
+            );
+            self.start(&mut job).then_some(job)
+        };
+        Ok(MemoryTransfer {
+            dma: self,
+            job,
+            src,
+            dst,
+        })
+    }
+
+    // dst を value で埋め始める（完了は MemoryTransfer で確認）
+    pub fn fill_async<W: Word>(
+        mut self,
+        dst: &'static mut [W],
+        value: W,
+    ) -> MemoryTransfer<N, (), &'static mut [W]> {
+        let job = match fill_head(&self.config, dst, value) {
+            Some(mut job) => self.start(&mut job).then_some(job),
+            None => None,
+        };
+        MemoryTransfer {
+            dma: self,
+            job,
+            src: (),
+            dst,
+        }
+    }
+
+    pub fn release(mut self) -> Stream<DMA2, N> {
+        self.stream.disable();
+        self.stream.clear_all_flags();
+        self.stream
+    }
+
+    // 最後まで転送して待つ
+    fn run(&mut self, job: &mut Job) -> Result<(), Error> {
+        while self.start(job) {
+            while !self.poll(job)? {}
+        }
+        Ok(())
+    }
+
+    // 次の分を DMA で開始する（残りを CPU で済ませた場合は false）
+    fn start(&mut self, job: &mut Job) -> bool {
+        let items = job.next_items();
+        if items == 0 {
+            job.finish_on_cpu();
+            compiler_fence(Ordering::SeqCst);
+            return false;
+        }
+
+        self.stream.disable();
+        self.stream.clear_all_flags();
+        let st = Stream::<DMA2, N>::st();
+        st.par.write(|w| unsafe { w.bits(job.src as u32) });
+        st.m0ar.write(|w| unsafe { w.bits(job.dst as u32) });
+        st.ndtr.write(|w| unsafe { w.bits(items as u32) });
+        st.fcr
+            .write(|w| unsafe { w.bits(FCR_DMDIS | self.config.fifo_threshold as u32) });
+
+        let size = job.size.trailing_zeros(); // 0: バイト, 1: ハーフワード, 2: ワード
+        let mut cr = ((self.config.priority as u32) << 16)
+            | ((job.burst as u32) << 23) // MBURST
+            | ((job.burst as u32) << 21) // PBURST
+            | (size << 13) // MSIZE
+            | (size << 11) // PSIZE
+            | CR_MINC
+            | (0b10 << 6); // メモリからメモリ
+        if !job.fill {
+            cr |= CR_PINC;
+        }
+        if self.config.interrupt {
+            cr |= CR_TCIE | CR_TEIE;
+        }
+        st.cr.write(|w| unsafe { w.bits(cr) });
+
+        let bytes = items * job.size;
+        if !job.fill {
+            job.src += bytes;
+        }
+        job.dst += bytes;
+        job.len -= bytes;
+        self.stream.enable();
+        true
+    }
+
+    // 今の分が終わったか（エラーなら止める）
+    fn poll(&mut self, job: &mut Job) -> Result<bool, Error> {
+        if let Err(error) = self.stream.check_errors(false) {
+            self.stream.disable();
+            job.len = 0;
+            return Err(error);
+        }
+        if !self.stream.flags().is_transfer_complete() {
+            return Ok(false);
+        }
+        self.stream.clear_flags(Flags(TCIF));
+        compiler_fence(Ordering::Acquire);
+        Ok(true)
+    }
+}
+
+// フィルの先頭(最大 16 バイト)を CPU で埋めて、残りを DMA で埋める Job を返す（全部 CPU で埋めた場合は None）
+fn fill_head<W: Word>(config: &MemoryConfig, dst: &mut [W], value: W) -> Option<Job> {
+    let len = size_of_val(dst);
+    if len < config.cpu_threshold.max(2 * FIFO_BYTES) {
+        dst.iter_mut().for_each(|item| *item = value);
+        return None;
+    }
+    let (head, _) = dst.split_at_mut(FIFO_BYTES / size_of::<W>());
+    head.iter_mut().for_each(|item| *item = value);
+    let origin = dst.as_mut_ptr() as usize;
+    Some(Job::new(
+        config,
+        true,
+        origin,
+        origin + FIFO_BYTES,
+        len - FIFO_BYTES,
+    ))
+}
+
+// 非同期のメモリ間転送（転送元/転送先のバッファを所有する）
+pub struct MemoryTransfer<const N: usize, SRC, DST> {
+    dma: MemoryDma<N>,
+    // None なら完了
+    job: Option<Job>,
+    src: SRC,
+    dst: DST,
+}
+
+impl<const N: usize, SRC, DST> MemoryTransfer<N, SRC, DST> {
+    // 転送を進める（DMA の分が終わっていれば次を開始する）。完了したら true
+    // ポーリングするか、MemoryConfig::interrupt を有効にして DMA2 のストリーム割り込みから呼ぶ。
+    pub fn update(&mut self) -> Result<bool, Error> {
+        let job = match self.job.as_mut() {
+            Some(job) => job,
+            None => return Ok(true),
+        };
+        let result = match self.dma.poll(job) {
+            Ok(true) => Ok(!self.dma.start(job)),
+            Ok(false) => Ok(false),
+            Err(error) => Err(error),
+        };
+        if result != Ok(false) {
+            self.job = None;
+        }
+        result
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.job.is_none()
+    }
+
+    // 完了まで待つ
+    pub fn wait(&mut self) -> Result<(), Error> {
+        while !self.update()? {}
+        Ok(())
+    }
+
+    // 転送を止めて、MemoryDma とバッファを返す
+    pub fn release(mut self) -> (MemoryDma<N>, SRC, DST) {
+        self.dma.stream.disable();
+        compiler_fence(Ordering::Acquire);
+        (self.dma, self.src, self.dst)
+    }
+}
+
+// async fn の中で transfer.await できるようにする
+// 割り込みで起こす仕組みは無いので、Pending のたびにすぐ起こし直す（実行器がポーリングする）。
+impl<const N: usize, SRC: Unpin, DST: Unpin> Future for MemoryTransfer<N, SRC, DST> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut().update() {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(error) => Poll::Ready(Err(error)),
+        }
+    }
+}
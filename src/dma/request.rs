@@ -0,0 +1,168 @@
+// DMA 要求の割り当て表（RM0390 の DMA1/DMA2 request mapping）
+// 要求（Tim2Up、Usart2Rx など）ごとに型があり、使えるストリームにだけ Request<DMA, ストリーム番号> を実装している。
+// Transfer を作るときに要求の型を渡すので、表に無い組み合わせはコンパイルエラーになる。
+// チャンネル(CHSEL)は Request::CHANNEL から決まる。使えるストリームの一覧は Mapping::STREAMS で確認できる。
+//
+// 表の1マスに複数の要求が書かれているもの（TIM2_UP/TIM2_CH3 など）は、それぞれの要求に同じストリームを割り当てている。
+// TIM1_CH1/CH2/CH3 のように1つの要求としてまとめられているものは、まとめた型(Tim1Ch1Ch2Ch3)にしている。
+
+use super::{Channel, Instance};
+use stm32f4::stm32f446::{DMA1, DMA2};
+
+// DMA 要求が DMA のストリーム STREAM で使えること
+pub trait Request<DMA, const STREAM: usize> {
+    // SxCR.CHSEL に設定するチャンネル
+    const CHANNEL: Channel;
+}
+
+// 割り当て表の1マス
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    // 1: DMA1, 2: DMA2
+    pub dma: u8,
+    pub stream: usize,
+    pub channel: Channel,
+}
+
+// 要求に使えるストリームとチャンネルの一覧
+pub trait Mapping {
+    const NAME: &'static str;
+    const STREAMS: &'static [Slot];
+}
+
+macro_rules! requests {
+    ($($name:ident => [$(($dma:ident, $stream:literal, $channel:ident)),+ $(,)?],)+) => {
+        $(
+            #[derive(Clone, Copy, Debug)]
+            pub struct $name;
+
+            impl Mapping for $name {
+                const NAME: &'static str = stringify!($name);
+                const STREAMS: &'static [Slot] = &[$(Slot {
+                    dma: <$dma as Instance>::NUMBER,
+                    stream: $stream,
+                    channel: Channel::$channel,
+                }),+];
+            }
+
+            $(
+                impl Request<$dma, $stream> for $name {
+                    const CHANNEL: Channel = Channel::$channel;
+                }
+            )+
+        )+
+    };
+}
+
+// DMA1
+requests! {
+    // チャンネル0
+    Spi3Rx => [(DMA1, 0, C0), (DMA1, 2, C0)],
+    SpdifrxDt => [(DMA1, 1, C0)],
+    Spi2Rx => [(DMA1, 3, C0)],
+    Spi2Tx => [(DMA1, 4, C0)],
+    Spi3Tx => [(DMA1, 5, C0), (DMA1, 7, C0)],
+    SpdifrxCs => [(DMA1, 6, C0)],
+    // チャンネル1
+    I2c1Rx => [(DMA1, 0, C1), (DMA1, 5, C1)],
+    I2c3Rx => [(DMA1, 1, C1), (DMA1, 2, C3)],
+    Tim7Up => [(DMA1, 2, C1), (DMA1, 4, C1)],
+    I2c1Tx => [(DMA1, 6, C1), (DMA1, 7, C1)],
+    Fmpi2c1Rx => [(DMA1, 3, C1), (DMA1, 0, C7)],
+    Fmpi2c1Tx => [(DMA1, 1, C2), (DMA1, 7, C4)],
+    // チャンネル2
+    Tim4Ch1 => [(DMA1, 0, C2)],
+    Tim4Ch2 => [(DMA1, 3, C2)],
+    Tim4Up => [(DMA1, 6, C2)],
+    Tim4Ch3 => [(DMA1, 7, C2)],
+    // チャンネル3
+    Tim2Up => [(DMA1, 1, C3), (DMA1, 7, C3)],
+    Tim2Ch3 => [(DMA1, 1, C3)],
+    I2c3Tx => [(DMA1, 4, C3)],
+    Tim2Ch1 => [(DMA1, 5, C3)],
+    Tim2Ch2 => [(DMA1, 6, C3)],
+    Tim2Ch4 => [(DMA1, 6, C3), (DMA1, 7, C3)],
+    // チャンネル4
+    Uart5Rx => [(DMA1, 0, C4)],
+    Usart3Rx => [(DMA1, 1, C4)],
+    Uart4Rx => [(DMA1, 2, C4)],
+    Usart3Tx => [(DMA1, 3, C4), (DMA1, 4, C7)],
+    Uart4Tx => [(DMA1, 4, C4)],
+    Usart2Rx => [(DMA1, 5, C4)],
+    Usart2Tx => [(DMA1, 6, C4)],
+    Uart5Tx => [(DMA1, 7, C4)],
+    // チャンネル5
+    Tim3Ch4 => [(DMA1, 2, C5)],
+    Tim3Up => [(DMA1, 2, C5)],
+    Tim3Ch1 => [(DMA1, 4, C5)],
+    Tim3Trig => [(DMA1, 4, C5)],
+    Tim3Ch2 => [(DMA1, 5, C5)],
+    Tim3Ch3 => [(DMA1, 7, C5)],
+    // チャンネル6
+    Tim5Ch3 => [(DMA1, 0, C6)],
+    Tim5Up => [(DMA1, 0, C6), (DMA1, 6, C6)],
+    Tim5Ch4 => [(DMA1, 1, C6), (DMA1, 3, C6)],
+    Tim5Trig => [(DMA1, 1, C6), (DMA1, 3, C6)],
+    Tim5Ch1 => [(DMA1, 2, C6)],
+    Tim5Ch2 => [(DMA1, 4, C6)],
+    // チャンネル7
+    Tim6Up => [(DMA1, 1, C7)],
+    I2c2Rx => [(DMA1, 2, C7), (DMA1, 3, C7)],
+    Dac1 => [(DMA1, 5, C7)],
+    Dac2 => [(DMA1, 6, C7)],
+    I2c2Tx => [(DMA1, 7, C7)],
+}
+
+// DMA2
+requests! {
+    // チャンネル0
+    Adc1 => [(DMA2, 0, C0), (DMA2, 4, C0)],
+    Sai1A => [(DMA2, 1, C0), (DMA2, 3, C0)],
+    Tim8Ch1Ch2Ch3 => [(DMA2, 2, C0)],
+    Sai1B => [(DMA2, 5, C0), (DMA2, 4, C1)],
+    Tim1Ch1Ch2Ch3 => [(DMA2, 6, C0)],
+    Sai2B => [(DMA2, 7, C0), (DMA2, 6, C3)],
+    // チャンネル1
+    Dcmi => [(DMA2, 1, C1), (DMA2, 7, C1)],
+    Adc2 => [(DMA2, 2, C1), (DMA2, 3, C1)],
+    // チャンネル2
+    Adc3 => [(DMA2, 0, C2), (DMA2, 1, C2)],
+    // チャンネル3
+    Spi1Rx => [(DMA2, 0, C3), (DMA2, 2, C3)],
+    Spi1Tx => [(DMA2, 3, C3), (DMA2, 5, C3)],
+    Sai2A => [(DMA2, 4, C3)],
+    Quadspi => [(DMA2, 7, C3)],
+    // チャンネル4
+    Spi4Rx => [(DMA2, 0, C4), (DMA2, 3, C5)],
+    Spi4Tx => [(DMA2, 1, C4), (DMA2, 4, C5)],
+    Usart1Rx => [(DMA2, 2, C4), (DMA2, 5, C4)],
+    Sdio => [(DMA2, 3, C4), (DMA2, 6, C4)],
+    Usart1Tx => [(DMA2, 7, C4)],
+    // チャンネル5
+    Usart6Rx => [(DMA2, 1, C5), (DMA2, 2, C5)],
+    Usart6Tx => [(DMA2, 6, C5), (DMA2, 7, C5)],
+    // チャンネル6
+    Tim1Trig => [(DMA2, 0, C6), (DMA2, 4, C6)],
+    Tim1Ch1 => [(DMA2, 1, C6), (DMA2, 3, C6)],
+    Tim1Ch2 => [(DMA2, 2, C6)],
+    Tim1Ch4 => [(DMA2, 4, C6)],
+    Tim1Com => [(DMA2, 4, C6)],
+    Tim1Up => [(DMA2, 5, C6)],
+    Tim1Ch3 => [(DMA2, 6, C6)],
+    // チャンネル7
+    Tim8Up => [(DMA2, 1, C7)],
+    Tim8Ch1 => [(DMA2, 2, C7)],
+    Tim8Ch2 => [(DMA2, 3, C7)],
+    Tim8Ch3 => [(DMA2, 4, C7)],
+    Tim8Ch4 => [(DMA2, 7, C7)],
+    Tim8Trig => [(DMA2, 7, C7)],
+    Tim8Com => [(DMA2, 7, C7)],
+}
+
+// 要求を DMA(1/2) のストリームで使う場合のチャンネル（使えなければ None。実行時に表を引く場合）
+pub fn channel_for<REQ: Mapping>(dma: u8, stream: usize) -> Option<Channel> {
+    REQ::STREAMS
+        .iter()
+        .find(|slot| slot.dma == dma && slot.stream == stream)
+        .map(|slot| slot.channel)
+}
@@ -0,0 +1,474 @@
+// ADC のサンプル列に使うディジタルフィルタ（固定小数点、ヒープ無し）
+// どのフィルタも Filter トレイトを実装していて、1サンプルずつ update() に渡すと出力を返す。
+// 間引き(デシメーション)するフィルタは、出力が無いサンプルでは None を返す。
+// then() でつなげばパイプラインにでき、feed() でスキャン DMA のブロックから1チャンネル分だけ流し込める。
+//
+// 入出力は u16（ADC の生の値）。内部の計算は整数のみで、丸めは四捨五入。
+
+pub trait Filter {
+    // 1サンプル入力して、出力があれば返す
+    fn update(&mut self, sample: u16) -> Option<u16>;
+
+    // 内部状態を初期化
+    fn reset(&mut self);
+
+    // input を順に通して output に書き、書いた個数を返す（output が足りなければそこで止める）
+    fn process(&mut self, input: &[u16], output: &mut [u16]) -> usize {
+        let mut written = 0;
+        for &sample in input {
+            if written == output.len() {
+                break;
+            }
+            if let Some(value) = self.update(sample) {
+                output[written] = value;
+                written += 1;
+            }
+        }
+        written
+    }
+
+    // スキャン DMA のブロック（チャンネルがインターリーブされている）から
+    // channel 番目(シーケンス内の位置)のサンプルだけを通し、出力ごとに f を呼ぶ
+    fn feed<F: FnMut(u16)>(&mut self, block: &[u16], channels: usize, channel: usize, mut f: F)
+    where
+        Self: Sized,
+    {
+        for &sample in block.iter().skip(channel).step_by(channels.max(1)) {
+            if let Some(value) = self.update(sample) {
+                f(value);
+            }
+        }
+    }
+
+    // 出力を次のフィルタにつなぐ
+    fn then<B: Filter>(self, next: B) -> Chain<Self, B>
+    where
+        Self: Sized,
+    {
+        Chain { first: self, next }
+    }
+}
+
+// 2つのフィルタを直列につないだもの
+pub struct Chain<A, B> {
+    first: A,
+    next: B,
+}
+
+impl<A: Filter, B: Filter> Filter for Chain<A, B> {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        self.first
+            .update(sample)
+            .and_then(|value| self.next.update(value))
+    }
+
+    fn reset(&mut self) {
+        self.first.reset();
+        self.next.reset();
+    }
+}
+
+// 移動平均（直近 N サンプルの平均）
+// 埋まるまでは入力済みのサンプルだけで平均する。
+pub struct MovingAverage<const N: usize> {
+    window: [u16; N],
+    index: usize,
+    len: usize,
+    sum: u32,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    pub const fn new() -> Self {
+        MovingAverage {
+            window: [0; N],
+            index: 0,
+            len: 0,
+            sum: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for MovingAverage<N> {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        if N == 0 {
+            return Some(sample);
+        }
+        if self.len == N {
+            self.sum -= self.window[self.index] as u32;
+        } else {
+            self.len += 1;
+        }
+        self.window[self.index] = sample;
+        self.sum += sample as u32;
+        self.index = (self.index + 1) % N;
+        let len = self.len as u32;
+        Some(((self.sum + len / 2) / len) as u16)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+// 指数移動平均 y += (x - y) / 2^shift
+// 内部は 16bit の小数部を持つ固定小数点(Q16)で保持する。最初のサンプルで初期化。
+pub struct Ema {
+    shift: u32,
+    state: Option<i64>,
+}
+
+impl Ema {
+    // shift は 0 ~ 16（大きいほど平滑化が強い。時定数は約 2^shift サンプル）
+    pub const fn new(shift: u32) -> Self {
+        Ema {
+            shift: if shift > 16 { 16 } else { shift },
+            state: None,
+        }
+    }
+}
+
+impl Filter for Ema {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        let x = (sample as i64) << 16;
+        // 差の 1/2^shift を四捨五入して足す（切り捨てだと shift = 16 で 1LSB 手前で止まる）
+        let round = if self.shift > 0 {
+            (1 << (self.shift - 1)) - 1
+        } else {
+            0
+        };
+        let y = match self.state {
+            Some(y) => y + ((x - y + round) >> self.shift),
+            None => x,
+        };
+        self.state = Some(y);
+        Some(((y + (1 << 15)) >> 16) as u16)
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+// メディアンフィルタ（直近 N サンプルの中央値、N は奇数にすること）
+// スパイク状のノイズを取り除くのに使う。埋まるまでは入力済みのサンプルの中央値。
+pub struct Median<const N: usize> {
+    window: [u16; N],
+    index: usize,
+    len: usize,
+}
+
+impl<const N: usize> Median<N> {
+    pub const fn new() -> Self {
+        Median {
+            window: [0; N],
+            index: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for Median<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Filter for Median<N> {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        if N == 0 {
+            return Some(sample);
+        }
+        self.window[self.index] = sample;
+        self.index = (self.index + 1) % N;
+        self.len = (self.len + 1).min(N);
+
+        // 窓のコピーを挿入ソート（N は小さい前提）
+        let mut sorted = [0u16; N];
+        sorted[..self.len].copy_from_slice(&self.window[..self.len]);
+        let sorted = &mut sorted[..self.len];
+        for i in 1..sorted.len() {
+            let mut j = i;
+            while j > 0 && sorted[j - 1] > sorted[j] {
+                sorted.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        Some(sorted[(sorted.len() - 1) / 2]) // 偶数個の間は小さい方
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+// CIC(積分器と櫛形フィルタを ORDER 段ずつ重ねた)デシメーションフィルタ
+// decimation サンプルごとに1つ出力する。利得は decimation^ORDER なので、出力は shift だけ右シフトする。
+// 内部は u32 の循環演算なので、12 + ORDER * log2(decimation) <= 32 の範囲で使うこと。
+pub struct Cic<const ORDER: usize> {
+    decimation: u32,
+    shift: u32,
+    integrators: [u32; ORDER],
+    combs: [u32; ORDER],
+    count: u32,
+}
+
+impl<const ORDER: usize> Cic<ORDER> {
+    pub const fn new(decimation: u32, shift: u32) -> Self {
+        Cic {
+            decimation: if decimation == 0 { 1 } else { decimation },
+            shift,
+            integrators: [0; ORDER],
+            combs: [0; ORDER],
+            count: 0,
+        }
+    }
+
+    // 出力を入力と同じスケールに戻すシフト量（decimation が 2 のべき乗の場合）
+    pub const fn unity_shift(decimation: u32) -> u32 {
+        ORDER as u32 * decimation.trailing_zeros()
+    }
+}
+
+impl<const ORDER: usize> Filter for Cic<ORDER> {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        let mut value = sample as u32;
+        for integrator in self.integrators.iter_mut() {
+            *integrator = integrator.wrapping_add(value);
+            value = *integrator;
+        }
+        self.count += 1;
+        if self.count < self.decimation {
+            return None;
+        }
+        self.count = 0;
+        for comb in self.combs.iter_mut() {
+            let previous = *comb;
+            *comb = value;
+            value = value.wrapping_sub(previous);
+        }
+        let round = if self.shift > 0 {
+            1 << (self.shift - 1)
+        } else {
+            0
+        };
+        Some(((value as u64 + round) >> self.shift).min(u16::MAX as u64) as u16)
+    }
+
+    fn reset(&mut self) {
+        self.integrators = [0; ORDER];
+        self.combs = [0; ORDER];
+        self.count = 0;
+    }
+}
+
+// オーバーサンプリングで分解能を extra_bits ビット増やす
+// 4^extra_bits サンプルを足して extra_bits だけ右シフトする（1段の CIC と同じ）。
+// 例えば 12bit の ADC で extra_bits = 2 なら 16 サンプルごとに 14bit の値を出力する。
+// （ノイズが 1LSB 程度以上ないと効果が無い。12 + extra_bits <= 16 まで）
+pub struct Oversample {
+    cic: Cic<1>,
+}
+
+impl Oversample {
+    pub const fn new(extra_bits: u32) -> Self {
+        let extra_bits = if extra_bits > 4 { 4 } else { extra_bits };
+        Oversample {
+            cic: Cic::new(1 << (2 * extra_bits), extra_bits),
+        }
+    }
+}
+
+impl Filter for Oversample {
+    fn update(&mut self, sample: u16) -> Option<u16> {
+        self.cic.update(sample)
+    }
+
+    fn reset(&mut self) {
+        self.cic.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 12bit のランプ + 疑似ノイズ
+    const INPUT: [u16; 32] = [
+        168, 408, 457, 558, 707, 745, 961, 1062, 1070, 1241, 1352, 1479, 1698, 1747, 1855, 2068,
+        2053, 2152, 2321, 2476, 2691, 2685, 2811, 2956, 3161, 3296, 3232, 3384, 3521, 3694, 3743,
+        3970,
+    ];
+
+    // 以下の参照出力は浮動小数点/直接畳み込みで別に計算したもの
+    const MOVING_AVERAGE_4: [u16; 32] = [
+        168, 288, 344, 398, 533, 617, 743, 869, 960, 1084, 1181, 1286, 1443, 1569, 1695, 1842,
+        1931, 2032, 2149, 2251, 2410, 2543, 2666, 2786, 2903, 3056, 3161, 3268, 3358, 3458, 3586,
+        3732,
+    ];
+    const EMA_2: [u16; 32] = [
+        168, 228, 285, 353, 442, 518, 628, 737, 820, 925, 1032, 1144, 1282, 1398, 1513, 1651, 1752,
+        1852, 1969, 2096, 2245, 2355, 2469, 2591, 2733, 2874, 2963, 3069, 3182, 3310, 3418, 3556,
+    ];
+    const MEDIAN_5: [u16; 32] = [
+        168, 168, 408, 408, 457, 558, 707, 745, 961, 1062, 1070, 1241, 1352, 1479, 1698, 1747,
+        1855, 2053, 2068, 2152, 2321, 2476, 2685, 2691, 2811, 2956, 3161, 3232, 3296, 3384, 3521,
+        3694,
+    ];
+    // 2次、4 サンプルごと、4bit シフト（最初の出力は入力前の 0 を含む）
+    const CIC_2_4: [u16; 8] = [211, 690, 1127, 1637, 2090, 2601, 3097, 3533];
+    // 1bit 増やす（4 サンプルごと）
+    const OVERSAMPLE_1: [u16; 8] = [796, 1738, 2571, 3684, 4501, 5572, 6537, 7464];
+
+    const FULL_SCALE: u16 = 4095;
+
+    fn assert_output<F: Filter>(mut filter: F, input: &[u16], expected: &[u16]) {
+        let mut output = [0u16; 64];
+        let written = filter.process(input, &mut output);
+        assert_eq!(&output[..written], expected);
+    }
+
+    // 同じ値を count 回入力して、出力を全部 f に渡す
+    fn constant<F: Filter>(filter: &mut F, value: u16, count: usize, mut f: impl FnMut(u16)) {
+        for _ in 0..count {
+            if let Some(output) = filter.update(value) {
+                f(output);
+            }
+        }
+    }
+
+    #[test]
+    fn moving_average_reference() {
+        assert_output(MovingAverage::<4>::new(), &INPUT, &MOVING_AVERAGE_4);
+    }
+
+    #[test]
+    fn moving_average_rounding_and_full_scale() {
+        // 1.5 は切り上げ、0.5 も切り上げ
+        assert_output(MovingAverage::<2>::new(), &[1, 2, 0, 1], &[1, 2, 1, 1]);
+
+        let mut filter = MovingAverage::<64>::new();
+        constant(&mut filter, FULL_SCALE, 200, |y| assert_eq!(y, FULL_SCALE));
+        // 窓の半分が 0 になったところで 2047.5 -> 2048
+        let mut last = 0;
+        constant(&mut filter, 0, 32, |y| last = y);
+        assert_eq!(last, 2048);
+        constant(&mut filter, 0, 32, |y| last = y);
+        assert_eq!(last, 0);
+    }
+
+    #[test]
+    fn ema_reference() {
+        assert_output(Ema::new(2), &INPUT, &EMA_2);
+        // shift = 0 はそのまま
+        assert_output(Ema::new(0), &INPUT[..4], &INPUT[..4]);
+    }
+
+    #[test]
+    fn ema_rounding_and_full_scale() {
+        // 0 -> 1 で 0.5 になり、切り上げて 1
+        assert_output(Ema::new(1), &[0, 1], &[0, 1]);
+
+        // 最も強い平滑化でも 0 からフルスケールまで単調に近づき、超えずに収束する
+        let mut filter = Ema::new(16);
+        filter.update(0);
+        let mut previous = 0;
+        constant(&mut filter, FULL_SCALE, 1_500_000, |y| {
+            assert!(y >= previous && y <= FULL_SCALE);
+            previous = y;
+        });
+        assert_eq!(previous, FULL_SCALE);
+        // 下がる方向も 0 まで戻る
+        constant(&mut filter, 0, 1_500_000, |y| previous = y);
+        assert_eq!(previous, 0);
+
+        let mut filter = Ema::new(4);
+        constant(&mut filter, FULL_SCALE, 1000, |y| assert_eq!(y, FULL_SCALE));
+    }
+
+    #[test]
+    fn median_reference() {
+        assert_output(Median::<5>::new(), &INPUT, &MEDIAN_5);
+    }
+
+    #[test]
+    fn median_removes_spikes() {
+        assert_output(
+            Median::<3>::new(),
+            &[100, 100, FULL_SCALE, 100, 100, 0, 100, 100],
+            &[100; 8],
+        );
+    }
+
+    #[test]
+    fn cic_reference() {
+        assert_output(Cic::<2>::new(4, 4), &INPUT, &CIC_2_4);
+        assert_eq!(Cic::<2>::unity_shift(4), 4);
+    }
+
+    #[test]
+    fn cic_full_scale_with_wrapping_integrators() {
+        // 3次、16 サンプルごと (12 + 3 * 4 = 24bit)
+        // 4000 サンプルで積分器は u32 を何度も回り込むが、出力は正しい
+        let mut filter = Cic::<3>::new(16, Cic::<3>::unity_shift(16));
+        let mut outputs = 0;
+        constant(&mut filter, FULL_SCALE, 4000, |y| {
+            // 最初の2つはウォームアップ中（インパルス応答の長さ 46 サンプル）
+            if outputs >= 2 {
+                assert_eq!(y, FULL_SCALE);
+            }
+            outputs += 1;
+        });
+        assert_eq!(outputs, 250);
+    }
+
+    #[test]
+    fn cic_saturates() {
+        // シフトしないと 4095 * 32 は u16 に収まらない
+        let mut filter = Cic::<1>::new(32, 0);
+        constant(&mut filter, FULL_SCALE, 32, |y| assert_eq!(y, u16::MAX));
+    }
+
+    #[test]
+    fn oversample_reference() {
+        assert_output(Oversample::new(1), &INPUT, &OVERSAMPLE_1);
+    }
+
+    #[test]
+    fn oversample_full_scale() {
+        let mut filter = Oversample::new(2);
+        constant(&mut filter, FULL_SCALE, 16, |y| assert_eq!(y, 16380));
+        // 16bit まで（4 より大きい値は 4 になる）
+        let mut filter = Oversample::new(8);
+        let mut outputs = 0;
+        constant(&mut filter, FULL_SCALE, 256, |y| {
+            assert_eq!(y, 65520);
+            outputs += 1;
+        });
+        assert_eq!(outputs, 1);
+    }
+
+    #[test]
+    fn chain_and_feed() {
+        // 2チャンネルのブロックから2番目だけを取り出す
+        let mut block = [0u16; 16];
+        for (i, sample) in block.iter_mut().enumerate() {
+            *sample = if i % 2 == 0 { 1000 } else { 2000 + i as u16 };
+        }
+        let mut filter = Median::<3>::new().then(Oversample::new(1));
+        let mut outputs = [0u16; 2];
+        let mut written = 0;
+        filter.feed(&block, 2, 1, |y| {
+            outputs[written] = y;
+            written += 1;
+        });
+        // メディアン: 2001, 2001, 2003, 2005, 2007, 2009, 2011, 2013
+        // 4つずつ足して 1bit シフト
+        assert_eq!(outputs, [4005, 4020]);
+    }
+}
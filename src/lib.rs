@@ -0,0 +1,19 @@
+// サンプル間で共有するドライバ類
+// examples から `stm32f446re_rust_example::xxx` として利用する。
+// ハードウェアに依存しない部分はホストでテストできる（cargo test --lib --target x86_64-unknown-linux-gnu）。
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod adc;
+pub mod clock;
+pub mod dac;
+pub mod dma;
+pub mod filter;
+pub mod log;
+pub mod profile;
+pub mod serial;
+pub mod shell;
+pub mod soft_timer;
+pub mod systick;
+pub mod table;
+pub mod timer;
@@ -0,0 +1,291 @@
+// レベルとモジュールごとのフィルタを持つログ出力
+// log!/error!/warn!/info!/debug!/trace! マクロで書式付きのメッセージを出力する。
+// 各行には起動からの時間（systick の TimeBase を開始していない場合は 0）、レベル、モジュール名が付く。
+//
+//   [    1.234567] INFO  my_app::sensor: adc = 1234
+//
+// 出力先は cargo の feature で選ぶ（複数選ぶと全部に出力する）。
+//   log-semihosting: hprintln! と同じホストの標準出力（デフォルト、デバッガが接続されていなければ出力しない）
+//   log-usart: USART に直接書き込む（NUCLEO では USART2 が ST-Link の仮想 COM ポート。usart::init() が必要）
+//   log-itm: ITM のスティミュラスポート0（SWO。openocd.gdb の tpiu/itm の設定を有効にする）
+//   log-rtt: RAM のリングバッファ（SEGGER RTT 互換の制御ブロック。probe-rs や openocd の rtt で読む）
+//
+//   cargo run --example log --no-default-features --features log-usart
+//
+// 1行は LINE_LEN バイトまで（超えた分は切り詰めて "..." を付ける）。
+// 割り込みからも使えるが、RTT 以外は行の途中に割り込みの出力が混ざることがある。
+// ターゲットで整形しないバイナリのログは deferred（defer_info! など）を使う。
+
+pub mod deferred;
+#[cfg(feature = "log-itm")]
+pub mod itm;
+#[cfg(feature = "log-rtt")]
+pub mod rtt;
+#[cfg(feature = "log-semihosting")]
+pub mod semihosting;
+#[cfg(feature = "log-usart")]
+pub mod usart;
+
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use cortex_m::interrupt::Mutex;
+
+use crate::systick;
+
+// 1行の最大長[byte]
+pub const LINE_LEN: usize = 128;
+// 登録できるモジュールごとのフィルタの数
+pub const MAX_FILTERS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    // 何も出力しない（フィルタ用）
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Off,
+            1 => Level::Error,
+            2 => Level::Warn,
+            3 => Level::Info,
+            4 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // フィルタの登録数が MAX_FILTERS を超えた
+    TooManyFilters,
+}
+
+// モジュール名の前方一致（"::" 区切り）で適用するレベル
+#[derive(Clone, Copy)]
+struct Filter {
+    module: &'static str,
+    level: Level,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+// フィルタも含めた一番詳細なレベル（これより詳細なものはフィルタを見ずに捨てる）
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static FILTERS: Mutex<RefCell<[Option<Filter>; MAX_FILTERS]>> =
+    Mutex::new(RefCell::new([None; MAX_FILTERS]));
+
+// 全体のレベル（フィルタに一致しないモジュールに適用、初期値は Info）
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    update_max_level();
+}
+
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+// module（"my_app::sensor" など）とその子モジュールのレベルを設定する
+// 複数のフィルタに一致する場合は、一番長いものが使われる。
+pub fn set_module_level(module: &'static str, level: Level) -> Result<(), Error> {
+    cortex_m::interrupt::free(|cs| {
+        let mut filters = FILTERS.borrow(cs).borrow_mut();
+        let index = filters
+            .iter()
+            .position(|f| matches!(f, Some(f) if f.module == module))
+            .or_else(|| filters.iter().position(|f| f.is_none()))
+            .ok_or(Error::TooManyFilters)?;
+        filters[index] = Some(Filter { module, level });
+        Ok(())
+    })?;
+    update_max_level();
+    Ok(())
+}
+
+// module のフィルタを消す
+pub fn clear_module_level(module: &str) {
+    cortex_m::interrupt::free(|cs| {
+        for filter in FILTERS.borrow(cs).borrow_mut().iter_mut() {
+            if matches!(filter, Some(f) if f.module == module) {
+                *filter = None;
+            }
+        }
+    });
+    update_max_level();
+}
+
+fn update_max_level() {
+    let max = cortex_m::interrupt::free(|cs| {
+        FILTERS
+            .borrow(cs)
+            .borrow()
+            .iter()
+            .flatten()
+            .map(|f| f.level)
+            .fold(level(), Level::max)
+    });
+    MAX_LEVEL.store(max as u8, Ordering::Relaxed);
+}
+
+// module が prefix そのものか、その子モジュールか
+fn is_in(module: &str, prefix: &str) -> bool {
+    match module.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+// module_path!() のモジュールで level のログを出力するか
+pub fn enabled(level: Level, module: &str) -> bool {
+    if level == Level::Off || level as u8 > MAX_LEVEL.load(Ordering::Relaxed) {
+        return false;
+    }
+    let filter = cortex_m::interrupt::free(|cs| {
+        FILTERS
+            .borrow(cs)
+            .borrow()
+            .iter()
+            .flatten()
+            .filter(|f| is_in(module, f.module))
+            .max_by_key(|f| f.module.len())
+            .map(|f| f.level)
+    });
+    level <= filter.unwrap_or_else(self::level)
+}
+
+// 1行分のバッファ（入りきらない分は捨てる）
+struct Line {
+    buffer: [u8; LINE_LEN],
+    len: usize,
+    truncated: bool,
+}
+
+impl Line {
+    fn new() -> Self {
+        Line {
+            buffer: [0; LINE_LEN],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    // 改行を付けて返す（切り詰めた場合は末尾を "..." にする）
+    fn finish(&mut self) -> &[u8] {
+        const MARK: &[u8] = b"...\n";
+        if self.truncated || self.len == LINE_LEN {
+            self.len = LINE_LEN - MARK.len();
+            self.buffer[self.len..].copy_from_slice(MARK);
+            self.len = LINE_LEN;
+        } else {
+            self.buffer[self.len] = b'\n';
+            self.len += 1;
+        }
+        &self.buffer[..self.len]
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(LINE_LEN - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        if count < s.len() {
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
+
+// 時刻、レベル、モジュール名を付けて1行を出力する（マクロから呼ばれる。レベルの確認は呼び出し側で済ませる）
+pub fn write(level: Level, module: &str, args: fmt::Arguments) {
+    let us = systick::uptime_us();
+    let mut line = Line::new();
+    write!(
+        line,
+        "[{:>5}.{:06}] {:<5} {}: ",
+        us / 1_000_000,
+        us % 1_000_000,
+        level.as_str(),
+        module
+    )
+    .ok();
+    line.write_fmt(args).ok();
+    write_bytes(line.finish());
+}
+
+// 有効な出力先すべてにそのまま書き込む
+pub fn write_bytes(bytes: &[u8]) {
+    #[cfg(feature = "log-semihosting")]
+    semihosting::write(bytes);
+    #[cfg(feature = "log-usart")]
+    usart::write(bytes);
+    #[cfg(feature = "log-itm")]
+    itm::write(bytes);
+    #[cfg(feature = "log-rtt")]
+    rtt::write(bytes);
+    let _ = bytes;
+}
+
+// level のログを出力する（フィルタで無効なら引数の評価もしない）
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {{
+        let level = $level;
+        if $crate::log::enabled(level, module_path!()) {
+            $crate::log::write(level, module_path!(), format_args!($($arg)+));
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Error, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Warn, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Info, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Debug, $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Trace, $($arg)+)
+    };
+}
@@ -0,0 +1,345 @@
+// 遅延フォーマットのバイナリログ
+// 書式文字列はターゲットに書き込まない ELF のセクション(.binlog、binlog.x で定義)に置き、
+// ターゲットからは書式文字列の番号（セクション内のアドレス）と引数の値だけを送る。
+// 文字列の整形はホストの tools/binlog-decode が ELF を読んで行うので、Flash の使用量も整形の時間も減る。
+//
+//   defer_info!("adc = {}, temp = {}", value, temp);
+//
+// レベルとモジュールのフィルタは log! と同じ（log::set_level、log::set_module_level）。
+// 出力先も log と同じ feature で選ぶ（バイナリなので log-usart か log-rtt を使う。セミホスティングでは読めない）。
+//
+// 書式は {} と {0} などの位置指定、{:?}、{:x} などの書式指定が使える。
+// 名前の引数（{name}、変数を取り込む {value}）と、幅や精度を引数で指定する {:width$}、{:.1$} は
+// 値が送られないので使えない（マクロでコンパイルエラーにする）。
+// 書式と引数はコンパイル時に format_args! で確認するが、実行時には整形しない。
+// 使える引数の型は Arg を実装したもの（整数、f32/f64、bool、char、&str、&[u8]）。
+//
+// 1つのログは1フレーム（COBS でエンコードして 0x00 で区切る。途中から受信しても次のフレームから読める）。
+//   フレームの中身: 書式の番号(varint) 時刻[us](varint) 引数(型タグ + 値)...
+//   varint は LEB128（下位7bitずつ、続きがあれば最上位bitが1）、符号付き整数は zigzag で符号なしにする。
+// 1フレームは FRAME_LEN バイトまで（入りきらない引数は捨て、ホストには切り詰めたことを知らせる）。
+
+use crate::log::write_bytes;
+use crate::systick;
+
+// 1フレームの最大長（エンコード前）[byte]
+pub const FRAME_LEN: usize = 128;
+
+// 引数の型タグ
+pub const TAG_UNSIGNED: u8 = 0;
+pub const TAG_SIGNED: u8 = 1;
+pub const TAG_F32: u8 = 2;
+pub const TAG_F64: u8 = 3;
+pub const TAG_BOOL: u8 = 4;
+pub const TAG_CHAR: u8 = 5;
+pub const TAG_STR: u8 = 6;
+pub const TAG_BYTES: u8 = 7;
+// 入りきらなかった引数があった（フレームの最後に付く）
+pub const TAG_TRUNCATED: u8 = 0xFF;
+
+// 書式文字列に、値を送れない引数（名前の引数や $ での幅/精度の指定）があるか（マクロからコンパイル時に呼ばれる）
+// {{ は飛ばし、{ の後の位置指定が英字か _ で始まるものと、書式指定に $ があるものを探す。
+pub const fn has_named_argument(format: &str) -> bool {
+    let bytes = format.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'{' {
+            i += 1;
+            continue;
+        }
+        if i + 1 < bytes.len() && bytes[i + 1] == b'{' {
+            i += 2;
+            continue;
+        }
+        i += 1;
+        if i < bytes.len() && (bytes[i].is_ascii_alphabetic() || bytes[i] == b'_') {
+            return true;
+        }
+        while i < bytes.len() && bytes[i] != b'}' {
+            if bytes[i] == b'$' {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+// 書式文字列のエントリ（マクロから呼ばれる。"レベル\x1fモジュール\x1fファイル\x1f行\x1f書式\0" の文字列を配列にする）
+pub const fn entry<const N: usize>(s: &str) -> [u8; N] {
+    let bytes = s.as_bytes();
+    let mut entry = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        entry[i] = bytes[i];
+        i += 1;
+    }
+    entry
+}
+
+pub struct Frame {
+    buffer: [u8; FRAME_LEN],
+    len: usize,
+    truncated: bool,
+}
+
+impl Frame {
+    // index は書式文字列のエントリのアドレス
+    pub fn new(index: usize) -> Self {
+        let mut frame = Frame {
+            buffer: [0; FRAME_LEN],
+            len: 0,
+            truncated: false,
+        };
+        frame.push_varint(index as u64);
+        frame.push_varint(systick::uptime_us());
+        frame
+    }
+
+    // 引数を1つ追加する（全部入らなければ何もしない）
+    fn push_arg(&mut self, tag: u8, encode: impl FnOnce(&mut Self)) {
+        if self.truncated {
+            return;
+        }
+        let start = self.len;
+        self.push(&[tag]);
+        encode(self);
+        // TAG_TRUNCATED を書く場所を残しておく
+        if self.truncated || self.len == FRAME_LEN {
+            self.len = start;
+            self.truncated = true;
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let count = bytes.len().min(FRAME_LEN - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        if count < bytes.len() {
+            self.truncated = true;
+        }
+    }
+
+    fn push_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.push(&[byte]);
+                return;
+            }
+            self.push(&[byte | 0x80]);
+        }
+    }
+
+    pub fn unsigned(&mut self, value: u64) {
+        self.push_arg(TAG_UNSIGNED, |frame| frame.push_varint(value));
+    }
+
+    pub fn signed(&mut self, value: i64) {
+        // zigzag: 0, -1, 1, -2, ... を 0, 1, 2, 3, ... に
+        let value = ((value << 1) ^ (value >> 63)) as u64;
+        self.push_arg(TAG_SIGNED, |frame| frame.push_varint(value));
+    }
+
+    pub fn bytes(&mut self, tag: u8, bytes: &[u8]) {
+        self.push_arg(tag, |frame| {
+            frame.push_varint(bytes.len() as u64);
+            frame.push(bytes);
+        });
+    }
+
+    fn raw(&mut self, tag: u8, bytes: &[u8]) {
+        self.push_arg(tag, |frame| frame.push(bytes));
+    }
+
+    // COBS でエンコードして、区切りの 0x00 と一緒に出力する
+    pub fn send(mut self) {
+        if self.truncated {
+            self.buffer[self.len] = TAG_TRUNCATED;
+            self.len += 1;
+        }
+        // COBS はデータ 254 バイトごとに1バイト増える
+        let mut output = [0u8; FRAME_LEN + FRAME_LEN / 254 + 2];
+        let len = cobs_encode(&self.buffer[..self.len], &mut output);
+        output[len] = 0;
+        write_bytes(&output[..len + 1]);
+    }
+}
+
+// 0x00 を含まないようにエンコードして、長さを返す（output は input.len() + input.len() / 254 + 1 以上）
+fn cobs_encode(input: &[u8], output: &mut [u8]) -> usize {
+    let mut code_index = 0;
+    let mut code = 1u8;
+    let mut len = 1;
+    for &byte in input {
+        if byte != 0 {
+            output[len] = byte;
+            len += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            output[code_index] = code;
+            code_index = len;
+            len += 1;
+            code = 1;
+        }
+    }
+    output[code_index] = code;
+    len
+}
+
+// defer_*! の引数にできる型
+pub trait Arg {
+    fn encode(&self, frame: &mut Frame);
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, frame: &mut Frame) {
+        (**self).encode(frame);
+    }
+}
+
+macro_rules! arg_unsigned {
+    ($($t:ty),*) => {
+        $(impl Arg for $t {
+            fn encode(&self, frame: &mut Frame) {
+                frame.unsigned(*self as u64);
+            }
+        })*
+    };
+}
+
+macro_rules! arg_signed {
+    ($($t:ty),*) => {
+        $(impl Arg for $t {
+            fn encode(&self, frame: &mut Frame) {
+                frame.signed(*self as i64);
+            }
+        })*
+    };
+}
+
+arg_unsigned!(u8, u16, u32, u64, usize);
+arg_signed!(i8, i16, i32, i64, isize);
+
+impl Arg for f32 {
+    fn encode(&self, frame: &mut Frame) {
+        frame.raw(TAG_F32, &self.to_le_bytes());
+    }
+}
+
+impl Arg for f64 {
+    fn encode(&self, frame: &mut Frame) {
+        frame.raw(TAG_F64, &self.to_le_bytes());
+    }
+}
+
+impl Arg for bool {
+    fn encode(&self, frame: &mut Frame) {
+        frame.raw(TAG_BOOL, &[*self as u8]);
+    }
+}
+
+impl Arg for char {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push_arg(TAG_CHAR, |frame| frame.push_varint(*self as u64));
+    }
+}
+
+impl Arg for str {
+    fn encode(&self, frame: &mut Frame) {
+        frame.bytes(TAG_STR, self.as_bytes());
+    }
+}
+
+impl Arg for [u8] {
+    fn encode(&self, frame: &mut Frame) {
+        frame.bytes(TAG_BYTES, self);
+    }
+}
+
+impl<const N: usize> Arg for [u8; N] {
+    fn encode(&self, frame: &mut Frame) {
+        frame.bytes(TAG_BYTES, self);
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defer {
+    ($level:ident, $digit:literal, $fmt:literal $(, $arg:expr)* $(,)?) => {{
+        if $crate::log::enabled($crate::log::Level::$level, module_path!()) {
+            // 書式と引数の型をコンパイル時に確認する（実行はしない）
+            const _: () = assert!(
+                !$crate::log::deferred::has_named_argument($fmt),
+                "defer_*!: named arguments ({{name}}) and {{:width$}} are not supported; use positional arguments"
+            );
+            if false {
+                let _ = format_args!($fmt $(, $arg)*);
+            }
+            const ENTRY: &str = concat!(
+                $digit, "\x1f", module_path!(), "\x1f", file!(), "\x1f", line!(), "\x1f", $fmt, "\0"
+            );
+            #[link_section = ".binlog"]
+            static ENTRY_BYTES: [u8; ENTRY.len()] = $crate::log::deferred::entry(ENTRY);
+            #[allow(unused_mut)]
+            let mut frame = $crate::log::deferred::Frame::new(
+                core::ptr::addr_of!(ENTRY_BYTES) as usize,
+            );
+            $($crate::log::deferred::Arg::encode(&$arg, &mut frame);)*
+            frame.send();
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! defer_error {
+    ($($arg:tt)+) => {
+        $crate::__defer!(Error, "1", $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! defer_warn {
+    ($($arg:tt)+) => {
+        $crate::__defer!(Warn, "2", $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! defer_info {
+    ($($arg:tt)+) => {
+        $crate::__defer!(Info, "3", $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! defer_debug {
+    ($($arg:tt)+) => {
+        $crate::__defer!(Debug, "4", $($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! defer_trace {
+    ($($arg:tt)+) => {
+        $crate::__defer!(Trace, "5", $($arg)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_arguments_are_rejected() {
+        assert!(!has_named_argument("adc = {}, temp = {:>08.3}"));
+        assert!(!has_named_argument("{0} {1:#x} {0:?}"));
+        assert!(!has_named_argument("{{value}} {}"));
+        assert!(has_named_argument("adc = {value}"));
+        assert!(has_named_argument("{_x:?}"));
+        assert!(has_named_argument("{:width$}"));
+        assert!(has_named_argument("{:.1$}"));
+    }
+}
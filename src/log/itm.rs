@@ -0,0 +1,18 @@
+// ログの出力先: ITM のスティミュラスポート0（SWO ピン）
+// TPIU と ITM の設定はデバッガ側で行う（openocd.gdb の tpiu config と itm port 0 on のコメントを外す）。
+// ITM やポート0 が有効になっていなければ何もしない（FIFO の空きを待って止まらないように）。
+
+use cortex_m::itm;
+use cortex_m::peripheral::{itm::RegisterBlock, ITM};
+
+// ITM_TCR.ITMENA
+const TCR_ITMENA: u32 = 1 << 0;
+
+pub fn write(bytes: &[u8]) {
+    // ITM はコアの周辺機能なので、他から所有されていても書き込みだけならレジスタを直接使ってよい
+    let itm = unsafe { &mut *(ITM::PTR as *mut RegisterBlock) };
+    if itm.tcr.read() & TCR_ITMENA == 0 || itm.ter[0].read() & 1 == 0 {
+        return;
+    }
+    itm::write_all(&mut itm.stim[0], bytes);
+}
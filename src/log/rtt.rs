@@ -0,0 +1,106 @@
+// ログの出力先: RAM のリングバッファ（SEGGER RTT 互換）
+// デバッガがコアを止めずに RAM を読み出して表示する。セミホスティングや SWO より速く、配線も不要。
+// 制御ブロックは _SEGGER_RTT というシンボルで置くので、ツールが自動で見つけられる。
+//
+//   probe-rs: probe-rs run --chip STM32F446RETx <elf>（RTT を自動で表示）
+//   openocd: monitor rtt setup 0x20000000 0x20000 "SEGGER RTT"
+//            monitor rtt start
+//            monitor rtt server start 9090 0（localhost:9090 に telnet などで接続）
+//
+// バッファが一杯のときは、入りきらない分を捨てる（ホストが読んでいなくても止まらない）。
+
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+// リングバッファの大きさ[byte]
+pub const BUFFER_SIZE: usize = 1024;
+
+// 一杯なら入りきらない分を捨てる
+const MODE_NO_BLOCK_TRIM: u32 = 1;
+
+#[repr(C)]
+struct UpBuffer {
+    name: *const u8,
+    buffer: *mut u8,
+    size: u32,
+    // 書き込み位置（ターゲットが更新）
+    write: u32,
+    // 読み出し位置（ホストが更新）
+    read: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    max_up_buffers: i32,
+    max_down_buffers: i32,
+    up: UpBuffer,
+}
+
+struct Rtt {
+    control: UnsafeCell<ControlBlock>,
+    buffer: UnsafeCell<[u8; BUFFER_SIZE]>,
+}
+
+// 書き込みは割り込み禁止の中で行う
+unsafe impl Sync for Rtt {}
+
+// id は最初の書き込みで設定する（ホストが初期化前の制御ブロックを見つけないように）
+#[no_mangle]
+static _SEGGER_RTT: Rtt = Rtt {
+    control: UnsafeCell::new(ControlBlock {
+        id: [0; 16],
+        max_up_buffers: 1,
+        max_down_buffers: 0,
+        up: UpBuffer {
+            name: ptr::null(),
+            buffer: ptr::null_mut(),
+            size: 0,
+            write: 0,
+            read: 0,
+            flags: MODE_NO_BLOCK_TRIM,
+        },
+    }),
+    buffer: UnsafeCell::new([0; BUFFER_SIZE]),
+};
+
+const ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+const NAME: &[u8] = b"Terminal\0";
+
+unsafe fn init(control: *mut ControlBlock) {
+    let up = ptr::addr_of_mut!((*control).up);
+    ptr::addr_of_mut!((*up).name).write_volatile(NAME.as_ptr());
+    ptr::addr_of_mut!((*up).buffer).write_volatile(_SEGGER_RTT.buffer.get() as *mut u8);
+    ptr::addr_of_mut!((*up).size).write_volatile(BUFFER_SIZE as u32);
+    compiler_fence(Ordering::SeqCst);
+    // ホストが途中まで書いた ID を見つけないように、後ろから書く
+    let id = ptr::addr_of_mut!((*control).id) as *mut u8;
+    for (i, &byte) in ID.iter().enumerate().rev() {
+        id.add(i).write_volatile(byte);
+    }
+}
+
+pub fn write(bytes: &[u8]) {
+    cortex_m::interrupt::free(|_| unsafe {
+        let control = _SEGGER_RTT.control.get();
+        if ptr::addr_of!((*control).up.size).read_volatile() == 0 {
+            init(control);
+        }
+        let up = ptr::addr_of_mut!((*control).up);
+        let buffer = _SEGGER_RTT.buffer.get() as *mut u8;
+        let size = BUFFER_SIZE;
+        let mut write = ptr::addr_of!((*up).write).read_volatile() as usize;
+        let read = ptr::addr_of!((*up).read).read_volatile() as usize;
+        // 1バイトは空けておく（write == read は空）
+        let free = (read + size - write - 1) % size;
+        for &byte in &bytes[..bytes.len().min(free)] {
+            buffer.add(write).write_volatile(byte);
+            write = (write + 1) % size;
+        }
+        // データを書いてから位置を更新する（ホストは write を見て読みに来る）
+        compiler_fence(Ordering::SeqCst);
+        ptr::addr_of_mut!((*up).write).write_volatile(write as u32);
+    });
+}
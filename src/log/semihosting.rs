@@ -0,0 +1,16 @@
+// ログの出力先: セミホスティング（ホストの標準出力）
+// デバッガが接続されていないときにセミホスティングを呼ぶと HardFault になるので、
+// DHCSR の C_DEBUGEN を見て、接続されていなければ何もしない。
+// 1行ごとにコアが止まるので遅い（数ms）。タイミングが重要な処理の中では他の出力先を使うこと。
+
+use cortex_m::peripheral::DCB;
+use cortex_m_semihosting::hio;
+
+pub fn write(bytes: &[u8]) {
+    if !DCB::is_debugger_attached() {
+        return;
+    }
+    if let Ok(mut stdout) = hio::hstdout() {
+        stdout.write_all(bytes).ok();
+    }
+}
@@ -0,0 +1,54 @@
+// ログの出力先: USART（TXE をポーリングして1バイトずつ書き込む）
+// 割り込みを使わないので、割り込みハンドラや panic ハンドラの中からでも出力できる。
+// NUCLEO-F446RE では USART2 (PA2: TX, AF7) が ST-Link の仮想 COM ポートにつながっている。
+// USART のクロック供給とピンの設定は、init() の前に呼び出し側で済ませておくこと。
+// 改行(\n)は \r\n にして送る。
+
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use stm32f4::stm32f446::usart1;
+
+use crate::clock::Clocks;
+use crate::serial::{self, Config, Instance, SR_TC, SR_TXE};
+
+static REGS: AtomicPtr<usart1::RegisterBlock> = AtomicPtr::new(core::ptr::null_mut());
+
+// usart をログ出力専用にして、実際のボーレートを返す（init() までの出力は捨てる）
+pub fn init<USART: Instance>(
+    usart: USART,
+    clocks: &Clocks,
+    config: &Config,
+) -> Result<u32, serial::Error> {
+    let baud = serial::configure(&usart, clocks, config, 0, 0)?;
+    REGS.store(USART::ptr() as *mut _, Ordering::Release);
+    Ok(baud)
+}
+
+fn write_byte(regs: &usart1::RegisterBlock, byte: u8) {
+    while regs.sr.read().bits() & SR_TXE == 0 {}
+    regs.dr.write(|w| unsafe { w.bits(byte as u32) });
+}
+
+pub fn write(bytes: &[u8]) {
+    let ptr = REGS.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return;
+    }
+    let regs = unsafe { &*ptr };
+    for &byte in bytes {
+        if byte == b'\n' {
+            write_byte(regs, b'\r');
+        }
+        write_byte(regs, byte);
+    }
+}
+
+// 最後のバイトまで送り終わるのを待つ（リセットや低消費電力モードに入る前に）
+pub fn flush() {
+    let ptr = REGS.load(Ordering::Acquire);
+    if ptr.is_null() {
+        return;
+    }
+    let regs = unsafe { &*ptr };
+    while regs.sr.read().bits() & SR_TC == 0 {}
+}
@@ -0,0 +1,197 @@
+// DWT のサイクルカウンタ(CYCCNT)を使った処理時間の計測
+// 名前ごとに 回数/最小/最大/平均 のサイクル数を集計する。
+// CYCCNT は32bitなので、1回の計測は 2^32 サイクル（180MHzで約23秒）まで。
+//
+// ISR の処理時間: 先頭で let _scope = profile::scope("EXTI15_10"); とすれば抜ける時に記録される。
+// ISR の遅延: profile::pend(割り込み) で保留にし、ISR の先頭で profile::mark_entry("名前") を呼ぶと
+//             保留にしてから ISR に入るまでのサイクル数が記録される。
+
+use core::cell::RefCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use cortex_m::interrupt::{InterruptNumber, Mutex};
+use cortex_m::peripheral::{DCB, DWT, NVIC};
+
+// 集計できる名前の数（超えた分は記録しない）
+pub const MAX_SCOPES: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub name: &'static str,
+    pub count: u32,
+    pub total: u64,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Stats {
+    pub fn average(&self) -> u32 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.total / self.count as u64) as u32
+        }
+    }
+}
+
+pub struct Profiler {
+    entries: [Option<Stats>; MAX_SCOPES],
+}
+
+impl Profiler {
+    pub const fn new() -> Self {
+        Profiler {
+            entries: [None; MAX_SCOPES],
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, cycles: u32) {
+        let mut empty = None;
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            match entry {
+                Some(stats) if stats.name == name => {
+                    stats.count = stats.count.saturating_add(1);
+                    stats.total += cycles as u64;
+                    stats.min = stats.min.min(cycles);
+                    stats.max = stats.max.max(cycles);
+                    return;
+                }
+                None if empty.is_none() => empty = Some(i),
+                _ => {}
+            }
+        }
+        if let Some(i) = empty {
+            self.entries[i] = Some(Stats {
+                name,
+                count: 1,
+                total: cycles as u64,
+                min: cycles,
+                max: cycles,
+            });
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Stats> {
+        self.iter().find(|stats| stats.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Stats> + '_ {
+        self.entries.iter().flatten().copied()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries = [None; MAX_SCOPES];
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static PROFILER: Mutex<RefCell<Profiler>> = Mutex::new(RefCell::new(Profiler::new()));
+
+static PEND_STAMP: AtomicU32 = AtomicU32::new(0);
+static PEND_VALID: AtomicBool = AtomicBool::new(false);
+
+// サイクルカウンタを有効化（計測前に1回呼ぶ）
+pub fn init(dcb: &mut DCB, dwt: &mut DWT) {
+    dcb.enable_trace(); // トレース有効化しないと CYCCNT が動かない場合がある
+    DWT::unlock();
+    dwt.set_cycle_count(0);
+    dwt.enable_cycle_counter();
+}
+
+pub fn now() -> u32 {
+    DWT::cycle_count()
+}
+
+pub fn record(name: &'static str, cycles: u32) {
+    cortex_m::interrupt::free(|cs| PROFILER.borrow(cs).borrow_mut().record(name, cycles));
+}
+
+pub fn stats(name: &str) -> Option<Stats> {
+    cortex_m::interrupt::free(|cs| PROFILER.borrow(cs).borrow().get(name))
+}
+
+pub fn reset() {
+    cortex_m::interrupt::free(|cs| PROFILER.borrow(cs).borrow_mut().reset());
+}
+
+// 関数の実行時間を計測
+pub fn measure<R>(name: &'static str, f: impl FnOnce() -> R) -> R {
+    let _scope = scope(name);
+    f()
+}
+
+// 戻り値が破棄されるまでの時間を計測
+pub fn scope(name: &'static str) -> Scope {
+    Scope {
+        name,
+        start: DWT::cycle_count(),
+    }
+}
+
+pub struct Scope {
+    name: &'static str,
+    start: u32,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        record(self.name, DWT::cycle_count().wrapping_sub(self.start));
+    }
+}
+
+// 時刻を記録してから割り込みを保留にする（遅延計測用）
+pub fn pend<I: InterruptNumber>(interrupt: I) {
+    PEND_STAMP.store(DWT::cycle_count(), Ordering::Relaxed);
+    PEND_VALID.store(true, Ordering::Release);
+    NVIC::pend(interrupt);
+}
+
+// ISR の先頭で呼ぶ（pend() されていなければ何もしない）
+pub fn mark_entry(name: &'static str) {
+    let now = DWT::cycle_count();
+    if PEND_VALID.swap(false, Ordering::Acquire) {
+        record(name, now.wrapping_sub(PEND_STAMP.load(Ordering::Relaxed)));
+    }
+}
+
+// 集計結果を表形式で出力（sysclk_hz を渡すと us 換算も出す）
+pub fn dump<W: fmt::Write>(out: &mut W, sysclk_hz: u32) -> fmt::Result {
+    let profiler = cortex_m::interrupt::free(|cs| {
+        let profiler = PROFILER.borrow(cs).borrow();
+        Profiler {
+            entries: profiler.entries,
+        }
+    });
+    let mhz = (sysclk_hz / 1_000_000).max(1);
+    writeln!(
+        out,
+        "{:<16} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "name", "count", "min", "max", "avg", "avg[us]"
+    )?;
+    for stats in profiler.iter() {
+        writeln!(
+            out,
+            "{:<16} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            stats.name,
+            stats.count,
+            stats.min,
+            stats.max,
+            stats.average(),
+            stats.average() / mhz
+        )?;
+    }
+    Ok(())
+}
+
+// semihosting に出力（デバッガ接続が必要）
+pub fn dump_semihosting(sysclk_hz: u32) {
+    if let Ok(mut stdout) = cortex_m_semihosting::hio::hstdout() {
+        dump(&mut stdout, sysclk_hz).ok();
+    }
+}
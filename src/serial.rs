@@ -0,0 +1,433 @@
+// USART の非同期シリアル通信（割り込みで送受信するリングバッファ付き）
+// NUCLEO-F446RE では USART2 (PA2: TX, PA3: RX, AF7) が ST-Link の仮想 COM ポートにつながっているので、
+// デバッガ無しで PC のターミナルに出力できる（hprintln! と違ってコアも止まらない）。
+//
+// ・ボーレートは設定済みのクロック(Clocks)の PCLK から計算する（OVER8 で 8 倍オーバーサンプリングも可）
+// ・送信: write() でバッファに入れ、TXE 割り込みで1バイトずつ送る（一杯なら TXE をポーリングして送る）
+// ・受信: RXNE 割り込みでバッファに入れ、read() で取り出す
+// ・オーバーラン/フレーミング/ノイズ/パリティのエラーは、次の read() で Err として返す
+// 割り込みハンドラから on_interrupt() を呼ぶこと（NVIC の許可とピンの設定は呼び出し側で行う）。
+// 1バイトごとの割り込みを避けたい場合は dma の SerialDma を使う。
+
+pub mod dma;
+pub mod ring_buffer;
+
+use core::fmt;
+
+use stm32f4::stm32f446;
+use stm32f446::usart1;
+
+use crate::clock::Clocks;
+use ring_buffer::RingBuffer;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // 受信データを読む前に次のデータが届いた (SR.ORE)
+    Overrun,
+    // ストップビットが検出できなかった (SR.FE)
+    Framing,
+    // ノイズを検出した (SR.NF、データは受け取る)
+    Noise,
+    // パリティが合わない (SR.PE)
+    Parity,
+    // 受信バッファが一杯で捨てた
+    BufferFull,
+    // 設定できないボーレート（誤差が大きい場合も含む）
+    InvalidBaud,
+    // DMA のエラー
+    Dma(crate::dma::Error),
+}
+
+// SR のビット
+const SR_PE: u32 = 1 << 0;
+const SR_FE: u32 = 1 << 1;
+const SR_NF: u32 = 1 << 2;
+const SR_ORE: u32 = 1 << 3;
+const SR_IDLE: u32 = 1 << 4;
+const SR_RXNE: u32 = 1 << 5;
+pub(crate) const SR_TC: u32 = 1 << 6;
+pub(crate) const SR_TXE: u32 = 1 << 7;
+// CR1 のビット
+const CR1_RE: u32 = 1 << 2;
+const CR1_TE: u32 = 1 << 3;
+const CR1_IDLEIE: u32 = 1 << 4;
+const CR1_RXNEIE: u32 = 1 << 5;
+const CR1_TXEIE: u32 = 1 << 7;
+const CR1_PEIE: u32 = 1 << 8;
+const CR1_PS: u32 = 1 << 9;
+const CR1_PCE: u32 = 1 << 10;
+const CR1_M: u32 = 1 << 12;
+const CR1_UE: u32 = 1 << 13;
+const CR1_OVER8: u32 = 1 << 15;
+// CR3 のビット
+const CR3_EIE: u32 = 1 << 0;
+const CR3_DMAR: u32 = 1 << 6;
+const CR3_DMAT: u32 = 1 << 7;
+
+// SR のエラーフラグを Error に（複数立っていれば重大な方）
+fn sr_error(sr: u32) -> Option<Error> {
+    if sr & SR_ORE != 0 {
+        Some(Error::Overrun)
+    } else if sr & SR_FE != 0 {
+        Some(Error::Framing)
+    } else if sr & SR_PE != 0 {
+        Some(Error::Parity)
+    } else if sr & SR_NF != 0 {
+        Some(Error::Noise)
+    } else {
+        None
+    }
+}
+
+pub trait Instance {
+    fn ptr() -> *const usart1::RegisterBlock;
+
+    fn regs(&self) -> &usart1::RegisterBlock {
+        unsafe { &*Self::ptr() }
+    }
+
+    // RCC からクロック供給
+    fn enable_clock(rcc: &stm32f446::RCC);
+
+    // USART に入るクロック周波数[Hz]（PCLK1 か PCLK2）
+    fn clock(clocks: &Clocks) -> u32;
+}
+
+macro_rules! instance {
+    ($USART:ident, $apbenr:ident, $usarten:ident, $pclk:ident) => {
+        impl Instance for stm32f446::$USART {
+            fn ptr() -> *const usart1::RegisterBlock {
+                stm32f446::$USART::ptr() as *const usart1::RegisterBlock
+            }
+
+            fn enable_clock(rcc: &stm32f446::RCC) {
+                rcc.$apbenr.modify(|_, w| w.$usarten().enabled());
+            }
+
+            fn clock(clocks: &Clocks) -> u32 {
+                clocks.$pclk
+            }
+        }
+    };
+}
+
+instance!(USART1, apb2enr, usart1en, pclk2);
+instance!(USART2, apb1enr, usart2en, pclk1);
+instance!(USART3, apb1enr, usart3en, pclk1);
+instance!(USART6, apb2enr, usart6en, pclk2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oversampling {
+    By16,
+    // 高いボーレート向け（ノイズに弱くなる）
+    By8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopBits {
+    One = 0b00,
+    Half = 0b01,
+    Two = 0b10,
+    OneAndHalf = 0b11,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Config {
+    pub baud: u32,
+    pub oversampling: Oversampling,
+    // パリティ有りの場合は 8bit データ + パリティ（M = 1）
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            baud: 115_200,
+            oversampling: Oversampling::By16,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+// ボーレートの誤差の上限[‰]（受信側の許容誤差を考えて 2%）
+const MAX_BAUD_ERROR_PERMILLE: u32 = 20;
+
+// BRR の値と実際のボーレートを求める
+// USARTDIV = fck / (8 * (2 - OVER8) * baud) で、BRR は USARTDIV を 1/16 (OVER8 なら 1/8) 単位で表したもの。
+pub fn brr(clock: u32, baud: u32, oversampling: Oversampling) -> Option<(u32, u32)> {
+    if baud == 0 {
+        return None;
+    }
+    let (brr, actual) = match oversampling {
+        Oversampling::By16 => {
+            let div = (clock + baud / 2) / baud;
+            if !(16..=0xFFFF).contains(&div) {
+                return None;
+            }
+            (div, clock / div)
+        }
+        Oversampling::By8 => {
+            // div = 8 * USARTDIV（小数部は3bit）
+            let div = (clock + baud / 2) / baud;
+            if !(8..=0xFFF * 8 + 7).contains(&div) {
+                return None;
+            }
+            // 小数部は BRR[2:0] に入れる（BRR[3] は 0）
+            (((div & !0b111) << 1) | (div & 0b111), clock / div)
+        }
+    };
+    let error = (actual as i64 - baud as i64).unsigned_abs() * 1000 / baud as u64;
+    if error > MAX_BAUD_ERROR_PERMILLE as u64 {
+        return None;
+    }
+    Some((brr, actual))
+}
+
+// USART を設定して、実際のボーレートを返す（cr1/cr3 には割り込みや DMA の許可を追加で渡す）
+pub(crate) fn configure<USART: Instance>(
+    usart: &USART,
+    clocks: &Clocks,
+    config: &Config,
+    cr1: u32,
+    cr3: u32,
+) -> Result<u32, Error> {
+    let (brr, baud) =
+        brr(USART::clock(clocks), config.baud, config.oversampling).ok_or(Error::InvalidBaud)?;
+
+    let regs = usart.regs();
+    regs.cr1.write(|w| unsafe { w.bits(0) });
+    regs.brr.write(|w| unsafe { w.bits(brr) });
+    regs.cr2
+        .write(|w| unsafe { w.bits((config.stop_bits as u32) << 12) });
+    regs.cr3.write(|w| unsafe { w.bits(cr3) });
+
+    let mut cr1 = cr1 | CR1_UE | CR1_TE | CR1_RE;
+    if config.oversampling == Oversampling::By8 {
+        cr1 |= CR1_OVER8;
+    }
+    match config.parity {
+        Parity::None => {}
+        Parity::Even => cr1 |= CR1_M | CR1_PCE | CR1_PEIE,
+        Parity::Odd => cr1 |= CR1_M | CR1_PCE | CR1_PEIE | CR1_PS,
+    }
+    regs.cr1.write(|w| unsafe { w.bits(cr1) });
+    Ok(baud)
+}
+
+pub struct Serial<USART, const TX: usize = 256, const RX: usize = 256> {
+    usart: USART,
+    tx: RingBuffer<TX>,
+    rx: RingBuffer<RX>,
+    // 次の read() で返すエラー
+    error: Option<Error>,
+    baud: u32,
+}
+
+impl<USART: Instance, const TX: usize, const RX: usize> Serial<USART, TX, RX> {
+    // USART のクロック供給は呼び出し側で済ませておくこと
+    pub fn new(usart: USART, clocks: &Clocks, config: &Config) -> Result<Self, Error> {
+        let baud = configure(&usart, clocks, config, CR1_RXNEIE, 0)?;
+        Ok(Serial {
+            usart,
+            tx: RingBuffer::new(),
+            rx: RingBuffer::new(),
+            error: None,
+            baud,
+        })
+    }
+
+    // 実際のボーレート
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    // USART の割り込みから呼ぶ
+    pub fn on_interrupt(&mut self) {
+        let regs = self.usart.regs();
+        let sr = regs.sr.read().bits();
+
+        // 受信（エラーフラグは SR を読んだ後に DR を読むとクリアされる）
+        if sr & (SR_RXNE | SR_ORE | SR_FE | SR_PE | SR_NF) != 0 {
+            let byte = regs.dr.read().bits() as u8;
+            let error = sr_error(sr);
+            // フレーミング/パリティエラーのデータは捨てる
+            let valid = sr & (SR_FE | SR_PE) == 0 && sr & SR_RXNE != 0;
+            if valid && self.rx.push(byte).is_err() {
+                self.set_error(Error::BufferFull);
+            }
+            if let Some(error) = error {
+                self.set_error(error);
+            }
+        }
+
+        // 送信（set_error() で self を借りた後なので、レジスタは取り直す）
+        let regs = self.usart.regs();
+        if sr & SR_TXE != 0 && regs.cr1.read().bits() & CR1_TXEIE != 0 {
+            match self.tx.pop() {
+                Some(byte) => regs.dr.write(|w| unsafe { w.bits(byte as u32) }),
+                None => self.listen_tx(false),
+            }
+        }
+    }
+
+    fn set_error(&mut self, error: Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    fn listen_tx(&self, enable: bool) {
+        self.usart.regs().cr1.modify(|r, w| unsafe {
+            w.bits(if enable {
+                r.bits() | CR1_TXEIE
+            } else {
+                r.bits() & !CR1_TXEIE
+            })
+        });
+    }
+
+    // 受信した1バイトを取り出す（無ければ WouldBlock、エラーがあれば先にそれを返す）
+    pub fn read(&mut self) -> nb::Result<u8, Error> {
+        if let Some(error) = self.error.take() {
+            return Err(nb::Error::Other(error));
+        }
+        self.rx.pop().ok_or(nb::Error::WouldBlock)
+    }
+
+    // 受信済みのバイト数
+    pub fn available(&self) -> usize {
+        self.rx.len()
+    }
+
+    // 送信バッファに1バイト入れる（一杯なら WouldBlock）
+    pub fn write(&mut self, byte: u8) -> nb::Result<(), Error> {
+        self.tx.push(byte).map_err(|_| nb::Error::WouldBlock)?;
+        self.listen_tx(true);
+        Ok(())
+    }
+
+    // 全部送信バッファに入れる（一杯なら割り込みを待たずに TXE をポーリングして送る）
+    // クリティカルセクションの中から呼んでも止まらない。
+    pub fn write_all(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            while self.tx.push(byte).is_err() {
+                self.send_one();
+            }
+        }
+        if !self.tx.is_empty() {
+            self.listen_tx(true);
+        }
+    }
+
+    // TXE を待って、送信バッファから1バイト送る
+    fn send_one(&mut self) {
+        let regs = self.usart.regs();
+        while regs.sr.read().bits() & SR_TXE == 0 {}
+        if let Some(byte) = self.tx.pop() {
+            regs.dr.write(|w| unsafe { w.bits(byte as u32) });
+        }
+    }
+
+    // 送信バッファが空になり、最後のバイトを送り終わったら Ok
+    pub fn flush(&mut self) -> nb::Result<(), Error> {
+        if self.tx.is_empty() && self.usart.regs().sr.read().bits() & SR_TC != 0 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    pub fn release(self) -> USART {
+        self.usart.regs().cr1.write(|w| unsafe { w.bits(0) });
+        self.usart
+    }
+}
+
+impl<USART: Instance, const TX: usize, const RX: usize> fmt::Write for Serial<USART, TX, RX> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<USART: Instance, const TX: usize, const RX: usize> embedded_hal::serial::Read<u8>
+    for Serial<USART, TX, RX>
+{
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        Serial::read(self)
+    }
+}
+
+impl<USART: Instance, const TX: usize, const RX: usize> embedded_hal::serial::Write<u8>
+    for Serial<USART, TX, RX>
+{
+    type Error = Error;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Error> {
+        Serial::write(self, byte)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Error> {
+        Serial::flush(self)
+    }
+}
+
+// embedded_hal::blocking::serial::Write（write() と flush() を繰り返す）
+impl<USART: Instance, const TX: usize, const RX: usize>
+    embedded_hal::blocking::serial::write::Default<u8> for Serial<USART, TX, RX>
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brr_reference_manual_example() {
+        // RM0390 の例: USARTDIV = 25.62 なら BRR = 0x19A（OVER8 = 0）、0x195（OVER8 = 1）
+        assert_eq!(
+            brr(3_935_232, 9600, Oversampling::By16),
+            Some((0x19A, 9598))
+        );
+        assert_eq!(brr(1_967_616, 9600, Oversampling::By8), Some((0x195, 9598)));
+    }
+
+    #[test]
+    fn brr_actual_baud() {
+        // APB1 = 45MHz で 115200bps
+        assert_eq!(
+            brr(45_000_000, 115_200, Oversampling::By16),
+            Some((391, 115_089))
+        );
+        // OVER8 でも同じボーレートになる（391 = 48 * 8 + 7）
+        assert_eq!(
+            brr(45_000_000, 115_200, Oversampling::By8),
+            Some(((48 << 4) | 7, 115_089))
+        );
+        // OVER8 なら fck / 8 まで出せる
+        assert_eq!(
+            brr(45_000_000, 5_625_000, Oversampling::By8),
+            Some((0x10, 5_625_000))
+        );
+        assert_eq!(brr(45_000_000, 5_625_000, Oversampling::By16), None);
+    }
+
+    #[test]
+    fn brr_out_of_range() {
+        assert_eq!(brr(45_000_000, 0, Oversampling::By16), None);
+        // 分周比が大きすぎる
+        assert_eq!(brr(90_000_000, 1000, Oversampling::By16), None);
+        assert_eq!(brr(90_000_000, 1000, Oversampling::By8), None);
+    }
+}
@@ -0,0 +1,304 @@
+// USART の DMA 送受信
+// 受信: 循環モードの DMA で受信バッファに取り込み続け、受信が途切れた(IDLE)ときと、
+//       バッファの半分/最後まで埋まったとき(HT/TC)に、まだ渡していない分をアプリに渡す。
+//       1バイトごとの割り込みが無いので、長さの決まっていないパケットの受信に向く。
+//       IDLE で渡した最後の部分には frame_end = true が付く（パケットの区切りとして使える）。
+//       フレームの最後まで HT/TC で渡し終わっていた場合は、IDLE で空のデータに frame_end = true を付けて渡す。
+// 送信: write() で送信バッファにコピーして DMA で送る（送り終わるまで次の write() は WouldBlock）。
+//
+// USART の割り込みと、受信/送信の DMA ストリームの割り込みから on_interrupt() を呼ぶこと。
+// 受信バッファは、割り込みの間隔（半分埋まるまでの時間）に処理が間に合う大きさにする。
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use super::{
+    configure, sr_error, Config, Error, Instance, CR1_IDLEIE, CR3_DMAR, CR3_DMAT, CR3_EIE, SR_IDLE,
+    SR_TC,
+};
+use crate::clock::Clocks;
+use crate::dma::request::Request;
+use crate::dma::{self, Channel, Stream};
+
+pub struct SerialDma<USART, DMA, const RXS: usize, const TXS: usize> {
+    usart: USART,
+    rx_stream: Stream<DMA, RXS>,
+    rx_buffer: &'static mut [u8],
+    // 次にアプリに渡す位置
+    read_position: usize,
+    tx_stream: Stream<DMA, TXS>,
+    tx_channel: Channel,
+    tx_buffer: &'static mut [u8],
+    tx_busy: bool,
+    baud: u32,
+}
+
+impl<USART: Instance, DMA: dma::Instance, const RXS: usize, const TXS: usize>
+    SerialDma<USART, DMA, RXS, TXS>
+{
+    // rx/tx は DMA のストリームと要求（USART2 なら DMA1 の stream5 + Usart2Rx、stream6 + Usart2Tx）
+    // USART と DMA のクロック供給は呼び出し側で済ませておくこと
+    pub fn new<RXREQ: Request<DMA, RXS>, TXREQ: Request<DMA, TXS>>(
+        usart: USART,
+        clocks: &Clocks,
+        config: &Config,
+        rx: (Stream<DMA, RXS>, RXREQ),
+        tx: (Stream<DMA, TXS>, TXREQ),
+        buffers: (&'static mut [u8], &'static mut [u8]),
+    ) -> Result<Self, Error> {
+        let (mut rx_stream, _) = rx;
+        let (tx_stream, _) = tx;
+        let (rx_buffer, tx_buffer) = buffers;
+        if tx_buffer.is_empty() || tx_buffer.len() > u16::MAX as usize {
+            return Err(Error::Dma(dma::Error::InvalidBuffer));
+        }
+
+        let baud = configure(
+            &usart,
+            clocks,
+            config,
+            CR1_IDLEIE,
+            CR3_DMAR | CR3_DMAT | CR3_EIE,
+        )?;
+        let rx_config = dma::Config {
+            circular: true,
+            half_transfer_interrupt: true,
+            transfer_complete_interrupt: true,
+            error_interrupt: true,
+            ..Default::default()
+        };
+        rx_stream
+            .configure(
+                RXREQ::CHANNEL,
+                0b00,
+                dma::address(&usart.regs().dr),
+                rx_buffer,
+                &rx_config,
+            )
+            .map_err(Error::Dma)?;
+        rx_stream.enable();
+
+        Ok(SerialDma {
+            usart,
+            rx_stream,
+            rx_buffer,
+            read_position: 0,
+            tx_stream,
+            tx_channel: TXREQ::CHANNEL,
+            tx_buffer,
+            tx_busy: false,
+            baud,
+        })
+    }
+
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    // USART の割り込みと DMA のストリームの割り込みから呼ぶ
+    // 受信したデータを f(データ, frame_end) に渡す（バッファの端で折り返す場合は2回に分けて呼ぶ）。
+    // 受信エラーや DMA のエラーがあれば、受信済みのデータを渡した後で Err を返す。
+    pub fn on_interrupt<F: FnMut(&[u8], bool)>(&mut self, mut f: F) -> Result<(), Error> {
+        let regs = self.usart.regs();
+        let sr = regs.sr.read().bits();
+        let error = sr_error(sr);
+        let idle = sr & SR_IDLE != 0;
+        if idle || error.is_some() {
+            // SR を読んだ後に DR を読むと IDLE とエラーフラグがクリアされる
+            let _ = regs.dr.read().bits();
+        }
+
+        let rx_result = self.rx_stream.check_errors(false);
+        let flags = self.rx_stream.flags();
+        self.rx_stream.clear_flags(flags);
+        self.deliver(idle, &mut f);
+
+        let tx_result = self.poll_tx();
+        rx_result.and(tx_result).map_err(Error::Dma)?;
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // DMA が書き込んだ位置までをアプリに渡す
+    fn deliver<F: FnMut(&[u8], bool)>(&mut self, idle: bool, f: &mut F) {
+        let len = self.rx_buffer.len();
+        let position = (len - self.rx_stream.remaining() as usize) % len;
+        // DMA が書き終えた範囲だけを読む
+        compiler_fence(Ordering::Acquire);
+        self.read_position = deliver_range(self.rx_buffer, self.read_position, position, idle, f);
+    }
+
+    // 送信の完了を確認する
+    fn poll_tx(&mut self) -> Result<(), dma::Error> {
+        if !self.tx_busy {
+            return Ok(());
+        }
+        let result = self.tx_stream.check_errors(false);
+        if result.is_err() || self.tx_stream.flags().is_transfer_complete() {
+            self.tx_stream.disable();
+            self.tx_stream.clear_all_flags();
+            self.tx_busy = false;
+        }
+        result
+    }
+
+    // 送信中か
+    pub fn is_tx_busy(&mut self) -> bool {
+        self.poll_tx().ok();
+        self.tx_busy
+    }
+
+    // data を送信バッファにコピーして送り始め、コピーしたバイト数を返す（送信中なら WouldBlock）
+    pub fn write(&mut self, data: &[u8]) -> nb::Result<usize, Error> {
+        self.poll_tx()
+            .map_err(|error| nb::Error::Other(Error::Dma(error)))?;
+        if self.tx_busy {
+            return Err(nb::Error::WouldBlock);
+        }
+        if data.is_empty() {
+            return Ok(0);
+        }
+        let count = data.len().min(self.tx_buffer.len());
+        self.tx_buffer[..count].copy_from_slice(&data[..count]);
+
+        let config = dma::Config {
+            transfer_complete_interrupt: true,
+            error_interrupt: true,
+            ..Default::default()
+        };
+        let regs = self.usart.regs();
+        self.tx_stream
+            .configure(
+                self.tx_channel,
+                0b01,
+                dma::address(&regs.dr),
+                &self.tx_buffer[..count],
+                &config,
+            )
+            .map_err(|error| nb::Error::Other(Error::Dma(error)))?;
+        // TC は最後のバイトを送り終わったことの確認に使うので、送信前にクリアしておく
+        regs.sr.write(|w| unsafe { w.bits(!SR_TC) });
+        self.tx_busy = true;
+        self.tx_stream.enable();
+        Ok(count)
+    }
+
+    // 全部送り終わるまで待つ
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            match self.write(data) {
+                Ok(count) => data = &data[count..],
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(error)) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    // DMA の送信が終わり、最後のバイトまで送り終わったら Ok
+    pub fn flush(&mut self) -> nb::Result<(), Error> {
+        if !self.is_tx_busy() && self.usart.regs().sr.read().bits() & SR_TC != 0 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    // 止めて、USART、ストリーム、バッファを返す
+    #[allow(clippy::type_complexity)]
+    pub fn release(
+        mut self,
+    ) -> (
+        USART,
+        (Stream<DMA, RXS>, Stream<DMA, TXS>),
+        (&'static mut [u8], &'static mut [u8]),
+    ) {
+        self.rx_stream.disable();
+        self.tx_stream.disable();
+        self.rx_stream.clear_all_flags();
+        self.tx_stream.clear_all_flags();
+        self.usart.regs().cr1.write(|w| unsafe { w.bits(0) });
+        self.usart.regs().cr3.write(|w| unsafe { w.bits(0) });
+        (
+            self.usart,
+            (self.rx_stream, self.tx_stream),
+            (self.rx_buffer, self.tx_buffer),
+        )
+    }
+}
+
+impl<USART: Instance, DMA: dma::Instance, const RXS: usize, const TXS: usize> core::fmt::Write
+    for SerialDma<USART, DMA, RXS, TXS>
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| core::fmt::Error)
+    }
+}
+
+// 受信バッファの start から position（DMA が次に書く位置）の手前までを f に渡して、次に渡す位置を返す
+// idle なら最後の部分に frame_end = true を付ける。新しいデータが無くても f(&[], true) を呼ぶ。
+fn deliver_range<F: FnMut(&[u8], bool)>(
+    buffer: &[u8],
+    start: usize,
+    position: usize,
+    idle: bool,
+    f: &mut F,
+) -> usize {
+    if position == start {
+        if idle {
+            f(&[], true);
+        }
+    } else if position > start {
+        f(&buffer[start..position], idle);
+    } else {
+        f(&buffer[start..], idle && position == 0);
+        if position > 0 {
+            f(&buffer[..position], idle);
+        }
+    }
+    position
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUFFER: [u8; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+    // f に渡された (データ, frame_end) を集める
+    fn collect(start: usize, position: usize, idle: bool) -> (usize, Vec<(Vec<u8>, bool)>) {
+        let mut calls = Vec::new();
+        let next = deliver_range(&BUFFER, start, position, idle, &mut |data, frame_end| {
+            calls.push((data.to_vec(), frame_end))
+        });
+        (next, calls)
+    }
+
+    #[test]
+    fn frame_within_buffer() {
+        assert_eq!(collect(1, 3, true), (3, vec![(vec![1, 2], true)]));
+        assert_eq!(collect(1, 3, false), (3, vec![(vec![1, 2], false)]));
+        assert_eq!(collect(3, 3, false), (3, vec![]));
+    }
+
+    #[test]
+    fn frame_wraps_around() {
+        assert_eq!(
+            collect(6, 2, true),
+            (2, vec![(vec![6, 7], false), (vec![0, 1], true)])
+        );
+        // ちょうどバッファの最後で終わる
+        assert_eq!(collect(6, 0, true), (0, vec![(vec![6, 7], true)]));
+    }
+
+    #[test]
+    fn half_buffer_frame_ends_on_idle() {
+        // バッファの半分ちょうどのフレームは HT で全部渡され、IDLE では空のデータで区切りだけ渡す
+        assert_eq!(collect(0, 4, false), (4, vec![(vec![0, 1, 2, 3], false)]));
+        assert_eq!(collect(4, 4, true), (4, vec![(vec![], true)]));
+        // 後半の場合は TC で渡される
+        assert_eq!(collect(4, 0, false), (0, vec![(vec![4, 5, 6, 7], false)]));
+        assert_eq!(collect(0, 0, true), (0, vec![(vec![], true)]));
+    }
+}
@@ -0,0 +1,77 @@
+// 固定長のリングバッファ（ヒープ無し）
+// 送受信の割り込みとメインの処理でバイト列を受け渡すのに使う。
+// 排他は呼び出し側で行う（Serial ごと Mutex に入れるなど）。
+
+pub struct RingBuffer<const N: usize> {
+    buffer: [u8; N],
+    // 次に読む位置
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        RingBuffer {
+            buffer: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    // 1バイト追加（一杯なら Err で返す）
+    pub fn push(&mut self, byte: u8) -> Result<(), u8> {
+        if self.len == N {
+            return Err(byte);
+        }
+        self.buffer[(self.head + self.len) % N] = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    // 1バイト取り出す
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    // 入るだけ追加して、追加したバイト数を返す
+    pub fn extend(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(N - self.len);
+        for &byte in &bytes[..count] {
+            self.push(byte).ok();
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,499 @@
+// シリアルポートのコマンドシェル
+// 受信したバイトを feed() に渡すと、行の編集（editor）、履歴（↑↓）、Tab での補完をして、
+// Enter で登録されたコマンドを実行する。出力は fmt::Write に書く（Serial などを渡す）。
+//
+//   let mut shell: Shell<Board> = Shell::new("> ");
+//   shell.register(builtin::COMMANDS).unwrap();
+//   shell.register(&[Command { name: "led", ... }]).unwrap();
+//   loop {
+//       if let Ok(byte) = serial.read() {
+//           shell.feed(byte, &mut board, &mut serial).ok();
+//       }
+//   }
+//
+// コマンドは名前と実行する関数の組み（Command）を static なスライスで登録する。
+// 関数にはコンテキスト C（ハードウェアなど、コマンドが使うもの）と引数、出力先が渡される。
+// help（コマンドの一覧）と history（履歴の一覧）はシェル自身が持っている。
+// builtin に GPIO/ADC/DAC/PWM/レジスタ/クロックのコマンドがある。
+
+pub mod builtin;
+pub mod editor;
+pub mod history;
+pub mod registers;
+
+use core::fmt::{self, Write};
+
+use editor::{Editor, Event};
+use history::History;
+
+// 1行の引数の最大数（コマンド名を含む）
+pub const MAX_ARGS: usize = 8;
+// register() できるコマンドのスライスの数
+pub const MAX_GROUPS: usize = 4;
+// Tab で一覧を表示する候補の最大数（多すぎる場合は数だけ表示する）
+const MAX_LIST: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // register() の数が MAX_GROUPS を超えた
+    TooManyGroups,
+}
+
+// コマンドの実行結果のエラー（シェルがメッセージを表示する）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandError {
+    // 引数の数や形が違う（使い方を表示する）
+    Usage,
+    // 引数の値が範囲外、または知らない名前
+    InvalidArgument,
+    // コマンドが使うハードウェアがコンテキストに無い
+    Unavailable,
+    Failed(&'static str),
+}
+
+pub type CommandResult = Result<(), CommandError>;
+
+// index 番目（0 から）の引数の候補をすべて f に渡す（Tab での補完に使う）
+pub type Complete = fn(index: usize, f: &mut dyn FnMut(&str));
+
+pub struct Command<C: 'static> {
+    pub name: &'static str,
+    // 引数の書き方（"<pin> [0|1]" など）
+    pub usage: &'static str,
+    pub help: &'static str,
+    // 引数（args[0] はコマンド名）を受け取って実行する
+    pub run: fn(&mut C, &[&str], &mut dyn Write) -> CommandResult,
+    pub complete: Option<Complete>,
+}
+
+pub struct Shell<C: 'static, const LEN: usize = 80, const HISTORY: usize = 8> {
+    groups: [&'static [Command<C>]; MAX_GROUPS],
+    group_count: usize,
+    editor: Editor<LEN>,
+    history: History<LEN, HISTORY>,
+    // 履歴を遡っている位置（None なら新しい行を入力中）
+    history_age: Option<usize>,
+    prompt: &'static str,
+}
+
+impl<C: 'static, const LEN: usize, const HISTORY: usize> Shell<C, LEN, HISTORY> {
+    pub fn new(prompt: &'static str) -> Self {
+        Shell {
+            groups: [&[]; MAX_GROUPS],
+            group_count: 0,
+            editor: Editor::new(),
+            history: History::new(),
+            history_age: None,
+            prompt,
+        }
+    }
+
+    // コマンドを追加する（同じ名前があれば先に登録したものが使われる）
+    pub fn register(&mut self, commands: &'static [Command<C>]) -> Result<(), Error> {
+        if self.group_count == MAX_GROUPS {
+            return Err(Error::TooManyGroups);
+        }
+        self.groups[self.group_count] = commands;
+        self.group_count += 1;
+        Ok(())
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &'static Command<C>> + '_ {
+        self.groups[..self.group_count]
+            .iter()
+            .flat_map(|group| group.iter())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&'static Command<C>> {
+        self.commands().find(|command| command.name == name)
+    }
+
+    // プロンプトを表示する（最初に1回呼ぶ）
+    pub fn start(&mut self, out: &mut dyn Write) -> fmt::Result {
+        self.editor.clear();
+        out.write_str("\r\n")?;
+        self.editor.redraw(out, self.prompt)
+    }
+
+    // 受信した1バイトを処理する（Enter ならコマンドを実行する）
+    pub fn feed(&mut self, byte: u8, context: &mut C, out: &mut dyn Write) -> fmt::Result {
+        match self.editor.feed(byte, out, self.prompt)? {
+            Event::None => Ok(()),
+            Event::Enter => {
+                out.write_str("\r\n")?;
+                let mut line = [0u8; LEN];
+                let text = self.editor.line().trim();
+                let len = text.len();
+                line[..len].copy_from_slice(text.as_bytes());
+                self.history.push(&line[..len]);
+                self.history_age = None;
+                self.editor.clear();
+                self.execute(
+                    core::str::from_utf8(&line[..len]).unwrap_or_default(),
+                    context,
+                    out,
+                )?;
+                self.editor.redraw(out, self.prompt)
+            }
+            Event::Cancel => {
+                out.write_str("^C\r\n")?;
+                self.history_age = None;
+                self.editor.clear();
+                self.editor.redraw(out, self.prompt)
+            }
+            Event::Up => {
+                let age = self.history_age.map_or(0, |age| age + 1);
+                match self.history.get(age) {
+                    Some(line) => {
+                        self.history_age = Some(age);
+                        self.editor.set(line, out, self.prompt)
+                    }
+                    None => out.write_char('\x07'),
+                }
+            }
+            Event::Down => match self.history_age {
+                Some(0) => {
+                    self.history_age = None;
+                    self.editor.set(b"", out, self.prompt)
+                }
+                Some(age) => {
+                    self.history_age = Some(age - 1);
+                    let line = self.history.get(age - 1).unwrap_or_default();
+                    self.editor.set(line, out, self.prompt)
+                }
+                None => out.write_char('\x07'),
+            },
+            Event::Tab => self.complete(out),
+        }
+    }
+
+    // 1行を実行する
+    pub fn execute(&mut self, line: &str, context: &mut C, out: &mut dyn Write) -> fmt::Result {
+        let mut args = [""; MAX_ARGS];
+        let mut count = 0;
+        for word in line.split_ascii_whitespace() {
+            if count == MAX_ARGS {
+                return writeln!(out, "error: too many arguments\r");
+            }
+            args[count] = word;
+            count += 1;
+        }
+        let args = &args[..count];
+        let Some(&name) = args.first() else {
+            return Ok(());
+        };
+
+        match name {
+            "help" => return self.help(args.get(1).copied(), out),
+            "history" => {
+                for age in (0..self.history.len()).rev() {
+                    let line = self.history.get(age).unwrap_or_default();
+                    let line = core::str::from_utf8(line).unwrap_or_default();
+                    writeln!(out, "{:>3}  {}\r", self.history.len() - age, line)?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        let Some(command) = self.find(name) else {
+            return writeln!(out, "{}: command not found (try help)\r", name);
+        };
+        match (command.run)(context, args, out) {
+            Ok(()) => Ok(()),
+            Err(CommandError::Usage) => self.usage(command, out),
+            Err(CommandError::InvalidArgument) => writeln!(out, "{}: invalid argument\r", name),
+            Err(CommandError::Unavailable) => writeln!(out, "{}: not available\r", name),
+            Err(CommandError::Failed(message)) => writeln!(out, "{}: {}\r", name, message),
+        }
+    }
+
+    fn usage(&self, command: &Command<C>, out: &mut dyn Write) -> fmt::Result {
+        if command.usage.is_empty() {
+            writeln!(out, "usage: {}\r", command.name)
+        } else {
+            writeln!(out, "usage: {} {}\r", command.name, command.usage)
+        }
+    }
+
+    fn help(&self, name: Option<&str>, out: &mut dyn Write) -> fmt::Result {
+        if let Some(name) = name {
+            return match self.find(name) {
+                Some(command) => {
+                    self.usage(command, out)?;
+                    writeln!(out, "  {}\r", command.help)
+                }
+                None => writeln!(out, "{}: command not found\r", name),
+            };
+        }
+        out.write_str("help      show commands, or usage of a command\r\n")?;
+        out.write_str("history   show command history\r\n")?;
+        for command in self.commands() {
+            writeln!(out, "{:<10}{}\r", command.name, command.help)?;
+        }
+        Ok(())
+    }
+
+    // 候補を順に f に渡す（index 0 はコマンド名）
+    fn candidates(&self, words: &[&str], index: usize, f: &mut dyn FnMut(&str)) {
+        if index == 0 {
+            f("help");
+            f("history");
+            for command in self.commands() {
+                f(command.name);
+            }
+        } else if words.first() == Some(&"help") && index == 1 {
+            for command in self.commands() {
+                f(command.name);
+            }
+        } else if let Some(complete) = words.first().and_then(|&name| self.find(name)?.complete) {
+            complete(index - 1, f);
+        }
+    }
+
+    // カーソルの前の単語を補完する
+    // 候補が1つならそれを入れ、複数なら共通部分まで入れる（それ以上進まなければ候補を表示する）。
+    fn complete(&mut self, out: &mut dyn Write) -> fmt::Result {
+        let mut before = [0u8; LEN];
+        let len = self.editor.before_cursor().len();
+        before[..len].copy_from_slice(self.editor.before_cursor().as_bytes());
+        let before = core::str::from_utf8(&before[..len]).unwrap_or_default();
+
+        let mut words = [""; MAX_ARGS];
+        let mut count = 0;
+        for word in before.split_ascii_whitespace().take(MAX_ARGS) {
+            words[count] = word;
+            count += 1;
+        }
+        // 補完する単語（カーソルの直前が空白なら新しい単語）
+        let (index, prefix) = if before.is_empty() || before.ends_with(' ') {
+            (count, "")
+        } else {
+            (count - 1, words[count - 1])
+        };
+        if index >= MAX_ARGS {
+            return out.write_char('\x07');
+        }
+
+        // 候補の共通部分
+        let mut common = [0u8; LEN];
+        let mut common_len = 0;
+        let mut matches = 0;
+        self.candidates(&words[..count], index, &mut |candidate| {
+            if !candidate.starts_with(prefix) || candidate.len() > LEN {
+                return;
+            }
+            let candidate = candidate.as_bytes();
+            if matches == 0 {
+                common[..candidate.len()].copy_from_slice(candidate);
+                common_len = candidate.len();
+            } else {
+                common_len = common[..common_len]
+                    .iter()
+                    .zip(candidate)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+            }
+            matches += 1;
+        });
+
+        let rest = core::str::from_utf8(&common[prefix.len().min(common_len)..common_len])
+            .unwrap_or_default();
+        match matches {
+            0 => out.write_char('\x07'),
+            // 1つに決まれば後ろに空白も入れる
+            1 => {
+                self.editor.insert(rest, out, self.prompt)?;
+                self.editor.insert(" ", out, self.prompt)
+            }
+            _ if !rest.is_empty() => self.editor.insert(rest, out, self.prompt),
+            _ => {
+                // 候補を並べて表示してから、行を表示し直す
+                out.write_str("\r\n")?;
+                if matches > MAX_LIST {
+                    writeln!(out, "({} candidates)\r", matches)?;
+                    return self.editor.redraw(out, self.prompt);
+                }
+                let mut result = Ok(());
+                self.candidates(&words[..count], index, &mut |candidate| {
+                    if candidate.starts_with(prefix) && result.is_ok() {
+                        result = write!(out, "{}  ", candidate);
+                    }
+                });
+                result?;
+                out.write_str("\r\n")?;
+                self.editor.redraw(out, self.prompt)
+            }
+        }
+    }
+}
+
+// 数値の引数（10進、0x で16進、0b で2進、_ は読み飛ばす）
+pub fn parse_number(s: &str) -> Result<u32, CommandError> {
+    let (digits, radix) = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        (bin, 2)
+    } else {
+        (s, 10)
+    };
+    let mut value: u32 = 0;
+    let mut any = false;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(radix).ok_or(CommandError::InvalidArgument)?;
+        value = value
+            .checked_mul(radix)
+            .and_then(|v| v.checked_add(digit))
+            .ok_or(CommandError::InvalidArgument)?;
+        any = true;
+    }
+    if any {
+        Ok(value)
+    } else {
+        Err(CommandError::InvalidArgument)
+    }
+}
+
+// 小数の引数を 10^decimals 倍した整数にする（"12.5" で decimals = 1 なら 125、桁が多い分は切り捨て）
+pub fn parse_decimal(s: &str, decimals: u32) -> Result<u32, CommandError> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if int.is_empty() && frac.is_empty() {
+        return Err(CommandError::InvalidArgument);
+    }
+    let mut value: u32 = 0;
+    let digits = int.chars().chain(
+        frac.chars()
+            .chain(core::iter::repeat('0'))
+            .take(decimals as usize),
+    );
+    for c in digits {
+        let digit = c.to_digit(10).ok_or(CommandError::InvalidArgument)?;
+        value = value
+            .checked_mul(10)
+            .and_then(|v| v.checked_add(digit))
+            .ok_or(CommandError::InvalidArgument)?;
+    }
+    if !frac.chars().all(|c| c.is_ascii_digit()) {
+        return Err(CommandError::InvalidArgument);
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nothing(_: &mut (), _: &[&str], _: &mut dyn Write) -> CommandResult {
+        Ok(())
+    }
+
+    fn complete_mode(index: usize, f: &mut dyn FnMut(&str)) {
+        if index == 0 {
+            f("single");
+            f("sine");
+        }
+    }
+
+    const COMMANDS: &[Command<()>] = &[
+        Command {
+            name: "status",
+            usage: "",
+            help: "",
+            run: nothing,
+            complete: None,
+        },
+        Command {
+            name: "stats",
+            usage: "",
+            help: "",
+            run: nothing,
+            complete: None,
+        },
+        Command {
+            name: "wave",
+            usage: "<mode>",
+            help: "",
+            run: nothing,
+            complete: Some(complete_mode),
+        },
+    ];
+
+    fn type_line(shell: &mut Shell<()>, text: &[u8]) -> String {
+        let mut out = String::new();
+        for &byte in text {
+            shell.feed(byte, &mut (), &mut out).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn completes_common_prefix() {
+        let mut shell = Shell::<()>::new("> ");
+        shell.register(COMMANDS).unwrap();
+
+        // status と stats の共通部分まで
+        type_line(&mut shell, b"st\t");
+        assert_eq!(shell.editor.line(), "stat");
+        // それ以上進まなければ候補を表示する
+        let out = type_line(&mut shell, b"\t");
+        assert!(out.contains("status  stats"));
+        assert_eq!(shell.editor.line(), "stat");
+        // 1つに決まれば空白も入れる
+        type_line(&mut shell, b"u\t");
+        assert_eq!(shell.editor.line(), "status ");
+
+        // 引数はコマンドの complete で補完する
+        type_line(&mut shell, b"\x15wa\tsi\tg\t");
+        assert_eq!(shell.editor.line(), "wave single ");
+        // help の後はコマンド名
+        type_line(&mut shell, b"\x15help w\t");
+        assert_eq!(shell.editor.line(), "help wave ");
+        // 候補が無ければ BEL
+        assert!(type_line(&mut shell, b"\x15x\t").ends_with("x\x07"));
+    }
+
+    #[test]
+    fn history_keys() {
+        let mut shell = Shell::<()>::new("> ");
+        shell.register(COMMANDS).unwrap();
+        type_line(&mut shell, b"stats\r  status  \rstatus\r");
+        // ↑ ↑ で古い行、↓ で戻る（前後の空白は除いて、同じ行は1つ）
+        type_line(&mut shell, b"\x1b[A\x1b[A");
+        assert_eq!(shell.editor.line(), "stats");
+        type_line(&mut shell, b"\x1b[B");
+        assert_eq!(shell.editor.line(), "status");
+        type_line(&mut shell, b"\x1b[B");
+        assert_eq!(shell.editor.line(), "");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("1234"), Ok(1234));
+        assert_eq!(parse_number("0x4002_0014"), Ok(0x4002_0014));
+        assert_eq!(parse_number("0B1010"), Ok(10));
+        assert_eq!(parse_number("0xFFFFFFFF"), Ok(u32::MAX));
+        assert_eq!(
+            parse_number("0x1_0000_0000"),
+            Err(CommandError::InvalidArgument)
+        );
+        assert_eq!(parse_number("0x"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse_number("12a"), Err(CommandError::InvalidArgument));
+        assert_eq!(parse_number("-1"), Err(CommandError::InvalidArgument));
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(parse_decimal("12.5", 1), Ok(125));
+        // 桁が多い分は切り捨て、足りない分は 0
+        assert_eq!(parse_decimal("12.345", 1), Ok(123));
+        assert_eq!(parse_decimal("12", 3), Ok(12_000));
+        assert_eq!(parse_decimal(".5", 2), Ok(50));
+        assert_eq!(parse_decimal("3.", 0), Ok(3));
+        assert_eq!(parse_decimal(".", 1), Err(CommandError::InvalidArgument));
+        assert_eq!(parse_decimal("1.2x", 1), Err(CommandError::InvalidArgument));
+        assert_eq!(parse_decimal("1,5", 1), Err(CommandError::InvalidArgument));
+        assert_eq!(
+            parse_decimal("4294967.296", 3),
+            Err(CommandError::InvalidArgument)
+        );
+    }
+}
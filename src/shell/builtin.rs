@@ -0,0 +1,647 @@
+// シェルの組み込みコマンド（Board をコンテキストにする）
+//   gpio  <pin> [0|1|in|out|analog]       ピンの読み出し/出力/モード設定（PA5 など）
+//   adc   <channel>                       ADC1 で1回変換して値と電圧を表示
+//   dac   <1|2> [value|<mV>mV]            DAC の出力値の表示/設定
+//   pwm   <timer> [freq <Hz>|<ch> <duty%>|<ch> off]  PWM の周波数/Duty の表示/設定
+//   peek  <register|address> [count]      レジスタを読む（GPIOA.ODR、0x40020014、GPIOA で全レジスタ）
+//   poke  <register|address> <value>      レジスタに書く
+//   clock                                 RCC のレジスタから求めたクロックの状態
+//
+// Board には使わせるペリフェラルだけを渡す（渡していないものは "not available" になる）。
+// GPIO とレジスタはアドレスで直接読み書きするので、他のドライバが使っているピンも変えられてしまう（デバッグ用）。
+
+use core::fmt::Write;
+use core::ptr;
+
+use stm32f4::stm32f446;
+use stm32f446::tim2;
+
+use super::{parse_decimal, parse_number, registers, Command, CommandError, CommandResult};
+use crate::adc::{self, Prescaler, SampleTime};
+use crate::clock::Clocks;
+use crate::dac::{Channel as DacChannel, Dac};
+use crate::systick;
+use crate::timer::{self, Channel};
+
+// add_pwm() できるタイマの数
+pub const MAX_PWM: usize = 4;
+// NUCLEO の HSE（ST-Link の MCO、バイパスモード）
+pub const HSE_HZ: u32 = 8_000_000;
+const HSI_HZ: u32 = 16_000_000;
+
+const GPIO_BASE: u32 = 0x4002_0000;
+const GPIO_SIZE: u32 = 0x400;
+const GPIO_MODER: u32 = 0x00;
+const GPIO_IDR: u32 = 0x10;
+const GPIO_ODR: u32 = 0x14;
+const GPIO_BSRR: u32 = 0x18;
+
+// PWM mode 1 (OCxM = 110)
+const OCM_PWM1: u32 = 0b110;
+// TIM の CR1.CEN, CR1.ARPE, EGR.UG
+const CR1_CEN: u32 = 1 << 0;
+const CR1_ARPE: u32 = 1 << 7;
+const EGR_UG: u32 = 1 << 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // add_pwm() の数が MAX_PWM を超えた
+    TooManyTimers,
+    // その周波数はタイマで作れない
+    InvalidFrequency,
+}
+
+struct Pwm {
+    name: &'static str,
+    tim: *const tim2::RegisterBlock,
+    clock: u32,
+    bits: u32,
+}
+
+impl Pwm {
+    fn regs(&self) -> &tim2::RegisterBlock {
+        unsafe { &*self.tim }
+    }
+}
+
+pub struct Board {
+    clocks: Clocks,
+    adc: Option<stm32f446::ADC1>,
+    adc_vref_mv: u32,
+    dac: Option<Dac>,
+    pwm: [Option<Pwm>; MAX_PWM],
+}
+
+impl Board {
+    pub fn new(clocks: Clocks) -> Self {
+        Board {
+            clocks,
+            adc: None,
+            adc_vref_mv: 3300,
+            dac: None,
+            pwm: Default::default(),
+        }
+    }
+
+    // adc コマンドで使う ADC1（クロック供給は呼び出し側で済ませておくこと）
+    // ADC クロックは PCLK2 / 4 にする（PCLK2 が 90MHz でも 36MHz 以下になるように）。
+    pub fn set_adc(&mut self, adc: stm32f446::ADC1, common: &stm32f446::ADC_COMMON) {
+        adc::set_prescaler(common, Prescaler::Div4);
+        self.adc = Some(adc);
+    }
+
+    // adc コマンドで mV に換算するときの VREF+[mV]（初期値 3.3V）
+    pub fn set_adc_vref(&mut self, vref_mv: u32) {
+        self.adc_vref_mv = vref_mv;
+    }
+
+    // dac コマンドで使う DAC（PA4/PA5 のアナログ設定は呼び出し側で済ませておくこと）
+    pub fn set_dac(&mut self, dac: Dac) {
+        self.dac = Some(dac);
+    }
+
+    // pwm コマンドで使うタイマを frequency[Hz] で動かし始め、実際の周波数を返す
+    // name は pwm コマンドで指定する名前（"TIM2" など）。タイマのクロック供給と出力ピンの設定は呼び出し側で行う。
+    pub fn add_pwm<TIM: timer::Instance>(
+        &mut self,
+        name: &'static str,
+        tim: TIM,
+        frequency: u32,
+    ) -> Result<u32, Error> {
+        let slot = self
+            .pwm
+            .iter()
+            .position(|p| p.is_none())
+            .ok_or(Error::TooManyTimers)?;
+        let pwm = Pwm {
+            name,
+            tim: tim.regs() as *const tim2::RegisterBlock,
+            clock: TIM::clock(&self.clocks),
+            bits: TIM::COUNTER_BITS,
+        };
+        let actual = set_frequency(&pwm, frequency).ok_or(Error::InvalidFrequency)?;
+        tim.enable_outputs();
+        let regs = pwm.regs();
+        regs.cr1
+            .modify(|r, w| unsafe { w.bits(r.bits() | CR1_ARPE | CR1_CEN) });
+        self.pwm[slot] = Some(pwm);
+        Ok(actual)
+    }
+
+    fn pwm(&self, name: &str) -> Option<&Pwm> {
+        self.pwm
+            .iter()
+            .flatten()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+pub const COMMANDS: &[Command<Board>] = &[
+    Command {
+        name: "gpio",
+        usage: "<pin> [0|1|in|out|analog]",
+        help: "read/write a GPIO pin (e.g. gpio PA5 1)",
+        run: gpio,
+        complete: Some(complete_gpio),
+    },
+    Command {
+        name: "adc",
+        usage: "<channel 0-18>",
+        help: "convert an ADC1 channel once",
+        run: adc,
+        complete: None,
+    },
+    Command {
+        name: "dac",
+        usage: "<1|2> [value|<mV>mV]",
+        help: "show/set a DAC output",
+        run: dac,
+        complete: Some(complete_dac),
+    },
+    Command {
+        name: "pwm",
+        usage: "<timer> [freq <Hz>|<ch 1-4> <duty%>|<ch 1-4> off]",
+        help: "show/set PWM frequency and duty",
+        run: pwm,
+        complete: Some(complete_pwm),
+    },
+    Command {
+        name: "peek",
+        usage: "<register|address> [count]",
+        help: "read registers (e.g. peek GPIOA.ODR, peek RCC)",
+        run: peek,
+        complete: Some(complete_register),
+    },
+    Command {
+        name: "poke",
+        usage: "<register|address> <value>",
+        help: "write a register (e.g. poke GPIOA.BSRR 0x20)",
+        run: poke,
+        complete: Some(complete_register),
+    },
+    Command {
+        name: "clock",
+        usage: "",
+        help: "show clock configuration",
+        run: clock,
+        complete: None,
+    },
+];
+
+fn read(address: u32) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn write(address: u32, value: u32) {
+    unsafe { ptr::write_volatile(address as *mut u32, value) }
+}
+
+// "PA5" を (ポートの番号, ピンの番号) に
+fn parse_pin(s: &str) -> Result<(u32, u32), CommandError> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 3 || !bytes[0].eq_ignore_ascii_case(&b'P') {
+        return Err(CommandError::InvalidArgument);
+    }
+    let port = bytes[1].to_ascii_uppercase().wrapping_sub(b'A') as u32;
+    let pin = s[2..]
+        .parse::<u32>()
+        .map_err(|_| CommandError::InvalidArgument)?;
+    if port > 7 || pin > 15 {
+        return Err(CommandError::InvalidArgument);
+    }
+    Ok((port, pin))
+}
+
+fn gpio(_: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let (port, pin) = match args {
+        [_, pin] | [_, pin, _] => parse_pin(pin)?,
+        _ => return Err(CommandError::Usage),
+    };
+    let base = GPIO_BASE + port * GPIO_SIZE;
+    // クロックが止まっているポートは読み書きできないので供給する
+    let rcc = unsafe { &*stm32f446::RCC::ptr() };
+    rcc.ahb1enr
+        .modify(|r, w| unsafe { w.bits(r.bits() | (1 << port)) });
+
+    let set_mode = |mode: u32| {
+        let moder = read(base + GPIO_MODER) & !(0b11 << (pin * 2));
+        write(base + GPIO_MODER, moder | (mode << (pin * 2)));
+    };
+    match args.get(2).copied() {
+        None => {}
+        Some("0") => write(base + GPIO_BSRR, 1 << (pin + 16)),
+        Some("1") => write(base + GPIO_BSRR, 1 << pin),
+        Some("in") => set_mode(0b00),
+        Some("out") => set_mode(0b01),
+        Some("analog") => set_mode(0b11),
+        Some(_) => return Err(CommandError::InvalidArgument),
+    }
+
+    let mode = match (read(base + GPIO_MODER) >> (pin * 2)) & 0b11 {
+        0b00 => "input",
+        0b01 => "output",
+        0b10 => "alternate",
+        _ => "analog",
+    };
+    let idr = (read(base + GPIO_IDR) >> pin) & 1;
+    let odr = (read(base + GPIO_ODR) >> pin) & 1;
+    writeln!(
+        out,
+        "P{}{}: {} ({}, odr {})\r",
+        (b'A' + port as u8) as char,
+        pin,
+        idr,
+        mode,
+        odr
+    )
+    .ok();
+    Ok(())
+}
+
+fn complete_gpio(index: usize, f: &mut dyn FnMut(&str)) {
+    match index {
+        0 => {
+            let mut name = [b'P', b'A', b'0', b'0'];
+            for port in b'A'..=b'H' {
+                name[1] = port;
+                for pin in 0..16u8 {
+                    let len = if pin < 10 {
+                        name[2] = b'0' + pin;
+                        3
+                    } else {
+                        name[2] = b'1';
+                        name[3] = b'0' + pin - 10;
+                        4
+                    };
+                    f(core::str::from_utf8(&name[..len]).unwrap_or_default());
+                }
+            }
+        }
+        1 => {
+            for s in ["0", "1", "in", "out", "analog"] {
+                f(s);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn adc(board: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let [_, channel] = args else {
+        return Err(CommandError::Usage);
+    };
+    let channel = parse_number(channel)?;
+    if channel > 18 {
+        return Err(CommandError::InvalidArgument);
+    }
+    let adc = board.adc.as_ref().ok_or(CommandError::Unavailable)?;
+    let regs = adc::Instance::regs(adc);
+    // 信号源のインピーダンスが分からないので一番長くする
+    adc::set_sample_time(regs, channel as u8, SampleTime::Cycles480);
+    let value = adc::convert(regs, channel as u8);
+    let mv = value as u32 * board.adc_vref_mv / 4095;
+    writeln!(out, "ch{}: {} ({} mV)\r", channel, value, mv).ok();
+    Ok(())
+}
+
+fn dac(board: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let channel = match args {
+        [_, "1"] | [_, "1", _] => DacChannel::C1,
+        [_, "2"] | [_, "2", _] => DacChannel::C2,
+        _ => return Err(CommandError::Usage),
+    };
+    let dac = board.dac.as_mut().ok_or(CommandError::Unavailable)?;
+    if let Some(value) = args.get(2) {
+        let mv = value
+            .strip_suffix("mV")
+            .or_else(|| value.strip_suffix("mv"));
+        match mv {
+            Some(mv) => {
+                dac.write_mv(channel, parse_number(mv)?)
+                    .map_err(|_| CommandError::InvalidArgument)?;
+            }
+            None => {
+                let value = parse_number(value)?;
+                if value > 4095 {
+                    return Err(CommandError::InvalidArgument);
+                }
+                dac.write(channel, value as u16);
+            }
+        }
+        dac.enable(channel);
+    }
+    writeln!(
+        out,
+        "dac{}: {} ({} mV)\r",
+        args[1],
+        dac.read(channel),
+        dac.read_mv(channel)
+    )
+    .ok();
+    Ok(())
+}
+
+fn complete_dac(index: usize, f: &mut dyn FnMut(&str)) {
+    if index == 0 {
+        f("1");
+        f("2");
+    }
+}
+
+// PSC と ARR を設定して実際の周波数を返す（Duty の比率は保つ）
+fn set_frequency(pwm: &Pwm, frequency: u32) -> Option<u32> {
+    let (psc, arr, actual) = timer::timer_period(pwm.clock, frequency, pwm.bits)?;
+    let regs = pwm.regs();
+    let old_period = regs.arr.read().bits() as u64 + 1;
+    let new_period = arr as u64 + 1;
+    for channel in [Channel::C1, Channel::C2, Channel::C3, Channel::C4] {
+        let ccr = compare(regs, channel) as u64;
+        timer::set_compare(regs, channel, (ccr * new_period / old_period) as u32);
+    }
+    regs.psc.write(|w| unsafe { w.bits(psc as u32) });
+    regs.arr.write(|w| unsafe { w.bits(arr) });
+    regs.egr.write(|w| unsafe { w.bits(EGR_UG) });
+    Some(actual)
+}
+
+fn compare(regs: &tim2::RegisterBlock, channel: Channel) -> u32 {
+    match channel {
+        Channel::C1 => regs.ccr1.read().bits(),
+        Channel::C2 => regs.ccr2.read().bits(),
+        Channel::C3 => regs.ccr3.read().bits(),
+        Channel::C4 => regs.ccr4.read().bits(),
+    }
+}
+
+fn pwm(board: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let Some(name) = args.get(1) else {
+        return Err(CommandError::Usage);
+    };
+    let pwm = board.pwm(name).ok_or(CommandError::Unavailable)?;
+    let regs = pwm.regs();
+    match args[2..] {
+        [] => {}
+        ["freq", frequency] => {
+            let actual = set_frequency(pwm, parse_number(frequency)?)
+                .ok_or(CommandError::InvalidArgument)?;
+            writeln!(out, "{}: {} Hz\r", pwm.name, actual).ok();
+            return Ok(());
+        }
+        [channel, duty] => {
+            let channel = match channel {
+                "1" => Channel::C1,
+                "2" => Channel::C2,
+                "3" => Channel::C3,
+                "4" => Channel::C4,
+                _ => return Err(CommandError::InvalidArgument),
+            };
+            if duty == "off" {
+                timer::enable_channel(regs, channel, false);
+            } else {
+                // 0.1% 単位
+                let permille = parse_decimal(duty.trim_end_matches('%'), 1)?;
+                if permille > 1000 {
+                    return Err(CommandError::InvalidArgument);
+                }
+                let period = regs.arr.read().bits() as u64 + 1;
+                timer::set_compare(regs, channel, (period * permille as u64 / 1000) as u32);
+                timer::set_output_mode(regs, channel, OCM_PWM1);
+                timer::enable_channel(regs, channel, true);
+            }
+        }
+        _ => return Err(CommandError::Usage),
+    }
+
+    // 現在の設定を表示
+    let period = regs.arr.read().bits() as u64 + 1;
+    let prescaler = regs.psc.read().bits() as u64 + 1;
+    let frequency = pwm.clock as u64 / (period * prescaler);
+    write!(out, "{}: {} Hz", pwm.name, frequency).ok();
+    let ccer = regs.ccer.read().bits();
+    for (i, channel) in [Channel::C1, Channel::C2, Channel::C3, Channel::C4]
+        .into_iter()
+        .enumerate()
+    {
+        if ccer & (1 << (i * 4)) != 0 {
+            let permille = compare(regs, channel) as u64 * 1000 / period;
+            write!(out, ", ch{} {}.{}%", i + 1, permille / 10, permille % 10).ok();
+        }
+    }
+    writeln!(out, "\r").ok();
+    Ok(())
+}
+
+fn complete_pwm(index: usize, f: &mut dyn FnMut(&str)) {
+    let candidates: &[&str] = match index {
+        0 => &["TIM1", "TIM2", "TIM3", "TIM4", "TIM5", "TIM8"],
+        1 => &["freq", "1", "2", "3", "4"],
+        2 => &["off"],
+        _ => &[],
+    };
+    for s in candidates {
+        f(s);
+    }
+}
+
+// レジスタ名か数値のアドレスを、アクセスしてよいか確認してアドレスに
+fn parse_address(s: &str, write: bool) -> Result<u32, CommandError> {
+    let address = match registers::address(s) {
+        Some(address) => address,
+        None => parse_number(s)?,
+    };
+    if registers::is_accessible(address, write) {
+        Ok(address)
+    } else {
+        Err(CommandError::Failed("address is not accessible"))
+    }
+}
+
+fn print_register(address: u32, out: &mut dyn Write) {
+    let value = read(address);
+    match registers::name_of(address) {
+        Some((peripheral, register)) => writeln!(
+            out,
+            "{:#010x} {:<18} = {:#010x}\r",
+            address,
+            Dotted(peripheral.name, register.name),
+            value
+        ),
+        None => writeln!(out, "{:#010x} {:<18} = {:#010x}\r", address, "", value),
+    }
+    .ok();
+}
+
+// "GPIOA.ODR" を幅を揃えて表示する
+struct Dotted(&'static str, &'static str);
+
+impl core::fmt::Display for Dotted {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let len = self.0.len() + 1 + self.1.len();
+        write!(f, "{}.{}", self.0, self.1)?;
+        for _ in len..f.width().unwrap_or(0) {
+            f.write_char(' ')?;
+        }
+        Ok(())
+    }
+}
+
+fn peek(_: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let (target, count) = match args {
+        [_, target] => (*target, 1),
+        [_, target, count] => (*target, parse_number(count)?),
+        _ => return Err(CommandError::Usage),
+    };
+    // ペリフェラル名だけなら全レジスタ
+    if let Some(peripheral) = registers::peripheral(target.trim_end_matches('.')) {
+        for register in peripheral.registers {
+            print_register(peripheral.base + register.offset, out);
+        }
+        return Ok(());
+    }
+    if count == 0 || count > 64 {
+        return Err(CommandError::InvalidArgument);
+    }
+    let start = parse_address(target, false)?;
+    for i in 0..count {
+        let address = start + i * 4;
+        if !registers::is_accessible(address, false) {
+            break;
+        }
+        print_register(address, out);
+    }
+    Ok(())
+}
+
+fn poke(_: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    let [_, target, value] = args else {
+        return Err(CommandError::Usage);
+    };
+    let address = parse_address(target, true)?;
+    write(address, parse_number(value)?);
+    print_register(address, out);
+    Ok(())
+}
+
+fn complete_register(index: usize, f: &mut dyn FnMut(&str)) {
+    if index == 0 {
+        registers::complete(f);
+    }
+}
+
+fn clock(board: &mut Board, args: &[&str], out: &mut dyn Write) -> CommandResult {
+    if args.len() != 1 {
+        return Err(CommandError::Usage);
+    }
+    let rcc = unsafe { &*stm32f446::RCC::ptr() };
+    let cr = rcc.cr.read().bits();
+    let pllcfgr = rcc.pllcfgr.read().bits();
+    let cfgr = rcc.cfgr.read().bits();
+
+    let hse = if cr & (1 << 18) != 0 {
+        "bypass"
+    } else {
+        "crystal"
+    };
+    writeln!(
+        out,
+        "HSI {}, HSE {} ({})\r",
+        on_off(cr & (1 << 1) != 0),
+        on_off(cr & (1 << 17) != 0),
+        hse
+    )
+    .ok();
+
+    let pll_source = if pllcfgr & (1 << 22) != 0 {
+        HSE_HZ
+    } else {
+        HSI_HZ
+    };
+    let m = pllcfgr & 0x3F;
+    let n = (pllcfgr >> 6) & 0x1FF;
+    let p = (((pllcfgr >> 16) & 0b11) + 1) * 2;
+    let r = (pllcfgr >> 28) & 0b111;
+    let vco = if m == 0 {
+        0
+    } else {
+        pll_source as u64 * n as u64 / m as u64
+    };
+    writeln!(
+        out,
+        "PLL {} ({} / M{} * N{} / P{} = {} Hz)\r",
+        on_off(cr & (1 << 25) != 0),
+        if pllcfgr & (1 << 22) != 0 {
+            "HSE"
+        } else {
+            "HSI"
+        },
+        m,
+        n,
+        p,
+        vco / p as u64
+    )
+    .ok();
+
+    let (source, sysclk) = match (cfgr >> 2) & 0b11 {
+        0b00 => ("HSI", HSI_HZ),
+        0b01 => ("HSE", HSE_HZ),
+        0b10 => ("PLL_P", (vco / p as u64) as u32),
+        _ => ("PLL_R", if r == 0 { 0 } else { (vco / r as u64) as u32 }),
+    };
+    let hpre = match (cfgr >> 4) & 0xF {
+        0b1000 => 2,
+        0b1001 => 4,
+        0b1010 => 8,
+        0b1011 => 16,
+        0b1100 => 64,
+        0b1101 => 128,
+        0b1110 => 256,
+        0b1111 => 512,
+        _ => 1,
+    };
+    let ppre = |bits: u32| match bits & 0b111 {
+        0b100 => 2,
+        0b101 => 4,
+        0b110 => 8,
+        0b111 => 16,
+        _ => 1,
+    };
+    let hclk = sysclk / hpre;
+    let clocks = Clocks {
+        sysclk,
+        hclk,
+        pclk1: hclk / ppre(cfgr >> 10),
+        pclk2: hclk / ppre(cfgr >> 13),
+    };
+    writeln!(out, "SYSCLK {} Hz ({})\r", clocks.sysclk, source).ok();
+    writeln!(out, "HCLK   {} Hz (/{})\r", clocks.hclk, hpre).ok();
+    writeln!(
+        out,
+        "PCLK1  {} Hz (/{}, timer {} Hz)\r",
+        clocks.pclk1,
+        ppre(cfgr >> 10),
+        clocks.timclk1()
+    )
+    .ok();
+    writeln!(
+        out,
+        "PCLK2  {} Hz (/{}, timer {} Hz)\r",
+        clocks.pclk2,
+        ppre(cfgr >> 13),
+        clocks.timclk2()
+    )
+    .ok();
+    if clocks != board.clocks {
+        writeln!(out, "(differs from the configured {:?})\r", board.clocks).ok();
+    }
+    let us = systick::uptime_us();
+    writeln!(out, "uptime {}.{:06} s\r", us / 1_000_000, us % 1_000_000).ok();
+    Ok(())
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
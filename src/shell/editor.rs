@@ -0,0 +1,355 @@
+// 1行の編集（VT100 互換のターミナル向け）
+// 受信したバイトを1つずつ feed() に渡すと、バッファを編集してエコーを出力する。
+// 行の確定や補完、履歴の呼び出しは Event で返し、Shell 側で処理する。
+//
+//   ← → / Ctrl-B Ctrl-F: カーソル移動、Home End / Ctrl-A Ctrl-E: 行頭/行末
+//   BS DEL: カーソルの前/位置の文字を消す、Ctrl-K: カーソルから行末まで消す
+//   Ctrl-U: 行を消す、Ctrl-W: カーソルの前の単語を消す、Ctrl-L: 画面を消す
+//   ↑ ↓ / Ctrl-P Ctrl-N: 履歴、Tab: 補完、Ctrl-C: 入力を取り消す
+// 入力できるのは ASCII の表示文字だけ（それ以外は無視する）。
+
+use core::fmt::{self, Write};
+
+const ESC: u8 = 0x1B;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    // 編集だけ（Shell は何もしない）
+    None,
+    Enter,
+    Tab,
+    Up,
+    Down,
+    Cancel,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Normal,
+    // ESC を受信した
+    Escape,
+    // ESC [ の後（最初の数字のパラメータ）
+    Csi(u8),
+    // ; の後のパラメータ（修飾キーなど。読み飛ばす）
+    CsiParams(u8),
+    // 中間バイトの後（知らないシーケンスなので、終端まで読み捨てる）
+    CsiIgnore,
+    // ESC O の後
+    Ss3,
+}
+
+pub struct Editor<const LEN: usize> {
+    buffer: [u8; LEN],
+    len: usize,
+    cursor: usize,
+    state: State,
+    // 直前が CR だった（CR LF の LF を無視する）
+    after_cr: bool,
+}
+
+impl<const LEN: usize> Editor<LEN> {
+    pub const fn new() -> Self {
+        Editor {
+            buffer: [0; LEN],
+            len: 0,
+            cursor: 0,
+            state: State::Normal,
+            after_cr: false,
+        }
+    }
+
+    // 入力中の行（ASCII だけなので常に UTF-8 として読める）
+    pub fn line(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.len]).unwrap_or_default()
+    }
+
+    // カーソルより前の部分
+    pub fn before_cursor(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.cursor]).unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.cursor = 0;
+    }
+
+    // 行を text に置き換えて表示し直す（カーソルは行末）
+    pub fn set(&mut self, text: &[u8], out: &mut dyn Write, prompt: &str) -> fmt::Result {
+        self.clear();
+        self.insert_bytes(text);
+        self.redraw(out, prompt)
+    }
+
+    // カーソルの位置に text を入れて表示し直す（入りきらない分は捨てる）
+    pub fn insert(&mut self, text: &str, out: &mut dyn Write, prompt: &str) -> fmt::Result {
+        self.insert_bytes(text.as_bytes());
+        self.redraw(out, prompt)
+    }
+
+    fn insert_bytes(&mut self, text: &[u8]) {
+        for &byte in text {
+            if self.len == LEN {
+                break;
+            }
+            if (0x20..0x7F).contains(&byte) {
+                self.buffer
+                    .copy_within(self.cursor..self.len, self.cursor + 1);
+                self.buffer[self.cursor] = byte;
+                self.len += 1;
+                self.cursor += 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, start: usize, end: usize) {
+        self.buffer.copy_within(end..self.len, start);
+        self.len -= end - start;
+        self.cursor = start;
+    }
+
+    // プロンプトから行全体を書き直して、カーソルを戻す
+    pub fn redraw(&self, out: &mut dyn Write, prompt: &str) -> fmt::Result {
+        write!(out, "\r{}{}\x1b[K", prompt, self.line())?;
+        if self.cursor < self.len {
+            write!(out, "\x1b[{}D", self.len - self.cursor)?;
+        }
+        Ok(())
+    }
+
+    pub fn feed(
+        &mut self,
+        byte: u8,
+        out: &mut dyn Write,
+        prompt: &str,
+    ) -> Result<Event, fmt::Error> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        match self.state {
+            State::Normal => {}
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => State::Csi(0),
+                    b'O' => State::Ss3,
+                    _ => State::Normal,
+                };
+                return Ok(Event::None);
+            }
+            State::Csi(param) if byte.is_ascii_digit() => {
+                self.state = State::Csi(param.saturating_mul(10).saturating_add(byte - b'0'));
+                return Ok(Event::None);
+            }
+            State::Csi(param) | State::CsiParams(param) => {
+                match byte {
+                    0x30..=0x3F => self.state = State::CsiParams(param),
+                    0x20..=0x2F => self.state = State::CsiIgnore,
+                    _ => self.state = State::Normal,
+                }
+                if !(0x40..=0x7E).contains(&byte) {
+                    return Ok(Event::None);
+                }
+                // 終端バイト（ESC [ 1 ; 5 C のような修飾キー付きも同じキーとして扱う）
+                return match (byte, param) {
+                    (b'~', 1 | 7) => self.home(out, prompt),
+                    (b'~', 4 | 8) => self.end(out, prompt),
+                    (b'~', 3) => self.delete(out, prompt),
+                    (b'~', _) => Ok(Event::None),
+                    _ => self.cursor_key(byte, out, prompt),
+                };
+            }
+            State::CsiIgnore => {
+                if !(0x20..=0x2F).contains(&byte) {
+                    self.state = State::Normal;
+                }
+                return Ok(Event::None);
+            }
+            State::Ss3 => {
+                self.state = State::Normal;
+                return self.cursor_key(byte, out, prompt);
+            }
+        }
+
+        match byte {
+            ESC => {
+                self.state = State::Escape;
+                Ok(Event::None)
+            }
+            b'\r' => Ok(Event::Enter),
+            b'\n' if after_cr => Ok(Event::None),
+            b'\n' => Ok(Event::Enter),
+            b'\t' => Ok(Event::Tab),
+            0x03 => Ok(Event::Cancel),
+            0x01 => self.home(out, prompt),
+            0x05 => self.end(out, prompt),
+            0x02 => self.cursor_key(b'D', out, prompt),
+            0x06 => self.cursor_key(b'C', out, prompt),
+            0x10 => Ok(Event::Up),
+            0x0E => Ok(Event::Down),
+            0x04 => self.delete(out, prompt),
+            0x08 | 0x7F => {
+                if self.cursor > 0 {
+                    self.remove(self.cursor - 1, self.cursor);
+                    self.redraw(out, prompt)?;
+                }
+                Ok(Event::None)
+            }
+            0x0B => {
+                self.len = self.cursor;
+                out.write_str("\x1b[K")?;
+                Ok(Event::None)
+            }
+            0x15 => {
+                self.clear();
+                self.redraw(out, prompt)?;
+                Ok(Event::None)
+            }
+            0x17 => {
+                let before = &self.buffer[..self.cursor];
+                let end = before.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+                let start = before[..end]
+                    .iter()
+                    .rposition(|&b| b == b' ')
+                    .map_or(0, |i| i + 1);
+                self.remove(start, self.cursor);
+                self.redraw(out, prompt)?;
+                Ok(Event::None)
+            }
+            0x0C => {
+                out.write_str("\x1b[2J\x1b[H")?;
+                self.redraw(out, prompt)?;
+                Ok(Event::None)
+            }
+            0x20..=0x7E => {
+                if self.len == LEN {
+                    // 一杯なら BEL を鳴らす
+                    out.write_char('\x07')?;
+                } else if self.cursor == self.len {
+                    self.insert_bytes(&[byte]);
+                    out.write_char(byte as char)?;
+                } else {
+                    self.insert_bytes(&[byte]);
+                    self.redraw(out, prompt)?;
+                }
+                Ok(Event::None)
+            }
+            _ => Ok(Event::None),
+        }
+    }
+
+    // ESC [ の後の A ~ D, H, F
+    fn cursor_key(
+        &mut self,
+        key: u8,
+        out: &mut dyn Write,
+        prompt: &str,
+    ) -> Result<Event, fmt::Error> {
+        match key {
+            b'A' => Ok(Event::Up),
+            b'B' => Ok(Event::Down),
+            b'C' => {
+                if self.cursor < self.len {
+                    self.cursor += 1;
+                    out.write_str("\x1b[C")?;
+                }
+                Ok(Event::None)
+            }
+            b'D' => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    out.write_str("\x1b[D")?;
+                }
+                Ok(Event::None)
+            }
+            b'H' => self.home(out, prompt),
+            b'F' => self.end(out, prompt),
+            _ => Ok(Event::None),
+        }
+    }
+
+    fn home(&mut self, out: &mut dyn Write, prompt: &str) -> Result<Event, fmt::Error> {
+        self.cursor = 0;
+        self.redraw(out, prompt)?;
+        Ok(Event::None)
+    }
+
+    fn end(&mut self, out: &mut dyn Write, prompt: &str) -> Result<Event, fmt::Error> {
+        self.cursor = self.len;
+        self.redraw(out, prompt)?;
+        Ok(Event::None)
+    }
+
+    fn delete(&mut self, out: &mut dyn Write, prompt: &str) -> Result<Event, fmt::Error> {
+        if self.cursor < self.len {
+            self.remove(self.cursor, self.cursor + 1);
+            self.redraw(out, prompt)?;
+        }
+        Ok(Event::None)
+    }
+}
+
+impl<const LEN: usize> Default for Editor<LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(editor: &mut Editor<16>, bytes: &[u8]) -> Vec<Event> {
+        let mut out = String::new();
+        bytes
+            .iter()
+            .map(|&byte| editor.feed(byte, &mut out, "> ").unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn editing_keys() {
+        let mut editor = Editor::<16>::new();
+        feed(&mut editor, b"gpio pa5 1\x08");
+        assert_eq!(editor.line(), "gpio pa5 ");
+        // Ctrl-W は空白を含めて前の単語を消す
+        feed(&mut editor, b"\x17");
+        assert_eq!(editor.line(), "gpio ");
+        feed(&mut editor, b"\x17\x17");
+        assert_eq!(editor.line(), "");
+
+        // Ctrl-K はカーソルから行末まで消す
+        feed(&mut editor, b"peek 0x20\x02\x02\x0B");
+        assert_eq!(editor.line(), "peek 0x");
+        // カーソルの途中で BS、DEL（ESC [ 3 ~）
+        feed(&mut editor, b"\x1b[D\x08\x1b[3~");
+        assert_eq!(editor.line(), "peek ");
+        assert_eq!(feed(&mut editor, b"\r\n"), [Event::Enter, Event::None]);
+    }
+
+    #[test]
+    fn escape_sequences_with_parameters() {
+        let mut editor = Editor::<16>::new();
+        feed(&mut editor, b"abc");
+        // Ctrl-← は ← として扱い、5 や D を入力しない
+        feed(&mut editor, b"\x1b[1;5D");
+        feed(&mut editor, b"x");
+        assert_eq!(editor.line(), "abxc");
+        // Home（ESC [ 1 ~）、End（ESC O F）
+        feed(&mut editor, b"\x1b[1~y\x1bOFz");
+        assert_eq!(editor.line(), "yabxcz");
+        // 中間バイトのある知らないシーケンスは読み捨てる
+        feed(&mut editor, b"\x1b[1 q\x1b[?25h");
+        assert_eq!(editor.line(), "yabxcz");
+        let events = feed(&mut editor, b"\x1b[1;2A");
+        assert_eq!(events.last(), Some(&Event::Up));
+        assert_eq!(editor.line(), "yabxcz");
+    }
+
+    #[test]
+    fn full_line_is_not_extended() {
+        let mut editor = Editor::<4>::new();
+        let mut out = String::new();
+        for &byte in b"abcde" {
+            editor.feed(byte, &mut out, "").unwrap();
+        }
+        assert_eq!(editor.line(), "abcd");
+        assert!(out.ends_with('\x07'));
+    }
+}
@@ -0,0 +1,100 @@
+// コマンド履歴（固定長の行を N 個まで、古いものから捨てる）
+// 空の行と、直前と同じ行は追加しない。
+
+pub struct History<const LEN: usize, const N: usize> {
+    lines: [[u8; LEN]; N],
+    lens: [usize; N],
+    // 一番古い行の位置
+    head: usize,
+    count: usize,
+}
+
+impl<const LEN: usize, const N: usize> History<LEN, N> {
+    pub const fn new() -> Self {
+        History {
+            lines: [[0; LEN]; N],
+            lens: [0; N],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, line: &[u8]) {
+        if N == 0 || line.is_empty() || self.get(0) == Some(line) {
+            return;
+        }
+        let index = if self.count == N {
+            let index = self.head;
+            self.head = (self.head + 1) % N;
+            index
+        } else {
+            self.count += 1;
+            (self.head + self.count - 1) % N
+        };
+        let len = line.len().min(LEN);
+        self.lines[index][..len].copy_from_slice(&line[..len]);
+        self.lens[index] = len;
+    }
+
+    // age 番目に新しい行（0 が一番新しい）
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        if age >= self.count {
+            return None;
+        }
+        let index = (self.head + self.count - 1 - age) % N;
+        Some(&self.lines[index][..self.lens[index]])
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.count = 0;
+    }
+}
+
+impl<const LEN: usize, const N: usize> Default for History<LEN, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around_and_skips_duplicates() {
+        let mut history = History::<8, 3>::new();
+        history.push(b"");
+        assert!(history.is_empty());
+        history.push(b"one");
+        history.push(b"one");
+        assert_eq!(history.len(), 1);
+        history.push(b"two");
+        history.push(b"one");
+        assert_eq!(history.len(), 3);
+
+        // 一杯になったら一番古い行を捨てる
+        history.push(b"three");
+        history.push(b"four");
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(0), Some(&b"four"[..]));
+        assert_eq!(history.get(1), Some(&b"three"[..]));
+        assert_eq!(history.get(2), Some(&b"one"[..]));
+        assert_eq!(history.get(3), None);
+
+        // 長すぎる行は LEN で切る
+        history.push(b"0123456789");
+        assert_eq!(history.get(0), Some(&b"01234567"[..]));
+
+        history.clear();
+        assert_eq!(history.get(0), None);
+    }
+}
@@ -0,0 +1,491 @@
+// レジスタの名前とアドレスの表（peek/poke コマンドで "GPIOA.ODR" のように名前で指定するため）
+// アドレスは RM0390 のメモリマップとレジスタマップから（PAC のポインタは const で整数にできないので値で書く）。
+
+pub struct Register {
+    pub name: &'static str,
+    pub offset: u32,
+}
+
+pub struct Peripheral {
+    pub name: &'static str,
+    pub base: u32,
+    pub registers: &'static [Register],
+}
+
+macro_rules! registers {
+    ($($name:ident = $offset:expr),* $(,)?) => {
+        &[$(Register { name: stringify!($name), offset: $offset }),*]
+    };
+}
+
+// DMA のストリームごとのレジスタ（S0CR など）
+macro_rules! dma_registers {
+    ($($stream:literal),*) => {
+        &[
+            Register { name: "LISR", offset: 0x00 },
+            Register { name: "HISR", offset: 0x04 },
+            Register { name: "LIFCR", offset: 0x08 },
+            Register { name: "HIFCR", offset: 0x0C },
+            $(
+                Register { name: concat!("S", $stream, "CR"), offset: 0x10 + 0x18 * $stream },
+                Register { name: concat!("S", $stream, "NDTR"), offset: 0x14 + 0x18 * $stream },
+                Register { name: concat!("S", $stream, "PAR"), offset: 0x18 + 0x18 * $stream },
+                Register { name: concat!("S", $stream, "M0AR"), offset: 0x1C + 0x18 * $stream },
+                Register { name: concat!("S", $stream, "M1AR"), offset: 0x20 + 0x18 * $stream },
+                Register { name: concat!("S", $stream, "FCR"), offset: 0x24 + 0x18 * $stream },
+            )*
+        ]
+    };
+}
+
+const GPIO: &[Register] = registers!(
+    MODER = 0x00,
+    OTYPER = 0x04,
+    OSPEEDR = 0x08,
+    PUPDR = 0x0C,
+    IDR = 0x10,
+    ODR = 0x14,
+    BSRR = 0x18,
+    LCKR = 0x1C,
+    AFRL = 0x20,
+    AFRH = 0x24,
+);
+
+// TIM1 ~ TIM5, TIM8（RCR と BDTR は TIM1/TIM8 のみ）
+const TIM: &[Register] = registers!(
+    CR1 = 0x00,
+    CR2 = 0x04,
+    SMCR = 0x08,
+    DIER = 0x0C,
+    SR = 0x10,
+    EGR = 0x14,
+    CCMR1 = 0x18,
+    CCMR2 = 0x1C,
+    CCER = 0x20,
+    CNT = 0x24,
+    PSC = 0x28,
+    ARR = 0x2C,
+    RCR = 0x30,
+    CCR1 = 0x34,
+    CCR2 = 0x38,
+    CCR3 = 0x3C,
+    CCR4 = 0x40,
+    BDTR = 0x44,
+    DCR = 0x48,
+    DMAR = 0x4C,
+);
+
+const BASIC_TIM: &[Register] = registers!(
+    CR1 = 0x00,
+    CR2 = 0x04,
+    DIER = 0x0C,
+    SR = 0x10,
+    EGR = 0x14,
+    CNT = 0x24,
+    PSC = 0x28,
+    ARR = 0x2C,
+);
+
+const USART: &[Register] = registers!(
+    SR = 0x00,
+    DR = 0x04,
+    BRR = 0x08,
+    CR1 = 0x0C,
+    CR2 = 0x10,
+    CR3 = 0x14,
+    GTPR = 0x18,
+);
+
+const ADC: &[Register] = registers!(
+    SR = 0x00,
+    CR1 = 0x04,
+    CR2 = 0x08,
+    SMPR1 = 0x0C,
+    SMPR2 = 0x10,
+    JOFR1 = 0x14,
+    JOFR2 = 0x18,
+    JOFR3 = 0x1C,
+    JOFR4 = 0x20,
+    HTR = 0x24,
+    LTR = 0x28,
+    SQR1 = 0x2C,
+    SQR2 = 0x30,
+    SQR3 = 0x34,
+    JSQR = 0x38,
+    JDR1 = 0x3C,
+    JDR2 = 0x40,
+    JDR3 = 0x44,
+    JDR4 = 0x48,
+    DR = 0x4C,
+);
+
+const ADC_COMMON: &[Register] = registers!(CSR = 0x00, CCR = 0x04, CDR = 0x08);
+
+const DAC: &[Register] = registers!(
+    CR = 0x00,
+    SWTRIGR = 0x04,
+    DHR12R1 = 0x08,
+    DHR12L1 = 0x0C,
+    DHR8R1 = 0x10,
+    DHR12R2 = 0x14,
+    DHR12L2 = 0x18,
+    DHR8R2 = 0x1C,
+    DHR12RD = 0x20,
+    DHR12LD = 0x24,
+    DHR8RD = 0x28,
+    DOR1 = 0x2C,
+    DOR2 = 0x30,
+    SR = 0x34,
+);
+
+const RCC: &[Register] = registers!(
+    CR = 0x00,
+    PLLCFGR = 0x04,
+    CFGR = 0x08,
+    CIR = 0x0C,
+    AHB1RSTR = 0x10,
+    AHB2RSTR = 0x14,
+    AHB3RSTR = 0x18,
+    APB1RSTR = 0x20,
+    APB2RSTR = 0x24,
+    AHB1ENR = 0x30,
+    AHB2ENR = 0x34,
+    AHB3ENR = 0x38,
+    APB1ENR = 0x40,
+    APB2ENR = 0x44,
+    AHB1LPENR = 0x50,
+    AHB2LPENR = 0x54,
+    AHB3LPENR = 0x58,
+    APB1LPENR = 0x60,
+    APB2LPENR = 0x64,
+    BDCR = 0x70,
+    CSR = 0x74,
+    SSCGR = 0x80,
+    PLLI2SCFGR = 0x84,
+    PLLSAICFGR = 0x88,
+    DCKCFGR = 0x8C,
+    CKGATENR = 0x90,
+    DCKCFGR2 = 0x94,
+);
+
+const DMA: &[Register] = dma_registers!(0, 1, 2, 3, 4, 5, 6, 7);
+
+const EXTI: &[Register] = registers!(
+    IMR = 0x00,
+    EMR = 0x04,
+    RTSR = 0x08,
+    FTSR = 0x0C,
+    SWIER = 0x10,
+    PR = 0x14,
+);
+
+const SYSCFG: &[Register] = registers!(
+    MEMRMP = 0x00,
+    PMC = 0x04,
+    EXTICR1 = 0x08,
+    EXTICR2 = 0x0C,
+    EXTICR3 = 0x10,
+    EXTICR4 = 0x14,
+    CMPCR = 0x20,
+    CFGR = 0x2C,
+);
+
+const FLASH: &[Register] = registers!(
+    ACR = 0x00,
+    KEYR = 0x04,
+    OPTKEYR = 0x08,
+    SR = 0x0C,
+    CR = 0x10,
+    OPTCR = 0x14,
+);
+
+const PWR: &[Register] = registers!(CR = 0x00, CSR = 0x04);
+
+const SYST: &[Register] = registers!(CSR = 0x00, RVR = 0x04, CVR = 0x08, CALIB = 0x0C);
+
+const DWT: &[Register] = registers!(CTRL = 0x00, CYCCNT = 0x04);
+
+pub const PERIPHERALS: &[Peripheral] = &[
+    Peripheral {
+        name: "TIM2",
+        base: 0x4000_0000,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "TIM3",
+        base: 0x4000_0400,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "TIM4",
+        base: 0x4000_0800,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "TIM5",
+        base: 0x4000_0C00,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "TIM6",
+        base: 0x4000_1000,
+        registers: BASIC_TIM,
+    },
+    Peripheral {
+        name: "TIM7",
+        base: 0x4000_1400,
+        registers: BASIC_TIM,
+    },
+    Peripheral {
+        name: "USART2",
+        base: 0x4000_4400,
+        registers: USART,
+    },
+    Peripheral {
+        name: "USART3",
+        base: 0x4000_4800,
+        registers: USART,
+    },
+    Peripheral {
+        name: "PWR",
+        base: 0x4000_7000,
+        registers: PWR,
+    },
+    Peripheral {
+        name: "DAC",
+        base: 0x4000_7400,
+        registers: DAC,
+    },
+    Peripheral {
+        name: "TIM1",
+        base: 0x4001_0000,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "TIM8",
+        base: 0x4001_0400,
+        registers: TIM,
+    },
+    Peripheral {
+        name: "USART1",
+        base: 0x4001_1000,
+        registers: USART,
+    },
+    Peripheral {
+        name: "USART6",
+        base: 0x4001_1400,
+        registers: USART,
+    },
+    Peripheral {
+        name: "ADC1",
+        base: 0x4001_2000,
+        registers: ADC,
+    },
+    Peripheral {
+        name: "ADC2",
+        base: 0x4001_2100,
+        registers: ADC,
+    },
+    Peripheral {
+        name: "ADC3",
+        base: 0x4001_2200,
+        registers: ADC,
+    },
+    Peripheral {
+        name: "ADC_COMMON",
+        base: 0x4001_2300,
+        registers: ADC_COMMON,
+    },
+    Peripheral {
+        name: "SYSCFG",
+        base: 0x4001_3800,
+        registers: SYSCFG,
+    },
+    Peripheral {
+        name: "EXTI",
+        base: 0x4001_3C00,
+        registers: EXTI,
+    },
+    Peripheral {
+        name: "GPIOA",
+        base: 0x4002_0000,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOB",
+        base: 0x4002_0400,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOC",
+        base: 0x4002_0800,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOD",
+        base: 0x4002_0C00,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOE",
+        base: 0x4002_1000,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOF",
+        base: 0x4002_1400,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOG",
+        base: 0x4002_1800,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "GPIOH",
+        base: 0x4002_1C00,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "RCC",
+        base: 0x4002_3800,
+        registers: RCC,
+    },
+    Peripheral {
+        name: "FLASH",
+        base: 0x4002_3C00,
+        registers: FLASH,
+    },
+    Peripheral {
+        name: "DMA1",
+        base: 0x4002_6000,
+        registers: DMA,
+    },
+    Peripheral {
+        name: "DMA2",
+        base: 0x4002_6400,
+        registers: DMA,
+    },
+    Peripheral {
+        name: "DWT",
+        base: 0xE000_1000,
+        registers: DWT,
+    },
+    Peripheral {
+        name: "SYST",
+        base: 0xE000_E010,
+        registers: SYST,
+    },
+];
+
+// 数値で直接アクセスしてよいメモリの範囲 (先頭, 末尾の次, 書き込めるか)
+const MEMORY: &[(u32, u32, bool)] = &[
+    (0x0800_0000, 0x0808_0000, false), // Flash
+    (0x1FFF_7A00, 0x1FFF_7A30, false), // UID, Flash サイズ, ADC の校正値など
+    (0x2000_0000, 0x2002_0000, true),  // SRAM1, SRAM2
+];
+
+impl Peripheral {
+    // 表にあるレジスタの範囲 (先頭, 末尾の次)
+    pub fn span(&self) -> (u32, u32) {
+        let end = self
+            .registers
+            .iter()
+            .map(|r| r.offset + 4)
+            .max()
+            .unwrap_or(0);
+        (self.base, self.base + end)
+    }
+}
+
+pub fn peripheral(name: &str) -> Option<&'static Peripheral> {
+    PERIPHERALS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+// "GPIOA.ODR" をアドレスに（大文字小文字は区別しない）
+pub fn address(name: &str) -> Option<u32> {
+    let (peripheral_name, register_name) = name.split_once('.')?;
+    let peripheral = peripheral(peripheral_name)?;
+    let register = peripheral
+        .registers
+        .iter()
+        .find(|r| r.name.eq_ignore_ascii_case(register_name))?;
+    Some(peripheral.base + register.offset)
+}
+
+// 32bit でアクセスしてよいアドレスか
+// 4 バイト境界で、MEMORY の中か、PERIPHERALS のペリフェラルのレジスタの範囲の中のものだけ。
+// （予約された領域や表に無いペリフェラルは BusFault になることがあるので断る）
+pub fn is_accessible(address: u32, write: bool) -> bool {
+    if address & 0b11 != 0 {
+        return false;
+    }
+    MEMORY
+        .iter()
+        .any(|&(start, end, writable)| (start..end).contains(&address) && (writable || !write))
+        || PERIPHERALS.iter().any(|p| {
+            let (start, end) = p.span();
+            (start..end).contains(&address)
+        })
+}
+
+// アドレスからレジスタの名前を探す（表示用）
+pub fn name_of(address: u32) -> Option<(&'static Peripheral, &'static Register)> {
+    PERIPHERALS.iter().find_map(|p| {
+        let offset = address.checked_sub(p.base)?;
+        p.registers
+            .iter()
+            .find(|r| r.offset == offset)
+            .map(|r| (p, r))
+    })
+}
+
+// 補完の候補（"GPIOA." と "GPIOA.ODR" のすべて）
+pub fn complete(f: &mut dyn FnMut(&str)) {
+    let mut name = [0u8; 32];
+    for peripheral in PERIPHERALS {
+        let len = peripheral.name.len();
+        name[..len].copy_from_slice(peripheral.name.as_bytes());
+        name[len] = b'.';
+        f(core::str::from_utf8(&name[..len + 1]).unwrap_or_default());
+        for register in peripheral.registers {
+            let end = len + 1 + register.name.len();
+            name[len + 1..end].copy_from_slice(register.name.as_bytes());
+            f(core::str::from_utf8(&name[..end]).unwrap_or_default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_names() {
+        assert_eq!(address("GPIOA.ODR"), Some(0x4002_0014));
+        assert_eq!(address("dma2.s7fcr"), Some(0x4002_6400 + 0x24 + 0x18 * 7));
+        assert_eq!(address("GPIOA"), None);
+        assert_eq!(address("GPIOA.XYZ"), None);
+        let (peripheral, register) = name_of(0x4000_0034).unwrap();
+        assert_eq!((peripheral.name, register.name), ("TIM2", "CCR1"));
+    }
+
+    #[test]
+    fn only_known_ranges_are_accessible() {
+        assert!(is_accessible(0x2000_0000, true));
+        assert!(is_accessible(0x0800_0000, false));
+        assert!(!is_accessible(0x0800_0000, true));
+        assert!(!is_accessible(0x2000_0002, false));
+        // 表にあるペリフェラルのレジスタ
+        assert!(is_accessible(0x4002_0014, true));
+        assert!(is_accessible(0x4000_004C, true));
+        assert!(is_accessible(0xE000_E018, false));
+        // 最後のレジスタ（TIM2.DMAR）の先や、表に無いペリフェラル・予約された領域
+        assert!(!is_accessible(0x4000_0050, false));
+        assert!(!is_accessible(0x4000_2000, false));
+        assert!(!is_accessible(0x4007_FFFC, false));
+        assert!(!is_accessible(0xE000_ED00, false));
+    }
+}
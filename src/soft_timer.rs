@@ -0,0 +1,381 @@
+// ソフトウェアタイマ（ハッシュ化タイミングホイール）
+// ハードウェアのティック1つで多数のワンショット/周期タイマを動かす。
+// タイマは期限 % SLOTS のスロットに双方向リストでつなぐので、登録も取り消しも O(1)。
+// ティックごとに今のスロットだけを見て、期限が来ているものを発火させる。
+// （1周以上先の期限のものは期限を比べて残しておく）
+//
+// 時刻はティック数で扱い、ハードウェアには依存しない。
+// tick() を SysTick などの割り込みから呼ぶか、Clock を実装した偽の時計で advance_to() すればよい。
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const NIL: u16 = u16::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // 登録できるタイマ数(N)を使い切った
+    Full,
+}
+
+// 期限が来た時の動作
+#[derive(Clone, Copy)]
+pub enum Action {
+    // フラグを立てる（メインループで見て処理する）
+    Flag(&'static AtomicBool),
+    // 関数を呼ぶ（tick() を呼んだコンテキスト、つまり割り込み内で実行される）
+    Callback(fn()),
+}
+
+// 登録したタイマの識別子
+// 発火済み/取り消し済みのタイマのハンドルで cancel() しても他のタイマには影響しない。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimerHandle {
+    index: u16,
+    generation: u16,
+}
+
+// 現在時刻[tick]を返すもの（ハードウェアタイマ、テスト用の偽の時計など）
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    deadline: u64,
+    // 0 ならワンショット
+    period: u32,
+    action: Option<Action>,
+    prev: u16,
+    next: u16,
+    generation: u16,
+    active: bool,
+}
+
+impl Node {
+    const EMPTY: Node = Node {
+        deadline: 0,
+        period: 0,
+        action: None,
+        prev: NIL,
+        next: NIL,
+        generation: 0,
+        active: false,
+    };
+}
+
+// SLOTS: ホイールのスロット数（2のべき乗にしておくと剰余が軽い）
+// N: 同時に登録できるタイマ数
+pub struct TimerWheel<const SLOTS: usize, const N: usize> {
+    now: u64,
+    slots: [u16; SLOTS],
+    nodes: [Node; N],
+    // 未使用ノードのリスト（next でつなぐ）
+    free: u16,
+}
+
+impl<const SLOTS: usize, const N: usize> TimerWheel<SLOTS, N> {
+    pub const fn new() -> Self {
+        let mut nodes = [Node::EMPTY; N];
+        let mut i = 0;
+        while i < N {
+            nodes[i].next = if i + 1 < N { (i + 1) as u16 } else { NIL };
+            i += 1;
+        }
+        TimerWheel {
+            now: 0,
+            slots: [NIL; SLOTS],
+            nodes,
+            free: if N > 0 { 0 } else { NIL },
+        }
+    }
+
+    // 現在時刻[tick]
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    // delay[tick] 後に1回だけ発火
+    pub fn one_shot(&mut self, delay: u32, action: Action) -> Result<TimerHandle, Error> {
+        self.start(delay, 0, action)
+    }
+
+    // period[tick] ごとに発火
+    pub fn periodic(&mut self, period: u32, action: Action) -> Result<TimerHandle, Error> {
+        self.start(period, period, action)
+    }
+
+    // delay[tick] 後に発火し、period が0以外ならその後 period ごとに発火
+    // delay = 0 は次のティックで発火する。
+    pub fn start(&mut self, delay: u32, period: u32, action: Action) -> Result<TimerHandle, Error> {
+        let index = self.free;
+        if index == NIL {
+            return Err(Error::Full);
+        }
+        let node = &mut self.nodes[index as usize];
+        self.free = node.next;
+
+        node.deadline = self.now + delay.max(1) as u64;
+        node.period = period;
+        node.action = Some(action);
+        node.active = true;
+        let handle = TimerHandle {
+            index,
+            generation: node.generation,
+        };
+        self.link(index);
+        Ok(handle)
+    }
+
+    // タイマを取り消す（既に発火済み/取り消し済みなら false）
+    pub fn cancel(&mut self, handle: TimerHandle) -> bool {
+        if !self.is_active(handle) {
+            return false;
+        }
+        self.unlink(handle.index);
+        self.release(handle.index);
+        true
+    }
+
+    pub fn is_active(&self, handle: TimerHandle) -> bool {
+        match self.nodes.get(handle.index as usize) {
+            Some(node) => node.active && node.generation == handle.generation,
+            None => false,
+        }
+    }
+
+    // 1ティック進めて、期限の来たタイマを発火させる（発火した数を返す）
+    pub fn tick(&mut self) -> usize {
+        self.now += 1;
+        let slot = (self.now % SLOTS as u64) as usize;
+
+        let mut fired = 0;
+        let mut index = self.slots[slot];
+        while index != NIL {
+            let next = self.nodes[index as usize].next;
+            let node = self.nodes[index as usize];
+            if node.deadline <= self.now {
+                self.unlink(index);
+                if node.period == 0 {
+                    self.release(index);
+                } else {
+                    // 周期タイマは同じノードのまま次の期限で登録し直す（ハンドルはそのまま使える）
+                    self.nodes[index as usize].deadline = node.deadline + node.period as u64;
+                    self.link(index);
+                }
+                if let Some(action) = node.action {
+                    match action {
+                        Action::Flag(flag) => flag.store(true, Ordering::Release),
+                        Action::Callback(callback) => callback(),
+                    }
+                }
+                fired += 1;
+            }
+            index = next;
+        }
+        fired
+    }
+
+    // now[tick] まで1ティックずつ進める（発火した数を返す）
+    pub fn advance_to(&mut self, now: u64) -> usize {
+        let mut fired = 0;
+        while self.now < now {
+            fired += self.tick();
+        }
+        fired
+    }
+
+    // 時計の現在時刻まで進める
+    pub fn poll<C: Clock>(&mut self, clock: &C) -> usize {
+        self.advance_to(clock.now())
+    }
+
+    // 期限のスロットのリスト先頭につなぐ
+    fn link(&mut self, index: u16) {
+        let slot = (self.nodes[index as usize].deadline % SLOTS as u64) as usize;
+        let head = self.slots[slot];
+        self.nodes[index as usize].prev = NIL;
+        self.nodes[index as usize].next = head;
+        if head != NIL {
+            self.nodes[head as usize].prev = index;
+        }
+        self.slots[slot] = index;
+    }
+
+    fn unlink(&mut self, index: u16) {
+        let Node {
+            prev,
+            next,
+            deadline,
+            ..
+        } = self.nodes[index as usize];
+        if prev != NIL {
+            self.nodes[prev as usize].next = next;
+        } else {
+            let slot = (deadline % SLOTS as u64) as usize;
+            self.slots[slot] = next;
+        }
+        if next != NIL {
+            self.nodes[next as usize].prev = prev;
+        }
+    }
+
+    // 未使用リストに戻す（世代を進めて古いハンドルを無効にする）
+    fn release(&mut self, index: u16) {
+        let node = &mut self.nodes[index as usize];
+        node.active = false;
+        node.action = None;
+        node.generation = node.generation.wrapping_add(1);
+        node.prev = NIL;
+        node.next = self.free;
+        self.free = index;
+    }
+}
+
+impl<const SLOTS: usize, const N: usize> Default for TimerWheel<SLOTS, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    // 手で進める偽の時計
+    struct ManualClock(Cell<u64>);
+
+    impl ManualClock {
+        fn advance(&self, ticks: u64) {
+            self.0.set(self.0.get() + ticks);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn nop() {}
+
+    #[test]
+    fn one_shot_fires_once_at_deadline() {
+        static FIRED: AtomicBool = AtomicBool::new(false);
+        let clock = ManualClock(Cell::new(0));
+        let mut wheel: TimerWheel<8, 4> = TimerWheel::new();
+        let handle = wheel.one_shot(3, Action::Flag(&FIRED)).unwrap();
+
+        clock.advance(2);
+        assert_eq!(wheel.poll(&clock), 0);
+        assert!(!FIRED.load(Ordering::Acquire));
+        clock.advance(1);
+        assert_eq!(wheel.poll(&clock), 1);
+        assert!(FIRED.load(Ordering::Acquire));
+        assert!(!wheel.is_active(handle));
+
+        clock.advance(16);
+        assert_eq!(wheel.poll(&clock), 0);
+    }
+
+    #[test]
+    fn zero_delay_fires_on_next_tick() {
+        let mut wheel: TimerWheel<8, 4> = TimerWheel::new();
+        wheel.one_shot(0, Action::Callback(nop)).unwrap();
+        assert_eq!(wheel.tick(), 1);
+    }
+
+    #[test]
+    fn periodic_fires_every_period() {
+        let clock = ManualClock(Cell::new(0));
+        let mut wheel: TimerWheel<8, 4> = TimerWheel::new();
+        let handle = wheel.periodic(5, Action::Callback(nop)).unwrap();
+
+        clock.advance(4);
+        assert_eq!(wheel.poll(&clock), 0);
+        clock.advance(1);
+        assert_eq!(wheel.poll(&clock), 1);
+        // 5, 10, ..., 50 で計10回
+        clock.advance(45);
+        assert_eq!(wheel.poll(&clock), 9);
+        assert!(wheel.is_active(handle));
+
+        assert!(wheel.cancel(handle));
+        clock.advance(50);
+        assert_eq!(wheel.poll(&clock), 0);
+    }
+
+    #[test]
+    fn cancel_and_stale_handle() {
+        static KEPT: AtomicBool = AtomicBool::new(false);
+        let mut wheel: TimerWheel<8, 1> = TimerWheel::new();
+        let stale = wheel.one_shot(2, Action::Callback(nop)).unwrap();
+        assert!(wheel.cancel(stale));
+        assert!(!wheel.cancel(stale));
+        assert_eq!(wheel.advance_to(4), 0);
+
+        // 同じノードが再利用されても古いハンドルでは取り消せない
+        let handle = wheel.one_shot(2, Action::Flag(&KEPT)).unwrap();
+        assert_ne!(handle, stale);
+        assert!(!wheel.is_active(stale));
+        assert!(!wheel.cancel(stale));
+        assert_eq!(wheel.advance_to(6), 1);
+        assert!(KEPT.load(Ordering::Acquire));
+
+        // 発火済みのハンドルも無効
+        assert!(!wheel.cancel(handle));
+    }
+
+    #[test]
+    fn deadline_beyond_one_turn() {
+        let mut wheel: TimerWheel<8, 4> = TimerWheel::new();
+        let far = wheel.one_shot(20, Action::Callback(nop)).unwrap();
+        let near = wheel.one_shot(4, Action::Callback(nop)).unwrap();
+
+        // 同じスロット(4)を 4, 12 で通るが、期限の来ていない far は残る
+        assert_eq!(wheel.advance_to(4), 1);
+        assert!(!wheel.is_active(near));
+        assert_eq!(wheel.advance_to(19), 0);
+        assert!(wheel.is_active(far));
+        assert_eq!(wheel.advance_to(20), 1);
+        assert!(!wheel.is_active(far));
+    }
+
+    #[test]
+    fn slot_index_wraps_around() {
+        // 期限のスロットが末尾から先頭へ回り込むタイマ
+        let mut wheel: TimerWheel<8, 4> = TimerWheel::new();
+        wheel.advance_to(6);
+        let handle = wheel.one_shot(3, Action::Callback(nop)).unwrap();
+        assert_eq!(wheel.advance_to(8), 0);
+        assert_eq!(wheel.advance_to(9), 1);
+        assert!(!wheel.is_active(handle));
+
+        // 周期タイマがホイールを何周しても期限どおりに発火する
+        let periodic = wheel.periodic(3, Action::Callback(nop)).unwrap();
+        let mut fired = [0u64; 4];
+        let mut count = 0;
+        while count < fired.len() {
+            if wheel.tick() == 1 {
+                fired[count] = wheel.now();
+                count += 1;
+            }
+        }
+        assert_eq!(fired, [12, 15, 18, 21]);
+        assert!(wheel.cancel(periodic));
+    }
+
+    #[test]
+    fn full_until_released() {
+        let mut wheel: TimerWheel<8, 2> = TimerWheel::new();
+        let first = wheel.one_shot(1, Action::Callback(nop)).unwrap();
+        wheel.periodic(4, Action::Callback(nop)).unwrap();
+        assert_eq!(wheel.one_shot(1, Action::Callback(nop)), Err(Error::Full));
+
+        // ワンショットが発火すると空きができる
+        assert_eq!(wheel.tick(), 1);
+        assert!(!wheel.is_active(first));
+        assert!(wheel.one_shot(1, Action::Callback(nop)).is_ok());
+        assert_eq!(wheel.periodic(1, Action::Callback(nop)), Err(Error::Full));
+    }
+}
@@ -0,0 +1,220 @@
+// SysTick を使ったタイムベース
+// 指定したティック周波数で SysTick 割り込みを発生させ、起動からのティック数(64bit)を数える。
+// アプリ側の SysTick 例外ハンドラから on_tick() を呼ぶこと。
+//
+// #[exception]
+// fn SysTick() {
+//     systick::on_tick();
+// }
+
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::syst::SystClkSource;
+use cortex_m::peripheral::{SCB, SYST};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::clock::Clocks;
+use crate::soft_timer;
+
+// 登録できるティックフックの数
+pub const MAX_HOOKS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // 24bit のリロード値ではそのティック周波数を作れない
+    InvalidRate,
+    // フックの登録数が MAX_HOOKS を超えた
+    TooManyHooks,
+}
+
+// ティックフックの識別子（remove_hook に渡す）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HookId(usize);
+
+// 割り込みから呼ぶティックフック（引数はティック数）
+type Hooks = [Option<fn(u64)>; MAX_HOOKS];
+
+static TICKS: Mutex<Cell<u64>> = Mutex::new(Cell::new(0));
+static HOOKS: Mutex<RefCell<Hooks>> = Mutex::new(RefCell::new([None; MAX_HOOKS]));
+
+// us 単位の時刻計算用（new() で設定）
+static TICK_HZ: AtomicU32 = AtomicU32::new(0);
+static COUNTER_HZ: AtomicU32 = AtomicU32::new(0);
+static RELOAD: AtomicU32 = AtomicU32::new(0);
+
+// tick_hz の割り込みを作るクロックソースとリロード値を求める
+// 戻り値は (クロックソース, リロード値, SysTick のカウント周波数[Hz])
+// HCLK で収まればそのまま、収まらなければ HCLK/8 を使う。
+pub fn reload_for(hclk: u32, tick_hz: u32) -> Option<(SystClkSource, u32, u32)> {
+    if tick_hz == 0 {
+        return None;
+    }
+    for (source, counter_hz) in [
+        (SystClkSource::Core, hclk),
+        (SystClkSource::External, hclk / 8),
+    ] {
+        let counts = counter_hz / tick_hz;
+        if (1..=1 << 24).contains(&counts) {
+            return Some((source, counts - 1, counter_hz));
+        }
+    }
+    None
+}
+
+pub struct TimeBase {
+    syst: SYST,
+}
+
+impl TimeBase {
+    // tick_hz[Hz] でティックを開始する（1000 なら1msティック）
+    pub fn new(mut syst: SYST, clocks: &Clocks, tick_hz: u32) -> Result<Self, Error> {
+        let (source, reload, counter_hz) =
+            reload_for(clocks.hclk, tick_hz).ok_or(Error::InvalidRate)?;
+
+        TICK_HZ.store(tick_hz, Ordering::Relaxed);
+        COUNTER_HZ.store(counter_hz, Ordering::Relaxed);
+        RELOAD.store(reload, Ordering::Relaxed);
+        cortex_m::interrupt::free(|cs| TICKS.borrow(cs).set(0));
+
+        syst.disable_counter();
+        syst.set_clock_source(source);
+        syst.set_reload(reload);
+        syst.clear_current();
+        syst.enable_interrupt();
+        syst.enable_counter();
+
+        Ok(TimeBase { syst })
+    }
+
+    pub fn tick_hz(&self) -> u32 {
+        TICK_HZ.load(Ordering::Relaxed)
+    }
+
+    pub fn delay(&self) -> Delay {
+        Delay { _private: () }
+    }
+
+    pub fn release(mut self) -> SYST {
+        self.syst.disable_interrupt();
+        self.syst.disable_counter();
+        self.syst
+    }
+}
+
+// SysTick 例外ハンドラから呼ぶ
+pub fn on_tick() {
+    let (ticks, hooks) = cortex_m::interrupt::free(|cs| {
+        let ticks = TICKS.borrow(cs).get() + 1;
+        TICKS.borrow(cs).set(ticks);
+        (ticks, *HOOKS.borrow(cs).borrow())
+    });
+    // フックは割り込み許可のまま呼ぶ（中で add_hook/remove_hook しても良いように）
+    for hook in hooks.iter().flatten() {
+        hook(ticks);
+    }
+}
+
+// ティックごとに呼ぶ関数を登録（引数は起動からのティック数）
+// SysTick 割り込み内で呼ばれるので、重い処理はフラグを立ててメインループで行うこと。
+pub fn add_hook(hook: fn(u64)) -> Result<HookId, Error> {
+    cortex_m::interrupt::free(|cs| {
+        let mut hooks = HOOKS.borrow(cs).borrow_mut();
+        let index = hooks
+            .iter()
+            .position(|h| h.is_none())
+            .ok_or(Error::TooManyHooks)?;
+        hooks[index] = Some(hook);
+        Ok(HookId(index))
+    })
+}
+
+pub fn remove_hook(id: HookId) {
+    cortex_m::interrupt::free(|cs| HOOKS.borrow(cs).borrow_mut()[id.0] = None);
+}
+
+// 起動からのティック数
+pub fn ticks() -> u64 {
+    cortex_m::interrupt::free(|cs| TICKS.borrow(cs).get())
+}
+
+// 起動からの時間[ms]
+pub fn uptime_ms() -> u64 {
+    uptime_us() / 1000
+}
+
+// 起動からの時間[us]（ティック未満は SysTick のカウント値から求める）
+pub fn uptime_us() -> u64 {
+    let tick_hz = TICK_HZ.load(Ordering::Relaxed) as u64;
+    let counter_hz = COUNTER_HZ.load(Ordering::Relaxed) as u64;
+    let reload = RELOAD.load(Ordering::Relaxed) as u64;
+    if tick_hz == 0 {
+        return 0;
+    }
+
+    let (ticks, elapsed) = cortex_m::interrupt::free(|cs| {
+        let mut ticks = TICKS.borrow(cs).get();
+        let mut current = SYST::get_current() as u64;
+        // 割り込み禁止中に0を跨いでいたら、まだ数えていない1ティック分を足す
+        if SCB::is_pendst_pending() {
+            ticks += 1;
+            current = SYST::get_current() as u64;
+        }
+        (ticks, reload - current)
+    });
+    ticks * 1_000_000 / tick_hz + elapsed * 1_000_000 / counter_hz
+}
+
+// uptime_us() をビジーウェイトで見るディレイ（SysTick の割り込みが止まっていると戻らない）
+#[derive(Clone, Copy)]
+pub struct Delay {
+    _private: (),
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        let start = uptime_us();
+        while uptime_us() - start < us as u64 {}
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        self.delay_us(us as u32);
+    }
+}
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        let start = uptime_us();
+        while uptime_us() - start < ms as u64 * 1000 {}
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.delay_ms(ms as u32);
+    }
+}
+
+// ソフトウェアタイマの時計として使う（単位はティック）
+pub struct Uptime;
+
+impl soft_timer::Clock for Uptime {
+    fn now(&self) -> u64 {
+        ticks()
+    }
+}
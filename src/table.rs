@@ -0,0 +1,320 @@
+// PWM の Duty や DAC の出力値のテーブルを const fn で作る
+// static や const の初期化に使えるので、テーブルはコンパイル時に計算されて Flash に置かれる。
+// 値は 0 ~ max に収まるように作るので、max にタイマの ARR（や DAC の 4095）を渡せば、
+// タイマの分解能を変えてもテーブルを書き直さなくてよい。
+//
+//   static DUTY: [u32; 100] = table::triangle(ARR);
+//   static SINE: [u16; 64] = table::to_u16(table::sine(4095));
+//   static CUBE: [u32; 32] = table!(32, |i| table::scale(table::pow(table::ramp(i, 32), 300), ARR));
+//
+// 計算は整数のみ（const fn で浮動小数点を使わない）。比率は 16bit の小数部を持つ固定小数点(Q16)で、ONE が 1.0。
+
+pub const ONE: u32 = 1 << 16;
+
+// 2π (Q30)
+const TWO_PI_Q30: i128 = 6_746_518_852;
+// 2^(2^-k) (Q30, k = 1 ~ 16)
+const EXP2_FRAC_Q30: [u64; 16] = [
+    1_518_500_250,
+    1_276_901_417,
+    1_170_923_762,
+    1_121_280_436,
+    1_097_253_708,
+    1_085_434_106,
+    1_079_572_136,
+    1_076_653_033,
+    1_075_196_443,
+    1_074_468_888,
+    1_074_105_294,
+    1_073_923_544,
+    1_073_832_680,
+    1_073_787_251,
+    1_073_764_537,
+    1_073_753_181,
+];
+
+// i 番目の要素を、式(body)で計算したテーブル（i は usize。static の初期化にも使える）
+#[macro_export]
+macro_rules! table {
+    ($len:expr, |$i:ident| $body:expr) => {{
+        let mut table = [0u32; $len];
+        let mut $i = 0;
+        while $i < $len {
+            table[$i] = $body;
+            $i += 1;
+        }
+        table
+    }};
+}
+
+// 0 ~ ONE の比率を 0 ~ max に変換（四捨五入）
+pub const fn scale(ratio: u32, max: u32) -> u32 {
+    let ratio = if ratio > ONE { ONE } else { ratio };
+    ((ratio as u64 * max as u64 + (ONE as u64 / 2)) >> 16) as u32
+}
+
+// len 個のうち i 番目が 0 ~ ONE のどこか（最初が 0、最後が ONE）
+pub const fn ramp(i: usize, len: usize) -> u32 {
+    if len < 2 {
+        return 0;
+    }
+    let last = (len - 1) as u64;
+    ((i as u64 * ONE as u64 + last / 2) / last) as u32
+}
+
+// sin(2π * i / len) (Q16、-ONE ~ ONE)
+// 1/4 周期に折り返してテイラー展開（11次）で求める。
+pub const fn sin(i: usize, len: usize) -> i32 {
+    if len == 0 {
+        return 0;
+    }
+    // 位相(1周 = 2^32)
+    let turn = (((i % len) as u64) << 32) / len as u64;
+    let quadrant = turn >> 30;
+    let mut r = (turn & ((1 << 30) - 1)) as i128;
+    if quadrant % 2 == 1 {
+        r = (1 << 30) - r;
+    }
+    // 角度 [rad] (Q30)
+    let x = (r * TWO_PI_Q30) >> 32;
+    let x2 = (x * x) >> 30;
+    // x - x^3/3! + x^5/5! - ... = x(1 - x^2/(2*3)(1 - x^2/(4*5)(1 - ...)))
+    let one = 1i128 << 30;
+    let mut acc = one;
+    let mut k = 5;
+    while k > 0 {
+        let n = 2 * k as i128;
+        acc = one - ((x2 * acc) >> 30) / (n * (n + 1));
+        k -= 1;
+    }
+    let y = (x * acc) >> 30;
+    let y = ((y + (1 << 13)) >> 14) as i32; // Q30 -> Q16
+    if quadrant >= 2 {
+        -y
+    } else {
+        y
+    }
+}
+
+// cos(2π * i / len) (Q16)
+pub const fn cos(i: usize, len: usize) -> i32 {
+    // 1/4 周期ずらす（len が 4 の倍数でない場合は位相を 4 倍して求める）
+    if len.is_multiple_of(4) {
+        sin(i + len / 4, len)
+    } else {
+        sin(4 * i + len, 4 * len)
+    }
+}
+
+// log2(x) (x は Q16 で 0 < x <= ONE、結果は Q16 で 0 以下)
+const fn log2(x: u32) -> i64 {
+    // [1, 2) (Q30) に正規化
+    let shift = x.leading_zeros() as i64 - 15;
+    let mut v = (x as u64) << (14 + shift);
+    let mut result = -shift << 16;
+    let mut bit = 1 << 15;
+    while bit > 0 {
+        v = (v * v) >> 30;
+        if v >= 2 << 30 {
+            v >>= 1;
+            result += bit;
+        }
+        bit >>= 1;
+    }
+    result
+}
+
+// 2^y (y は Q16 で 0 以下、結果は Q16)
+const fn exp2(y: i64) -> u32 {
+    let int = y >> 16; // 切り捨て（負の方向）
+    if int < -17 {
+        return 0;
+    }
+    let frac = (y - (int << 16)) as u64;
+    let mut v: u64 = 1 << 30;
+    let mut k = 0;
+    while k < 16 {
+        if frac & (1 << (15 - k)) != 0 {
+            v = (v * EXP2_FRAC_Q30[k]) >> 30;
+        }
+        k += 1;
+    }
+    // Q30 -> Q16 と 2^int
+    let shift = (14 - int) as u32;
+    ((v + (1 << (shift - 1))) >> shift) as u32
+}
+
+// x^(gamma_x100 / 100) (x は Q16 で 0 ~ ONE)
+// 例えば gamma_x100 = 220 でガンマ 2.2（LED の明るさを見た目に合わせる）。
+pub const fn pow(x: u32, gamma_x100: u32) -> u32 {
+    if x == 0 {
+        return if gamma_x100 == 0 { ONE } else { 0 };
+    }
+    let x = if x > ONE { ONE } else { x };
+    exp2(log2(x) * gamma_x100 as i64 / 100)
+}
+
+// 三角波: 0 から max まで上がって 0 に戻る（前半の最後と後半の最初が max）
+pub const fn triangle<const N: usize>(max: u32) -> [u32; N] {
+    let peak = if N > 1 { ((N - 1) / 2) as u64 } else { 1 };
+    let mut table = [0u32; N];
+    let mut i = 0;
+    while i < N {
+        let distance = if i < N - 1 - i { i } else { N - 1 - i };
+        table[i] = ((max as u64 * distance as u64 + peak / 2) / peak) as u32;
+        i += 1;
+    }
+    table
+}
+
+// 正弦波: max / 2 から始まる1周期（0 ~ max）
+pub const fn sine<const N: usize>(max: u32) -> [u32; N] {
+    let mut table = [0u32; N];
+    let mut i = 0;
+    while i < N {
+        let ratio = (ONE as i32 + sin(i, N)) as u32 / 2;
+        table[i] = scale(ratio, max);
+        i += 1;
+    }
+    table
+}
+
+// 呼吸するような明滅: (1 - cos) / 2 で 0 -> max -> 0 と変化させ、ガンマ補正(gamma_x100 / 100)をかける
+pub const fn breathing<const N: usize>(max: u32, gamma_x100: u32) -> [u32; N] {
+    let mut table = [0u32; N];
+    let mut i = 0;
+    while i < N {
+        let ratio = (ONE as i32 - cos(i, N)) as u32 / 2;
+        table[i] = scale(pow(ratio, gamma_x100), max);
+        i += 1;
+    }
+    table
+}
+
+// u16 のテーブルに変換（DAC の DMA やハーフワード転送用、u16 に収まらない値は切り詰める）
+pub const fn to_u16<const N: usize>(table: [u32; N]) -> [u16; N] {
+    let mut result = [0u16; N];
+    let mut i = 0;
+    while i < N {
+        result[i] = if table[i] > u16::MAX as u32 {
+            u16::MAX
+        } else {
+            table[i] as u16
+        };
+        i += 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-4;
+
+    fn q16(x: i64) -> f64 {
+        x as f64 / ONE as f64
+    }
+
+    // 0 < x <= 1 の Q16 の値（端と細かい値を含める）
+    fn ratios() -> impl Iterator<Item = u32> {
+        (1..=ONE).step_by(97).chain([1, 2, ONE / 2, ONE - 1, ONE])
+    }
+
+    fn assert_near(actual: f64, expected: f64, what: &str) {
+        assert!(
+            (actual - expected).abs() <= EPSILON,
+            "{}: {} != {}",
+            what,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sin_cos_match_f64() {
+        for len in [1, 3, 4, 7, 64, 100, 360, 1000] {
+            for i in 0..2 * len {
+                let turn = 2.0 * core::f64::consts::PI * i as f64 / len as f64;
+                assert_near(q16(sin(i, len) as i64), turn.sin(), "sin");
+                assert_near(q16(cos(i, len) as i64), turn.cos(), "cos");
+            }
+        }
+        assert_eq!(sin(0, 0), 0);
+    }
+
+    #[test]
+    fn log2_exp2_match_f64() {
+        for x in ratios() {
+            let expected = q16(x as i64).log2();
+            assert_near(q16(log2(x)), expected, "log2");
+            assert_near(q16(exp2(log2(x)) as i64), q16(x as i64), "exp2(log2)");
+        }
+        for y in (-17 * ONE as i64..=0).step_by(331) {
+            assert_near(q16(exp2(y) as i64), q16(y).exp2(), "exp2");
+        }
+        assert_eq!(exp2(-18 * ONE as i64), 0);
+    }
+
+    #[test]
+    fn pow_matches_f64() {
+        for gamma_x100 in [0, 50, 100, 180, 220, 300] {
+            for x in ratios() {
+                let expected = q16(x as i64).powf(gamma_x100 as f64 / 100.0);
+                assert_near(q16(pow(x, gamma_x100) as i64), expected, "pow");
+            }
+        }
+        assert_eq!(pow(0, 220), 0);
+        assert_eq!(pow(0, 0), ONE);
+        // 1.0 を超える値は 1.0 として扱う
+        assert_eq!(pow(2 * ONE, 220), ONE);
+    }
+
+    #[test]
+    fn triangle_matches_duty_table() {
+        // 以前 examples/dma.rs に手で書いていたテーブル（ARR = 49）
+        const DUTY_TABLE: [u32; 100] = [
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23,
+            24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45,
+            46, 47, 48, 49, 49, 48, 47, 46, 45, 44, 43, 42, 41, 40, 39, 38, 37, 36, 35, 34, 33, 32,
+            31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12, 11, 10,
+            9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+        ];
+        assert_eq!(triangle::<100>(49), DUTY_TABLE);
+        assert_eq!(triangle::<5>(100), [0, 50, 100, 50, 0]);
+        assert_eq!(triangle::<1>(100), [0]);
+    }
+
+    #[test]
+    fn sine_and_breathing_match_f64() {
+        const LEN: usize = 100;
+        let sine: [u32; LEN] = sine(4095);
+        let breathing: [u32; LEN] = breathing(4095, 220);
+        for i in 0..LEN {
+            let turn = 2.0 * core::f64::consts::PI * i as f64 / LEN as f64;
+            let expected = (1.0 + turn.sin()) / 2.0 * 4095.0;
+            assert!((sine[i] as f64 - expected).abs() <= 0.5 + EPSILON * 4095.0);
+            let expected = ((1.0 - turn.cos()) / 2.0).powf(2.2) * 4095.0;
+            assert!((breathing[i] as f64 - expected).abs() <= 0.5 + EPSILON * 4095.0);
+        }
+        assert_eq!(sine[0], 2048);
+        assert_eq!(sine[LEN / 4], 4095);
+        assert_eq!(sine[3 * LEN / 4], 0);
+        assert_eq!(breathing[0], 0);
+        assert_eq!(breathing[LEN / 2], 4095);
+    }
+
+    #[test]
+    fn scale_ramp_and_to_u16() {
+        assert_eq!(scale(0, 4095), 0);
+        assert_eq!(scale(ONE / 2, 4095), 2048);
+        assert_eq!(scale(ONE, 4095), 4095);
+        assert_eq!(scale(2 * ONE, 4095), 4095);
+        assert_eq!(ramp(0, 5), 0);
+        assert_eq!(ramp(2, 5), ONE / 2);
+        assert_eq!(ramp(4, 5), ONE);
+        assert_eq!(ramp(0, 1), 0);
+        assert_eq!(to_u16([0, 4095, 70_000]), [0, 4095, u16::MAX]);
+        assert_eq!(table!(4, |i| scale(ramp(i, 4), 3)), [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,224 @@
+// 汎用/高機能タイマ(TIM1 ~ TIM5, TIM8)の共通処理
+// レジスタ配置はこれらのタイマで共通なので、TIM2 のレジスタブロックとして扱う。
+// （TIM3/TIM4/TIM1/TIM8 はカウンタ上位16bitが予約、TIM1/TIM8 は RCR と BDTR が追加されている）
+
+use stm32f4::stm32f446;
+use stm32f446::tim2;
+
+use crate::clock::Clocks;
+
+pub mod master_slave;
+pub mod one_pulse;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // 指定時間がプリスケーラとカウンタ幅で表現できない
+    OutOfRange,
+    // トリガ入力と出力で同じチャンネルを使おうとした
+    ChannelConflict,
+    // マスタタイマがスレーブタイマの ITR に接続されていない
+    NoInternalTrigger,
+}
+
+// タイマの識別子（ITR の接続表を引くのに使う）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimerId {
+    Tim1,
+    Tim2,
+    Tim3,
+    Tim4,
+    Tim5,
+    Tim8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    C1,
+    C2,
+    C3,
+    C4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Polarity {
+    Rising,
+    Falling,
+}
+
+pub trait Instance {
+    const ID: TimerId;
+
+    // カウンタのビット幅（TIM2/TIM5 は32bit、それ以外は16bit）
+    const COUNTER_BITS: u32;
+
+    fn regs(&self) -> &tim2::RegisterBlock;
+
+    // RCC からクロック供給
+    fn enable_clock(rcc: &stm32f446::RCC);
+
+    // タイマに入るクロック周波数[Hz]
+    fn clock(clocks: &Clocks) -> u32;
+
+    // 高機能タイマは BDTR.MOE を立てないと出力されない
+    fn enable_outputs(&self) {}
+}
+
+macro_rules! instance {
+    ($TIM:ident, $id:ident, $bits:expr, $apbenr:ident, $timen:ident, $timclk:ident) => {
+        impl Instance for stm32f446::$TIM {
+            const ID: TimerId = TimerId::$id;
+            const COUNTER_BITS: u32 = $bits;
+
+            fn regs(&self) -> &tim2::RegisterBlock {
+                unsafe { &*(stm32f446::$TIM::ptr() as *const tim2::RegisterBlock) }
+            }
+
+            fn enable_clock(rcc: &stm32f446::RCC) {
+                rcc.$apbenr.modify(|_, w| w.$timen().enabled());
+            }
+
+            fn clock(clocks: &Clocks) -> u32 {
+                clocks.$timclk()
+            }
+        }
+    };
+    ($TIM:ident, $id:ident, $bits:expr, $apbenr:ident, $timen:ident, $timclk:ident, advanced) => {
+        impl Instance for stm32f446::$TIM {
+            const ID: TimerId = TimerId::$id;
+            const COUNTER_BITS: u32 = $bits;
+
+            fn regs(&self) -> &tim2::RegisterBlock {
+                unsafe { &*(stm32f446::$TIM::ptr() as *const tim2::RegisterBlock) }
+            }
+
+            fn enable_clock(rcc: &stm32f446::RCC) {
+                rcc.$apbenr.modify(|_, w| w.$timen().enabled());
+            }
+
+            fn clock(clocks: &Clocks) -> u32 {
+                clocks.$timclk()
+            }
+
+            fn enable_outputs(&self) {
+                let tim = unsafe { &*stm32f446::$TIM::ptr() };
+                tim.bdtr.modify(|_, w| w.moe().set_bit()); // メイン出力有効化
+            }
+        }
+    };
+}
+
+instance!(TIM1, Tim1, 16, apb2enr, tim1en, timclk2, advanced);
+instance!(TIM2, Tim2, 32, apb1enr, tim2en, timclk1);
+instance!(TIM3, Tim3, 16, apb1enr, tim3en, timclk1);
+instance!(TIM4, Tim4, 16, apb1enr, tim4en, timclk1);
+instance!(TIM5, Tim5, 32, apb1enr, tim5en, timclk1);
+instance!(TIM8, Tim8, 16, apb2enr, tim8en, timclk2, advanced);
+
+// span_us[us] 分のカウントがカウンタに収まるプリスケーラを求める
+// 戻り値は (PSC設定値, 1カウントあたりの周波数[Hz])
+pub fn prescaler_for(timclk: u32, span_us: u32, counter_bits: u32) -> Result<(u16, u32), Error> {
+    let max_count = (1u64 << counter_bits) - 1;
+    let total = timclk as u64 * span_us as u64 / 1_000_000;
+    let div = total / max_count + 1;
+    if div > 0x1_0000 {
+        return Err(Error::OutOfRange);
+    }
+    Ok(((div - 1) as u16, timclk / div as u32))
+}
+
+// timclk から rate[Hz] の更新周期を作る PSC, ARR を求める
+// 戻り値は (PSC, ARR, 実際の周波数[Hz])
+pub fn timer_period(timclk: u32, rate: u32, counter_bits: u32) -> Option<(u16, u32, u32)> {
+    if rate == 0 || rate > timclk / 2 {
+        return None;
+    }
+    let total = (timclk as u64 + rate as u64 / 2) / rate as u64; // 1周期のクロック数（四捨五入）
+    let max_count = 1u64 << counter_bits;
+    let div = total.div_ceil(max_count);
+    if div > 0x1_0000 {
+        return None;
+    }
+    let arr = (total / div).max(2) - 1;
+    let period = div * (arr + 1);
+    let actual = ((timclk as u64 + period / 2) / period) as u32;
+    Some(((div - 1) as u16, arr as u32, actual))
+}
+
+// 時間[us]をカウント数に変換
+pub fn us_to_ticks(tick_hz: u32, us: u32) -> u32 {
+    (tick_hz as u64 * us as u64 / 1_000_000) as u32
+}
+
+// 出力比較モード(OCxM)を設定し、チャンネルを出力として使う
+pub(crate) fn set_output_mode(tim: &tim2::RegisterBlock, channel: Channel, ocm: u32) {
+    // CCxS = 00(出力), OCxM をセット
+    let (shift, ccmr1) = match channel {
+        Channel::C1 => (0, true),
+        Channel::C2 => (8, true),
+        Channel::C3 => (0, false),
+        Channel::C4 => (8, false),
+    };
+    let mask = (0b111 << (shift + 4)) | (0b11 << shift);
+    let value = ocm << (shift + 4);
+    if ccmr1 {
+        tim.ccmr1_output()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | value) });
+    } else {
+        tim.ccmr2_output()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | value) });
+    }
+}
+
+// チャンネルを入力(TIx)として使う
+pub(crate) fn set_input(tim: &tim2::RegisterBlock, channel: Channel, polarity: Polarity) {
+    // CCxS = 01(ICx は TIx にマップ)
+    let (shift, ccmr1) = match channel {
+        Channel::C1 => (0, true),
+        Channel::C2 => (8, true),
+        Channel::C3 => (0, false),
+        Channel::C4 => (8, false),
+    };
+    let mask = 0xFF << shift;
+    let value = 0b01 << shift;
+    if ccmr1 {
+        tim.ccmr1_input()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | value) });
+    } else {
+        tim.ccmr2_input()
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | value) });
+    }
+    set_polarity(tim, channel, polarity);
+}
+
+// CCxP, CCxNP で極性を設定（出力なら Falling で反転出力）
+pub(crate) fn set_polarity(tim: &tim2::RegisterBlock, channel: Channel, polarity: Polarity) {
+    let shift = channel as u32 * 4;
+    let mask = 0b1010 << shift;
+    let value = match polarity {
+        Polarity::Rising => 0,
+        Polarity::Falling => 0b0010 << shift,
+    };
+    tim.ccer
+        .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | value) });
+}
+
+// CCxE でチャンネル有効/無効
+pub(crate) fn enable_channel(tim: &tim2::RegisterBlock, channel: Channel, enable: bool) {
+    let bit = 1 << (channel as u32 * 4);
+    tim.ccer.modify(|r, w| unsafe {
+        if enable {
+            w.bits(r.bits() | bit)
+        } else {
+            w.bits(r.bits() & !bit)
+        }
+    });
+}
+
+pub(crate) fn set_compare(tim: &tim2::RegisterBlock, channel: Channel, value: u32) {
+    match channel {
+        Channel::C1 => tim.ccr1.write(|w| unsafe { w.bits(value) }),
+        Channel::C2 => tim.ccr2.write(|w| unsafe { w.bits(value) }),
+        Channel::C3 => tim.ccr3.write(|w| unsafe { w.bits(value) }),
+        Channel::C4 => tim.ccr4.write(|w| unsafe { w.bits(value) }),
+    }
+}
@@ -0,0 +1,109 @@
+// タイマのマスタ/スレーブ接続
+// マスタの TRGO をスレーブの ITR(内部トリガ)に入れて、
+// 同期スタートやカスケード接続（TIM2 の更新で TIM3 をカウントして48bitカウンタにする等）を行う。
+
+use super::{Error, Instance, TimerId};
+
+// マスタが TRGO に出す信号 (CR2.MMS)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MasterMode {
+    // EGR.UG（またはスレーブのリセット）
+    Reset = 0b000,
+    // CR1.CEN（カウント開始を伝えて同期スタートする時に使う）
+    Enable = 0b001,
+    // 更新イベント（カスケード接続でプリスケーラ代わりに使う）
+    Update = 0b010,
+    // CC1IF がセットされる時のパルス
+    ComparePulse = 0b011,
+    Compare1 = 0b100,
+    Compare2 = 0b101,
+    Compare3 = 0b110,
+    Compare4 = 0b111,
+}
+
+// スレーブとしての動作 (SMCR.SMS)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlaveMode {
+    // トリガの立ち上がりでカウンタを0に戻す
+    Reset = 0b100,
+    // トリガがHの間だけカウントする
+    Gated = 0b101,
+    // トリガの立ち上がりでカウント開始（停止はしない）
+    Trigger = 0b110,
+    // トリガの立ち上がりをクロックとしてカウントする（外部クロックモード1）
+    ExternalClock = 0b111,
+}
+
+// slave の ITR0 ~ ITR3 にどのタイマの TRGO が接続されているか（RM0390 TIMx internal trigger connection）
+// TIM2 の ITR1 は OR.ITR1_RMP が初期値(TIM8_TRGOUT)の場合。
+const fn itr_table(slave: TimerId) -> [TimerId; 4] {
+    match slave {
+        TimerId::Tim1 => [TimerId::Tim5, TimerId::Tim2, TimerId::Tim3, TimerId::Tim4],
+        TimerId::Tim8 => [TimerId::Tim1, TimerId::Tim2, TimerId::Tim4, TimerId::Tim5],
+        TimerId::Tim2 => [TimerId::Tim1, TimerId::Tim8, TimerId::Tim3, TimerId::Tim4],
+        TimerId::Tim3 => [TimerId::Tim1, TimerId::Tim2, TimerId::Tim5, TimerId::Tim4],
+        TimerId::Tim4 => [TimerId::Tim1, TimerId::Tim2, TimerId::Tim3, TimerId::Tim8],
+        TimerId::Tim5 => [TimerId::Tim2, TimerId::Tim3, TimerId::Tim4, TimerId::Tim8],
+    }
+}
+
+// master の TRGO が slave のどの ITR に入るかを返す（接続が無ければ None）
+pub const fn internal_trigger(slave: TimerId, master: TimerId) -> Option<u8> {
+    let table = itr_table(slave);
+    let mut i = 0;
+    while i < table.len() {
+        if table[i] as u8 == master as u8 {
+            return Some(i as u8);
+        }
+        i += 1;
+    }
+    None
+}
+
+// TRGO に出す信号を設定
+// sync を true にすると MSM が立ち、スレーブと同時に動き出すようにマスタ側のトリガを1クロック遅らせる。
+pub fn set_master_mode<M: Instance>(master: &M, mode: MasterMode, sync: bool) {
+    let regs = master.regs();
+    regs.cr2
+        .modify(|r, w| unsafe { w.bits((r.bits() & !(0b111 << 4)) | ((mode as u32) << 4)) });
+    regs.smcr.modify(|_, w| w.msm().bit(sync));
+}
+
+// master の TRGO をトリガ入力として slave のスレーブモードを設定
+pub fn set_slave_mode<S: Instance, M: Instance>(
+    slave: &S,
+    _master: &M,
+    mode: SlaveMode,
+) -> Result<(), Error> {
+    let itr = internal_trigger(S::ID, M::ID).ok_or(Error::NoInternalTrigger)? as u32;
+    slave.regs().smcr.modify(|r, w| unsafe {
+        // TS を変える時は SMS = 000 にしておく必要がある
+        w.bits(r.bits() & !0x77)
+    });
+    slave
+        .regs()
+        .smcr
+        .modify(|r, w| unsafe { w.bits((r.bits() & !0x77) | (itr << 4) | mode as u32) });
+    Ok(())
+}
+
+// スレーブモードを解除
+pub fn clear_slave_mode<S: Instance>(slave: &S) {
+    slave
+        .regs()
+        .smcr
+        .modify(|r, w| unsafe { w.bits(r.bits() & !0x07) });
+}
+
+// 下位タイマの更新で上位タイマをカウントするカスケード接続の値を読む
+// （例：TIM2(32bit) -> TIM3(16bit) で48bit）
+// 上位 -> 下位 -> 上位 の順に読み、途中で桁上がりしていたら読み直す。
+pub fn read_cascade<L: Instance, H: Instance>(low: &L, high: &H) -> u64 {
+    loop {
+        let hi = high.regs().cnt.read().bits() as u64;
+        let lo = low.regs().cnt.read().bits() as u64;
+        if high.regs().cnt.read().bits() as u64 == hi {
+            return (hi << L::COUNTER_BITS) | lo;
+        }
+    }
+}
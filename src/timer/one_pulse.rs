@@ -0,0 +1,143 @@
+// ワンパルスモード(OPM)
+// トリガから delay[us] 後に width[us] 幅のパルスを1回だけ出力する。
+// PWM mode 2 で CNT >= CCR の間だけアクティブになるので、
+// CCR = delay, ARR = delay + width - 1 とすればよい。（ARR で更新イベントが発生しカウンタ停止）
+
+use super::{Channel, Error, Instance, Polarity};
+use crate::clock::Clocks;
+
+// パルス開始のトリガ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    // fire() で開始（EXTI 割り込みなどから呼ぶ。割り込み遅延分だけ遅れる）
+    Software,
+    // TI1FP1(CH1 入力)のエッジで開始
+    Ti1(Polarity),
+    // TI2FP2(CH2 入力)のエッジで開始
+    Ti2(Polarity),
+    // ETR 入力のエッジで開始
+    Etr(Polarity),
+}
+
+pub struct OnePulse<TIM> {
+    tim: TIM,
+    channel: Channel,
+    trigger: Trigger,
+    timclk: u32,
+}
+
+impl<TIM: Instance> OnePulse<TIM> {
+    // タイマのクロック供給と出力ピンの設定は呼び出し側で済ませておくこと
+    pub fn new(
+        tim: TIM,
+        clocks: &Clocks,
+        channel: Channel,
+        trigger: Trigger,
+        delay_us: u32,
+        width_us: u32,
+    ) -> Result<Self, Error> {
+        match (trigger, channel) {
+            (Trigger::Ti1(_), Channel::C1) | (Trigger::Ti2(_), Channel::C2) => {
+                return Err(Error::ChannelConflict)
+            }
+            _ => {}
+        }
+
+        let mut one_pulse = OnePulse {
+            tim,
+            channel,
+            trigger,
+            timclk: TIM::clock(clocks),
+        };
+
+        let regs = one_pulse.tim.regs();
+        regs.cr1.modify(|_, w| w.cen().clear_bit().opm().set_bit()); // 停止 & ワンパルスモード
+        super::set_output_mode(regs, channel, 0b111); // PWM mode 2
+
+        match trigger {
+            Trigger::Software => {}
+            Trigger::Ti1(polarity) => super::set_input(regs, Channel::C1, polarity),
+            Trigger::Ti2(polarity) => super::set_input(regs, Channel::C2, polarity),
+            Trigger::Etr(polarity) => regs
+                .smcr
+                .modify(|_, w| w.etp().bit(polarity == Polarity::Falling)),
+        }
+
+        one_pulse.set_timing(delay_us, width_us)?;
+
+        let regs = one_pulse.tim.regs();
+        super::enable_channel(regs, channel, true);
+        one_pulse.tim.enable_outputs();
+
+        one_pulse.arm();
+        Ok(one_pulse)
+    }
+
+    // 遅延とパルス幅を変更（出力中に呼ぶと次のパルスから反映）
+    pub fn set_timing(&mut self, delay_us: u32, width_us: u32) -> Result<(), Error> {
+        let span_us = delay_us.checked_add(width_us).ok_or(Error::OutOfRange)?;
+        let (psc, tick_hz) = super::prescaler_for(self.timclk, span_us, TIM::COUNTER_BITS)?;
+
+        // CCR = 0 だとトリガ直後にアクティブになるので最低1カウント遅らせる
+        let delay = super::us_to_ticks(tick_hz, delay_us).max(1);
+        let width = super::us_to_ticks(tick_hz, width_us).max(1);
+        let arr = delay as u64 + width as u64 - 1;
+        if arr >= 1u64 << TIM::COUNTER_BITS {
+            return Err(Error::OutOfRange);
+        }
+
+        let regs = self.tim.regs();
+        regs.psc.write(|w| unsafe { w.bits(psc as u32) });
+        regs.arr.write(|w| unsafe { w.bits(arr as u32) });
+        super::set_compare(regs, self.channel, delay);
+        if regs.cr1.read().cen().bit_is_clear() {
+            // 停止中なら更新生成でプリスケーラを即反映
+            regs.egr.write(|w| w.ug().set_bit());
+            regs.sr.write(|w| unsafe { w.bits(0) });
+        }
+        Ok(())
+    }
+
+    // ハードウェアトリガを受け付ける（トリガモード）
+    pub fn arm(&mut self) {
+        let ts = match self.trigger {
+            Trigger::Software => return,
+            Trigger::Ti1(_) => 0b101,
+            Trigger::Ti2(_) => 0b110,
+            Trigger::Etr(_) => 0b111,
+        };
+        self.tim.regs().smcr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !0x77) | (ts << 4) | 0b110) // SMS: トリガモード
+        });
+    }
+
+    // ハードウェアトリガを無視する（出力中のパルスはそのまま最後まで出る）
+    pub fn disarm(&mut self) {
+        self.tim
+            .regs()
+            .smcr
+            .modify(|r, w| unsafe { w.bits(r.bits() & !0b111) }); // SMS: スレーブモード無効
+    }
+
+    // 今からパルスを開始する
+    // 出力中に呼んだ場合はカウンタを0に戻して遅延からやり直す（リトリガ）
+    pub fn fire(&mut self) {
+        let regs = self.tim.regs();
+        regs.cr1.modify(|_, w| w.cen().clear_bit());
+        regs.egr.write(|w| w.ug().set_bit()); // カウンタを0に戻す
+        regs.sr.write(|w| unsafe { w.bits(0) });
+        regs.cr1.modify(|_, w| w.cen().set_bit());
+    }
+
+    // パルス出力中（遅延中も含む）ならtrue
+    pub fn is_busy(&self) -> bool {
+        self.tim.regs().cr1.read().cen().bit_is_set()
+    }
+
+    pub fn release(self) -> TIM {
+        let regs = self.tim.regs();
+        regs.cr1.modify(|_, w| w.cen().clear_bit());
+        super::enable_channel(regs, self.channel, false);
+        self.tim
+    }
+}
@@ -0,0 +1,120 @@
+// ELF ファイルからセクションを取り出す（32/64bit のリトルエンディアンのみ）
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    NotElf,
+    Unsupported,
+    Truncated,
+    SectionNotFound(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotElf => write!(f, "not an ELF file"),
+            Error::Unsupported => write!(f, "only little endian ELF is supported"),
+            Error::Truncated => write!(f, "ELF file is truncated"),
+            Error::SectionNotFound(name) => write!(f, "section {} not found", name),
+        }
+    }
+}
+
+pub struct Section<'a> {
+    pub address: u64,
+    pub data: &'a [u8],
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is64: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: u64, len: u64) -> Result<&'a [u8], Error> {
+        let start = usize::try_from(offset).map_err(|_| Error::Truncated)?;
+        let len = usize::try_from(len).map_err(|_| Error::Truncated)?;
+        let end = start.checked_add(len).ok_or(Error::Truncated)?;
+        self.data.get(start..end).ok_or(Error::Truncated)
+    }
+
+    fn u16(&self, offset: u64) -> Result<u64, Error> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as u64)
+    }
+
+    fn u32(&self, offset: u64) -> Result<u64, Error> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes(b.try_into().unwrap()) as u64)
+    }
+
+    // 32bit では 4 バイト、64bit では 8 バイトの値（アドレスやオフセット）
+    fn word(&self, offset: u64) -> Result<u64, Error> {
+        if self.is64 {
+            let b = self.bytes(offset, 8)?;
+            Ok(u64::from_le_bytes(b.try_into().unwrap()))
+        } else {
+            self.u32(offset)
+        }
+    }
+}
+
+// name のセクションのアドレスと中身
+pub fn section<'a>(data: &'a [u8], name: &str) -> Result<Section<'a>, Error> {
+    if data.len() < 16 || &data[..4] != b"\x7fELF" {
+        return Err(Error::NotElf);
+    }
+    let is64 = match data[4] {
+        1 => false,
+        2 => true,
+        _ => return Err(Error::NotElf),
+    };
+    if data[5] != 1 {
+        return Err(Error::Unsupported);
+    }
+    let r = Reader { data, is64 };
+
+    // ELF ヘッダ: セクションヘッダの位置、大きさ、数、名前の文字列テーブルの番号
+    let (shoff, shentsize, shnum, shstrndx) = if is64 {
+        (r.word(0x28)?, r.u16(0x3A)?, r.u16(0x3C)?, r.u16(0x3E)?)
+    } else {
+        (r.word(0x20)?, r.u16(0x2E)?, r.u16(0x30)?, r.u16(0x32)?)
+    };
+    // セクションヘッダ: (名前, アドレス, オフセット, 大きさ)
+    let header = |index: u64| -> Result<(u64, u64, u64, u64), Error> {
+        let base = shoff + index * shentsize;
+        if is64 {
+            Ok((
+                r.u32(base)?,
+                r.word(base + 0x10)?,
+                r.word(base + 0x18)?,
+                r.word(base + 0x20)?,
+            ))
+        } else {
+            Ok((
+                r.u32(base)?,
+                r.word(base + 0x0C)?,
+                r.word(base + 0x10)?,
+                r.word(base + 0x14)?,
+            ))
+        }
+    };
+
+    let (_, _, strtab_offset, strtab_size) = header(shstrndx)?;
+    let strtab = r.bytes(strtab_offset, strtab_size)?;
+    for index in 0..shnum {
+        let (name_offset, address, offset, size) = header(index)?;
+        let section_name = strtab
+            .get(name_offset as usize..)
+            .and_then(|s| s.split(|&b| b == 0).next())
+            .unwrap_or_default();
+        if section_name == name.as_bytes() {
+            return Ok(Section {
+                address,
+                data: r.bytes(offset, size)?,
+            });
+        }
+    }
+    Err(Error::SectionNotFound(name.to_string()))
+}
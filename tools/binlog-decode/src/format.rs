@@ -0,0 +1,313 @@
+// ターゲットから届いた引数で書式文字列を整形する（Rust の format! の位置指定の書式だけ）
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+// {:>08.3x} などの書式指定
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+    kind: String,
+}
+
+fn parse_spec(spec: &str) -> Spec {
+    let mut result = Spec::default();
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    if chars.len() >= 2 && matches!(chars[1], '<' | '^' | '>') {
+        result.fill = Some(chars[0]);
+        result.align = Some(chars[1]);
+        i = 2;
+    } else if !chars.is_empty() && matches!(chars[0], '<' | '^' | '>') {
+        result.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'+') {
+        result.plus = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'#') {
+        result.alternate = true;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        result.zero = true;
+        i += 1;
+    }
+    while let Some(digit) = chars.get(i).and_then(|c| c.to_digit(10)) {
+        result.width = result.width * 10 + digit as usize;
+        i += 1;
+    }
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let mut precision = 0;
+        while let Some(digit) = chars.get(i).and_then(|c| c.to_digit(10)) {
+            precision = precision * 10 + digit as usize;
+            i += 1;
+        }
+        result.precision = Some(precision);
+    }
+    result.kind = chars[i..].iter().collect();
+    result
+}
+
+// 符号と 0x などの接頭辞、本体に分けて整形する
+fn format_value(value: &Value, spec: &Spec) -> (String, String, String) {
+    let debug = spec.kind.ends_with('?');
+    let radix = |n: u64| -> (String, String) {
+        match spec.kind.as_str() {
+            "x" | "x?" => ("0x".into(), format!("{:x}", n)),
+            "X" | "X?" => ("0x".into(), format!("{:X}", n)),
+            "b" => ("0b".into(), format!("{:b}", n)),
+            "o" => ("0o".into(), format!("{:o}", n)),
+            _ => (String::new(), n.to_string()),
+        }
+    };
+    let float = |v: f64, text: String| -> (String, String, String) {
+        let sign = if v.is_sign_negative() {
+            "-"
+        } else if spec.plus {
+            "+"
+        } else {
+            ""
+        };
+        (
+            sign.into(),
+            String::new(),
+            text.trim_start_matches('-').to_string(),
+        )
+    };
+    match value {
+        Value::Unsigned(n) => {
+            let (prefix, body) = radix(*n);
+            let sign = if spec.plus { "+" } else { "" };
+            let prefix = if spec.alternate {
+                prefix
+            } else {
+                String::new()
+            };
+            (sign.into(), prefix, body)
+        }
+        Value::Signed(n) => {
+            if spec.kind.starts_with(['x', 'X', 'b', 'o']) {
+                // 負の数は 2 の補数（64bit）で表示する
+                let (prefix, body) = radix(*n as u64);
+                let prefix = if spec.alternate {
+                    prefix
+                } else {
+                    String::new()
+                };
+                (String::new(), prefix, body)
+            } else {
+                let sign = if *n < 0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else {
+                    ""
+                };
+                (sign.into(), String::new(), n.unsigned_abs().to_string())
+            }
+        }
+        Value::F32(v) => float(
+            *v as f64,
+            match (spec.precision, spec.kind.as_str()) {
+                (_, "e") => format!("{:e}", v),
+                (Some(p), _) => format!("{:.*}", p, v),
+                (None, _) if debug => format!("{:?}", v),
+                (None, _) => v.to_string(),
+            },
+        ),
+        Value::F64(v) => float(
+            *v,
+            match (spec.precision, spec.kind.as_str()) {
+                (_, "e") => format!("{:e}", v),
+                (Some(p), _) => format!("{:.*}", p, v),
+                (None, _) if debug => format!("{:?}", v),
+                (None, _) => v.to_string(),
+            },
+        ),
+        Value::Bool(b) => (String::new(), String::new(), b.to_string()),
+        Value::Char(c) if debug => (String::new(), String::new(), format!("{:?}", c)),
+        Value::Char(c) => (String::new(), String::new(), c.to_string()),
+        Value::Str(s) if debug => (String::new(), String::new(), format!("{:?}", s)),
+        Value::Str(s) => {
+            let body = match spec.precision {
+                Some(p) => s.chars().take(p).collect(),
+                None => s.clone(),
+            };
+            (String::new(), String::new(), body)
+        }
+        Value::Bytes(b) => {
+            let body = match spec.kind.as_str() {
+                "x?" => format!("{:x?}", b),
+                "X?" => format!("{:X?}", b),
+                _ => format!("{:?}", b),
+            };
+            (String::new(), String::new(), body)
+        }
+    }
+}
+
+fn is_numeric(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Unsigned(_) | Value::Signed(_) | Value::F32(_) | Value::F64(_)
+    )
+}
+
+fn pad(value: &Value, spec: &Spec) -> String {
+    let (sign, prefix, body) = format_value(value, spec);
+    let len = sign.chars().count() + prefix.chars().count() + body.chars().count();
+    if len >= spec.width {
+        return sign + &prefix + &body;
+    }
+    let padding = spec.width - len;
+    // 0 埋めは符号と接頭辞の後ろに入れる
+    if spec.zero && is_numeric(value) {
+        return sign + &prefix + &"0".repeat(padding) + &body;
+    }
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    let default_align = if is_numeric(value) { '>' } else { '<' };
+    let text = sign + &prefix + &body;
+    match spec.align.unwrap_or(default_align) {
+        '<' => text + &fill.repeat(padding),
+        '^' => fill.repeat(padding / 2) + &text + &fill.repeat(padding - padding / 2),
+        _ => fill.repeat(padding) + &text,
+    }
+}
+
+// 書式文字列 format を args で整形する（引数が足りない所は {?} になる）
+pub fn format(format: &str, args: &[Value]) -> String {
+    let mut output = String::new();
+    let mut next = 0;
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    field.push(c);
+                }
+                let (position, spec) = field.split_once(':').unwrap_or((&field, ""));
+                let index = if position.is_empty() {
+                    next += 1;
+                    Some(next - 1)
+                } else {
+                    // {name} の引数は送られてこない（ターゲットの defer_*! でコンパイルエラーにしている）
+                    position.parse::<usize>().ok()
+                };
+                match index.and_then(|i| args.get(i)) {
+                    Some(value) => output.push_str(&pad(value, &parse_spec(spec))),
+                    None => {
+                        output.push_str("{?");
+                        output.push_str(&field);
+                        output.push('}');
+                    }
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ホストの format! と同じ結果になること
+    macro_rules! assert_format {
+        ($fmt:literal, $value:expr, $arg:expr) => {
+            assert_eq!(
+                format($fmt, &[$value]),
+                std::format!($fmt, $arg),
+                "{}",
+                $fmt
+            );
+        };
+    }
+
+    #[test]
+    fn numbers_match_std() {
+        assert_format!("{:>08.3}", Value::F32(1.23456), 1.23456f32);
+        assert_format!("{:>08.3}", Value::F64(-1.23456), -1.23456f64);
+        assert_format!("{:+.1}", Value::F64(2.25), 2.25f64);
+        assert_format!("{:?}", Value::F32(1.0), 1.0f32);
+        assert_format!("{:e}", Value::F64(1234.5), 1234.5f64);
+        assert_format!("{:#x}", Value::Unsigned(255), 255u64);
+        assert_format!("{:#010x}", Value::Unsigned(255), 255u64);
+        assert_format!("{:X}", Value::Unsigned(0xBEEF), 0xBEEFu64);
+        assert_format!("{:#b}", Value::Unsigned(5), 5u64);
+        assert_format!("{:o}", Value::Unsigned(8), 8u64);
+        assert_format!("{:x}", Value::Signed(-1), -1i64);
+        assert_format!("{:05}", Value::Signed(-42), -42i64);
+        assert_format!("{:+}", Value::Signed(42), 42i64);
+        assert_format!("{:<6}|", Value::Unsigned(7), 7u64);
+        assert_format!("{:*^7}", Value::Signed(-3), -3i64);
+    }
+
+    #[test]
+    fn text_matches_std() {
+        assert_format!("{:>6}", Value::Str("ab".into()), "ab");
+        assert_format!("{:^7}", Value::Str("ab".into()), "ab");
+        assert_format!("{:.2}", Value::Str("abc".into()), "abc");
+        assert_format!("{:?}", Value::Str("a\"b".into()), "a\"b");
+        assert_format!("{:?}", Value::Char('\n'), '\n');
+        assert_format!("{:3}|", Value::Char('x'), 'x');
+        assert_format!("{}", Value::Bool(true), true);
+        assert_format!("{:x?}", Value::Bytes(vec![0, 0xAB]), [0u8, 0xAB]);
+        assert_format!("{:?}", Value::Bytes(vec![1, 2]), [1u8, 2]);
+    }
+
+    #[test]
+    fn pad_puts_zeros_after_sign_and_prefix() {
+        let spec = parse_spec("#08x");
+        assert_eq!(pad(&Value::Unsigned(0xAB), &spec), "0x0000ab");
+        let spec = parse_spec("+08.2");
+        assert_eq!(pad(&Value::F64(-1.5), &spec), "-0001.50");
+        // 0 埋めは数値だけ
+        let spec = parse_spec("05");
+        assert_eq!(pad(&Value::Str("a".into()), &spec), "a    ");
+        // 幅より長いものはそのまま
+        let spec = parse_spec("2");
+        assert_eq!(pad(&Value::Unsigned(12345), &spec), "12345");
+    }
+
+    #[test]
+    fn positions_and_missing_arguments() {
+        let args = [Value::Unsigned(1), Value::Str("b".into())];
+        assert_eq!(format("{} {}", &args), "1 b");
+        assert_eq!(format("{1} {0} {1}", &args), "b 1 b");
+        assert_eq!(format("{{{}}}", &args), "{1}");
+        // 足りない引数と、送られてこない名前の引数
+        assert_eq!(format("{} {} {}", &args), "1 b {?}");
+        assert_eq!(format("{2:x} {name}", &args), "{?2:x} {?name}");
+    }
+}
@@ -0,0 +1,446 @@
+// log::deferred のバイナリログをホストで読める形にする
+// ターゲットの ELF の .binlog セクションから書式文字列を読み、受信したフレームの引数で整形して表示する。
+//
+//   binlog-decode <ELF> [入力ファイル]（入力を省略すると標準入力）
+//     -l, --location  ソースの場所（ファイル:行）も表示する
+//
+// ELF はターゲットに書き込んだものと同じビルドのものを使うこと（書式の番号はビルドごとに変わる）。
+// ワークスペースのメンバだが、.cargo/config.toml でターゲットが thumbv7em になっているので、
+// ホストのターゲットを指定してビルドする。
+//
+//   stty -F /dev/ttyACM0 115200 raw
+//   cargo run -p binlog-decode --target x86_64-unknown-linux-gnu -- \
+//       target/thumbv7em-none-eabihf/debug/examples/deferred_log /dev/ttyACM0
+
+mod elf;
+mod format;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+
+use format::Value;
+
+// ターゲットの log::deferred と合わせる
+const SECTION: &str = ".binlog";
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_F32: u8 = 2;
+const TAG_F64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+const TAG_BYTES: u8 = 7;
+const TAG_TRUNCATED: u8 = 0xFF;
+
+// 書式文字列のエントリ
+struct Entry {
+    level: &'static str,
+    module: String,
+    file: String,
+    line: String,
+    format: String,
+}
+
+// 1フレームの中身
+#[derive(Debug, PartialEq)]
+struct Record {
+    index: u64,
+    timestamp_us: u64,
+    args: Vec<Value>,
+    truncated: bool,
+}
+
+fn level_name(digit: &str) -> &'static str {
+    match digit {
+        "1" => "ERROR",
+        "2" => "WARN",
+        "3" => "INFO",
+        "4" => "DEBUG",
+        "5" => "TRACE",
+        _ => "?",
+    }
+}
+
+// .binlog セクションの NUL 区切りのエントリを、アドレスをキーにして読む
+fn entries(section: &elf::Section) -> HashMap<u64, Entry> {
+    let mut entries = HashMap::new();
+    let mut offset = 0;
+    for raw in section.data.split(|&b| b == 0) {
+        let address = section.address + offset as u64;
+        offset += raw.len() + 1;
+        let text = String::from_utf8_lossy(raw);
+        let fields: Vec<&str> = text.splitn(5, '\x1f').collect();
+        if let [level, module, file, line, format] = fields[..] {
+            entries.insert(
+                address,
+                Entry {
+                    level: level_name(level),
+                    module: module.to_string(),
+                    file: file.to_string(),
+                    line: line.to_string(),
+                    format: format.to_string(),
+                },
+            );
+        }
+    }
+    entries
+}
+
+// COBS のデコード（区切りの 0x00 は含まない）
+fn cobs_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        let code = input[i] as usize;
+        if code == 0 || i + code > input.len() {
+            return None;
+        }
+        output.extend_from_slice(&input[i + 1..i + code]);
+        i += code;
+        if code < 0xFF && i < input.len() {
+            output.push(0);
+        }
+    }
+    Some(output)
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+}
+
+impl Cursor<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(first)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+fn decode_frame(data: &[u8]) -> Option<Record> {
+    let mut cursor = Cursor { data };
+    let index = cursor.varint()?;
+    let timestamp_us = cursor.varint()?;
+    let mut args = Vec::new();
+    let mut truncated = false;
+    while let Some(tag) = cursor.byte() {
+        let value = match tag {
+            TAG_UNSIGNED => Value::Unsigned(cursor.varint()?),
+            TAG_SIGNED => {
+                let n = cursor.varint()?;
+                Value::Signed((n >> 1) as i64 ^ -((n & 1) as i64))
+            }
+            TAG_F32 => Value::F32(f32::from_le_bytes(cursor.take(4)?.try_into().ok()?)),
+            TAG_F64 => Value::F64(f64::from_le_bytes(cursor.take(8)?.try_into().ok()?)),
+            TAG_BOOL => Value::Bool(cursor.byte()? != 0),
+            TAG_CHAR => Value::Char(char::from_u32(cursor.varint()? as u32)?),
+            TAG_STR | TAG_BYTES => {
+                let len = cursor.varint()? as usize;
+                let bytes = cursor.take(len)?;
+                if tag == TAG_STR {
+                    Value::Str(String::from_utf8_lossy(bytes).into_owned())
+                } else {
+                    Value::Bytes(bytes.to_vec())
+                }
+            }
+            TAG_TRUNCATED => {
+                truncated = true;
+                break;
+            }
+            _ => return None,
+        };
+        args.push(value);
+    }
+    Some(Record {
+        index,
+        timestamp_us,
+        args,
+        truncated,
+    })
+}
+
+fn print_record(
+    out: &mut impl Write,
+    entries: &HashMap<u64, Entry>,
+    record: &Record,
+    location: bool,
+) -> io::Result<()> {
+    let us = record.timestamp_us;
+    write!(out, "[{:>5}.{:06}] ", us / 1_000_000, us % 1_000_000)?;
+    let Some(entry) = entries.get(&record.index) else {
+        return writeln!(
+            out,
+            "?     <unknown format #{:#x}> {:?}",
+            record.index, record.args
+        );
+    };
+    write!(
+        out,
+        "{:<5} {}: {}",
+        entry.level,
+        entry.module,
+        format::format(&entry.format, &record.args)
+    )?;
+    if record.truncated {
+        write!(out, " ...")?;
+    }
+    if location {
+        write!(out, "  ({}:{})", entry.file, entry.line)?;
+    }
+    writeln!(out)
+}
+
+fn run() -> Result<(), String> {
+    let mut location = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-l" | "--location" => location = true,
+            "-h" | "--help" => {
+                println!("usage: binlog-decode [-l|--location] <ELF> [INPUT]");
+                return Ok(());
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let (elf_path, input_path) = match &paths[..] {
+        [elf] => (elf, None),
+        [elf, input] => (elf, Some(input)),
+        _ => return Err("usage: binlog-decode [-l|--location] <ELF> [INPUT]".into()),
+    };
+
+    let elf_data = std::fs::read(elf_path).map_err(|e| format!("{}: {}", elf_path, e))?;
+    let section = elf::section(&elf_data, SECTION).map_err(|e| format!("{}: {}", elf_path, e))?;
+    let entries = entries(&section);
+
+    let input: Box<dyn BufRead> = match input_path {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).map_err(|e| format!("{}: {}", path, e))?,
+        )),
+        None => Box::new(BufReader::new(io::stdin())),
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for frame in input.split(0) {
+        let frame = frame.map_err(|e| e.to_string())?;
+        if frame.is_empty() {
+            continue;
+        }
+        match cobs_decode(&frame).and_then(|data| decode_frame(&data)) {
+            Some(record) => print_record(&mut out, &entries, &record, location),
+            None => writeln!(out, "<broken frame: {} bytes>", frame.len()),
+        }
+        .and_then(|_| out.flush())
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run() {
+        eprintln!("binlog-decode: {}", message);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ターゲットの log::deferred::Frame と同じエンコード
+    fn varint(mut value: u64, output: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                output.push(byte);
+                return;
+            }
+            output.push(byte | 0x80);
+        }
+    }
+
+    fn zigzag(value: i64) -> u64 {
+        ((value << 1) ^ (value >> 63)) as u64
+    }
+
+    fn cobs_encode(input: &[u8]) -> Vec<u8> {
+        let mut output = vec![0];
+        let mut code_index = 0;
+        let mut code = 1u8;
+        for &byte in input {
+            if byte != 0 {
+                output.push(byte);
+                code += 1;
+            }
+            if byte == 0 || code == 0xFF {
+                output[code_index] = code;
+                code_index = output.len();
+                output.push(0);
+                code = 1;
+            }
+        }
+        output[code_index] = code;
+        output
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        let block = |len: usize| (1..=len).map(|i| (i % 255 + 1) as u8).collect::<Vec<u8>>();
+        let inputs = [
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![1, 2, 0, 3],
+            vec![1, 2, 3, 0],
+            // 254 バイトで1ブロックが終わる前後
+            block(253),
+            block(254),
+            block(255),
+            [block(254), vec![0], block(10)].concat(),
+            block(600),
+        ];
+        for input in inputs {
+            let encoded = cobs_encode(&input);
+            assert!(!encoded.contains(&0), "{:?}", input);
+            assert_eq!(cobs_decode(&encoded), Some(input));
+        }
+        // 254 バイトのブロックは 0xFF で始まり、0 を挟まない
+        let encoded = cobs_encode(&block(254));
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(encoded.len(), 256);
+    }
+
+    #[test]
+    fn cobs_rejects_broken_input() {
+        // 符号が指す先が足りない（途中で切れたフレーム）
+        assert_eq!(cobs_decode(&[5, 1, 2]), None);
+        assert_eq!(cobs_decode(&[0xFF, 1, 2]), None);
+        // 0x00 は区切りなので、フレームの中には現れない
+        assert_eq!(cobs_decode(&[1, 0, 1]), None);
+    }
+
+    #[test]
+    fn varint_round_trip() {
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut data = Vec::new();
+            varint(value, &mut data);
+            let mut cursor = Cursor { data: &data };
+            assert_eq!(cursor.varint(), Some(value));
+            assert!(cursor.data.is_empty());
+        }
+        // 続きのビットが立ったまま終わっている
+        assert_eq!(
+            Cursor {
+                data: &[0x80, 0x80]
+            }
+            .varint(),
+            None
+        );
+        assert_eq!(Cursor { data: &[0xFF; 11] }.varint(), None);
+    }
+
+    #[test]
+    fn decode_frame_with_all_types() {
+        let mut data = Vec::new();
+        varint(0x41, &mut data);
+        varint(1_234_567, &mut data);
+        for value in [0u64, 300] {
+            data.push(TAG_UNSIGNED);
+            varint(value, &mut data);
+        }
+        for value in [-1i64, 1, i64::MIN, i64::MAX] {
+            data.push(TAG_SIGNED);
+            varint(zigzag(value), &mut data);
+        }
+        data.push(TAG_F32);
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        data.push(TAG_F64);
+        data.extend_from_slice(&(-0.25f64).to_le_bytes());
+        data.extend_from_slice(&[TAG_BOOL, 1]);
+        data.push(TAG_CHAR);
+        varint('あ' as u64, &mut data);
+        data.push(TAG_STR);
+        varint(2, &mut data);
+        data.extend_from_slice(b"ok");
+        data.push(TAG_BYTES);
+        varint(3, &mut data);
+        data.extend_from_slice(&[0, 1, 0xFF]);
+        data.push(TAG_TRUNCATED);
+
+        let record = cobs_decode(&cobs_encode(&data)).and_then(|data| decode_frame(&data));
+        assert_eq!(
+            record,
+            Some(Record {
+                index: 0x41,
+                timestamp_us: 1_234_567,
+                args: vec![
+                    Value::Unsigned(0),
+                    Value::Unsigned(300),
+                    Value::Signed(-1),
+                    Value::Signed(1),
+                    Value::Signed(i64::MIN),
+                    Value::Signed(i64::MAX),
+                    Value::F32(1.5),
+                    Value::F64(-0.25),
+                    Value::Bool(true),
+                    Value::Char('あ'),
+                    Value::Str("ok".into()),
+                    Value::Bytes(vec![0, 1, 0xFF]),
+                ],
+                truncated: true,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_frame_rejects_short_data() {
+        // 文字列の長さに対してデータが足りない
+        assert_eq!(decode_frame(&[1, 0, TAG_STR, 5, b'a']), None);
+        assert_eq!(decode_frame(&[1, 0, TAG_F32, 0, 0]), None);
+        // 知らない型タグ
+        assert_eq!(decode_frame(&[1, 0, 0x20]), None);
+        // 時刻が無い
+        assert_eq!(decode_frame(&[1]), None);
+    }
+
+    #[test]
+    fn entries_are_keyed_by_address() {
+        // binlog.x で先頭に 1byte 置いているので、最初のエントリは 1 番
+        let data = b"\x003\x1fapp\x1fsrc/main.rs\x1f10\x1fvalue = {}\x002\x1fapp::adc\x1fsrc/adc.rs\x1f7\x1foverrun\x00";
+        let section = elf::Section { address: 0, data };
+        let entries = entries(&section);
+        assert_eq!(entries.len(), 2);
+        let first = &entries[&1];
+        assert_eq!(first.level, "INFO");
+        assert_eq!(first.module, "app");
+        assert_eq!(first.file, "src/main.rs");
+        assert_eq!(first.line, "10");
+        assert_eq!(first.format, "value = {}");
+        let second = &entries[&(1 + 32)];
+        assert_eq!(second.level, "WARN");
+        assert_eq!(second.format, "overrun");
+    }
+}